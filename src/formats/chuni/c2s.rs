@@ -1,7 +1,22 @@
 //! C2S Chart Format
 //!
 //! note: This format is TSV-based, with each tab-separated value representing a different field.
-use crate::formats::chuni::{AirDirection, ChuniNoteType};
+use std::{fmt, str::FromStr};
+
+use crate::formats::{
+    chuni::{
+        AirDirection, ChuniChart, ChuniNoteType,
+        ir::{
+            self, AirCrushColor, AirHoldPoint, AirSlidePoint, Conversion, ConversionWarning,
+            CrushInterval, DEFAULT_EXTAP_EFFECT, ExTapEffect, IrChart, IrMetadata, IrNote,
+            IrNoteKind, SlidePoint, SlidePointKind, TimeSignatureChange, rescale_tick,
+        },
+        music::{self, MusicXml},
+        scroll::ScrollMap,
+        tempo::TempoMap,
+    },
+    error::{Diagnostics, ParseError, ParseErrorKind, ParseMode, Parsed},
+};
 
 // Special Thanks:
 // - Yukopi, for composing [*Kyoufuu All Back*](https://youtu.be/D6DVTLvOupE)
@@ -11,8 +26,6 @@ use crate::formats::chuni::{AirDirection, ChuniNoteType};
 // This chart effectively served as our "Rosetta Stone" for decoding the
 // CHUNITHM NEW (v1.13.00+) C2S format structure.
 
-// todo: implement end tags
-
 const DEFAULT_RESOLUTION: u32 = 384;
 
 /// Static mapping between note type strings and ChuniNoteType variants
@@ -44,17 +57,28 @@ static NOTE_TYPE_PAIRS: &[(&str, ChuniNoteType)] = &[
     // Note: ASD is handled specially as a wrapper format, not a standalone note type
 ];
 
-/// Convert a note type string to ChuniNoteType
-pub fn string_to_note_type(s: &str) -> Result<ChuniNoteType, String> {
+/// Convert a note type string to ChuniNoteType, unknown types are wrapped in `Unknown`
+pub fn string_to_note_type(s: &str) -> ChuniNoteType {
     NOTE_TYPE_PAIRS
         .iter()
         .find(|(key, _)| *key == s)
         .map(|(_, note_type)| note_type.clone())
-        .or_else(|| {
-            // For unknown types, wrap them in Unknown variant
-            Some(ChuniNoteType::Unknown(s.to_string()))
-        })
-        .ok_or_else(|| format!("Failed to create note type for: {}", s))
+        .unwrap_or_else(|| ChuniNoteType::Unknown(s.to_string()))
+}
+
+/// Parses field `index` of a line split on whitespace
+fn parse_field<T: FromStr>(
+    line: &str,
+    parts: &[&str],
+    index: usize,
+    name: &'static str,
+) -> Result<T, ParseError> {
+    let field = parts
+        .get(index)
+        .ok_or_else(|| ParseError::missing(line, name))?;
+    field
+        .parse()
+        .map_err(|_| ParseError::invalid(line, field, name))
 }
 
 pub fn c2s_note_type_to_string(note_type: &ChuniNoteType) -> String {
@@ -72,11 +96,17 @@ pub fn c2s_note_type_to_string(note_type: &ChuniNoteType) -> String {
     }
 }
 
+#[derive(Debug, Clone, PartialEq)]
 pub struct C2SChart {
     pub metadata: C2SMetadata,
     pub notes: Vec<Note>,
+    /// Trailing `T_XXX` statistics, if the chart has them
+    pub end_tags: Option<C2SEndTags>,
+    /// The song's `Music.xml`, see [`C2SChart::attach_music`]
+    pub music: Option<MusicXml>,
 }
 
+#[derive(Debug, Clone, PartialEq)]
 pub struct C2SMetadata {
     pub version: [String; 2],
     /// Song ID, unused in C2S, is declared in Music.xml instead
@@ -88,6 +118,8 @@ pub struct C2SMetadata {
     pub difficulty: u32,
     /// Level of the chart, unused in C2S, is declared in Music.xml instead
     pub level: u32,
+    /// Decimal chart constant, e.g. `13.7`, from a `LEVEL` with decimals or Music.xml
+    pub constant: Option<f32>,
     /// Creator of the chart. Will be displayed in-game.
     pub creator: String,
     /// Default BPM (Beats Per Minute) of the song.
@@ -120,6 +152,7 @@ pub struct C2SMetadata {
     pub sfl: Vec<Sfl>,
 }
 
+#[derive(Debug, Clone, PartialEq)]
 pub struct Sfl {
     /// Beginning measure where this speed change takes effect
     pub measure: u32,
@@ -130,6 +163,7 @@ pub struct Sfl {
     /// The speed multiplier for the specified measure
     pub multiplier: f32,
 }
+#[derive(Debug, Clone, PartialEq)]
 pub struct Bpm {
     /// Beginning measure where this BPM change takes effect
     pub measure: u32,
@@ -139,6 +173,7 @@ pub struct Bpm {
     pub bpm: f32,
 }
 
+#[derive(Debug, Clone, PartialEq)]
 pub struct TimeSignature {
     /// Beginning measure where this time signature takes effect
     pub measure: u32,
@@ -150,6 +185,134 @@ pub struct TimeSignature {
     pub denominator: u32,
 }
 
+/// Per-type note statistics, as listed by `T_REC_XXX`, `T_NOTE_XXX` and `T_NUM_XXX`
+///
+/// The `T_XXX_ALL` totals are not stored, they are always the sum of these fields.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct NoteTypeCounts {
+    pub tap: u32,
+    pub chr: u32,
+    pub flk: u32,
+    pub mne: u32,
+    pub hld: u32,
+    pub sld: u32,
+    pub air: u32,
+    pub ahd: u32,
+}
+
+impl NoteTypeCounts {
+    /// Tag suffixes in the order the game writes them
+    const SUFFIXES: [&'static str; 8] = ["TAP", "CHR", "FLK", "MNE", "HLD", "SLD", "AIR", "AHD"];
+
+    fn get_mut(&mut self, suffix: &str) -> Option<&mut u32> {
+        match suffix {
+            "TAP" => Some(&mut self.tap),
+            "CHR" => Some(&mut self.chr),
+            "FLK" => Some(&mut self.flk),
+            "MNE" => Some(&mut self.mne),
+            "HLD" => Some(&mut self.hld),
+            "SLD" => Some(&mut self.sld),
+            "AIR" => Some(&mut self.air),
+            "AHD" => Some(&mut self.ahd),
+            _ => None,
+        }
+    }
+
+    fn values(&self) -> [u32; 8] {
+        [
+            self.tap, self.chr, self.flk, self.mne, self.hld, self.sld, self.air, self.ahd,
+        ]
+    }
+
+    /// The `T_XXX_ALL` value
+    pub fn total(&self) -> u32 {
+        self.values().iter().sum()
+    }
+}
+
+/// The `T_XXX` trailer found at the end of C2S files
+///
+/// According to the spec these don't influence gameplay, but official charts always carry them.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct C2SEndTags {
+    /// `T_REC_XXX`, the number of note records of each type
+    pub rec: NoteTypeCounts,
+    /// `T_NOTE_XXX`, the number of notes of each type, counting a chained slide/air hold once
+    pub note: NoteTypeCounts,
+    /// `T_NUM_XXX`, semantics unknown, computed the same way as `note`
+    pub num: NoteTypeCounts,
+    /// `T_NUM_AAC`, presumably the number of AIR-ACTIONs (ALD+NON)
+    pub num_aac: u32,
+    /// `T_CHRTYPE_UP`, the number of CHR notes with the `UP` modifier
+    pub chr_type_up: u32,
+    /// `T_CHRTYPE_DW`, the number of CHR notes with the `DW` modifier
+    pub chr_type_dw: u32,
+    /// `T_CHRTYPE_CE`, the number of CHR notes with the `CE` modifier
+    pub chr_type_ce: u32,
+    /// `T_LEN_HLD`, total milliseconds of hold notes
+    pub len_hld: u32,
+    /// `T_LEN_SLD`, total milliseconds of slide notes
+    pub len_sld: u32,
+    /// `T_LEN_AHD`, total milliseconds of air holds
+    pub len_ahd: u32,
+    /// `T_FIRST_MSEC`, timestamp of the first note in milliseconds
+    pub first_msec: u32,
+    /// `T_FIRST_RES`, timestamp of the first note in ticks
+    pub first_res: u32,
+    /// `T_FINAL_MSEC`, timestamp where the last note ends in milliseconds
+    pub final_msec: u32,
+    /// `T_FINAL_RES`, timestamp where the last note ends in ticks
+    pub final_res: u32,
+    /// `T_PROG_00` to `T_PROG_95`
+    ///
+    /// Meaning unknown, we compute the number of notes starting in each 5% slice of the chart.
+    pub prog: [u32; 20],
+    /// Any other `T_XXX` tag (e.g. `T_JUDGE_XXX`), kept verbatim in file order
+    pub other: Vec<(String, String)>,
+}
+
+impl C2SEndTags {
+    /// The `T_LEN_ALL` value
+    pub fn len_total(&self) -> u32 {
+        self.len_hld + self.len_sld + self.len_ahd
+    }
+
+    /// Parse a single `T_XXX value` line into this struct
+    ///
+    /// `T_XXX_ALL` totals are skipped since they are derived from the other fields.
+    pub fn parse_tag(&mut self, tag: &str, value: &str) {
+        let parsed = value.parse::<u32>().ok();
+        let field = match tag.strip_prefix("T_").and_then(|rest| rest.split_once('_')) {
+            Some(("REC" | "NOTE" | "LEN", "ALL")) if parsed.is_some() => return,
+            Some(("REC", suffix)) => self.rec.get_mut(suffix),
+            Some(("NOTE", suffix)) => self.note.get_mut(suffix),
+            Some(("NUM", "AAC")) => Some(&mut self.num_aac),
+            Some(("NUM", suffix)) => self.num.get_mut(suffix),
+            Some(("CHRTYPE", "UP")) => Some(&mut self.chr_type_up),
+            Some(("CHRTYPE", "DW")) => Some(&mut self.chr_type_dw),
+            Some(("CHRTYPE", "CE")) => Some(&mut self.chr_type_ce),
+            Some(("LEN", "HLD")) => Some(&mut self.len_hld),
+            Some(("LEN", "SLD")) => Some(&mut self.len_sld),
+            Some(("LEN", "AHD")) => Some(&mut self.len_ahd),
+            Some(("FIRST", "MSEC")) => Some(&mut self.first_msec),
+            Some(("FIRST", "RES")) => Some(&mut self.first_res),
+            Some(("FINAL", "MSEC")) => Some(&mut self.final_msec),
+            Some(("FINAL", "RES")) => Some(&mut self.final_res),
+            Some(("PROG", index)) => index
+                .parse::<usize>()
+                .ok()
+                .filter(|index| index % 5 == 0 && index / 5 < self.prog.len())
+                .map(|index| &mut self.prog[index / 5]),
+            _ => None,
+        };
+
+        match (field, parsed) {
+            (Some(field), Some(parsed)) => *field = parsed,
+            _ => self.other.push((tag.to_string(), value.to_string())),
+        }
+    }
+}
+
 /// Information about a note that was wrapped in ASD/ASC format
 /// Both ASD and ASC are wrapper formats that can contain any note type
 #[derive(Debug, Clone, PartialEq)]
@@ -200,14 +363,17 @@ pub struct Note {
     pub flick_modifier: Option<String>,
     /// Information about the wrapped note if this was parsed from ASD/ASC format
     pub wrapped_note_info: Option<WrappedNoteInfo>,
+    /// Trailing fields that aren't interpreted yet (e.g. the `DEF` after an AHX duration),
+    /// kept verbatim so the note can be written back out unchanged
+    pub extra_fields: Vec<String>,
 }
 
 impl Note {
-    pub fn from_line(line: &str) -> Result<Self, String> {
+    pub fn from_line(line: &str) -> Result<Self, ParseError> {
         let parts: Vec<&str> = line.split_whitespace().collect();
 
         if parts.is_empty() {
-            return Err("Empty line".to_string());
+            return Err(ParseError::missing(line, "note type"));
         }
 
         // Handle ASD and ASC notes (both are wrapper formats with 12 fields) by converting them to regular notes
@@ -217,28 +383,12 @@ impl Note {
             return Note::from_asd_line(line);
         }
 
-        let note_type = string_to_note_type(&parts[0].to_uppercase())?;
+        let note_type = string_to_note_type(&parts[0].to_uppercase());
 
-        if parts.len() < 5 {
-            return Err(format!(
-                "Not enough fields: expected at least 5, got {}. Line: '{}'",
-                parts.len(),
-                line
-            ));
-        }
-
-        let measure = parts[1]
-            .parse::<u32>()
-            .map_err(|e| format!("Invalid measure '{}': {}", parts[1], e))?;
-        let offset = parts[2]
-            .parse::<u32>()
-            .map_err(|e| format!("Invalid offset '{}': {}", parts[2], e))?;
-        let cell = parts[3]
-            .parse::<u32>()
-            .map_err(|e| format!("Invalid cell '{}': {}", parts[3], e))?;
-        let width = parts[4]
-            .parse::<u32>()
-            .map_err(|e| format!("Invalid width '{}': {}", parts[4], e))?;
+        let measure = parse_field::<u32>(line, &parts, 1, "measure")?;
+        let offset = parse_field::<u32>(line, &parts, 2, "offset")?;
+        let cell = parse_field::<u32>(line, &parts, 3, "cell")?;
+        let width = parse_field::<u32>(line, &parts, 4, "width")?;
 
         let mut duration = None;
         let mut end_cell = None;
@@ -250,24 +400,15 @@ impl Note {
 
         // Parse additional fields based on note type
         match note_type {
-            ChuniNoteType::Hold | ChuniNoteType::ExHold => {
-                if parts.len() > 5 {
-                    duration = Some(
-                        parts[5]
-                            .parse::<u32>()
-                            .map_err(|e| format!("Invalid hold duration '{}': {}", parts[5], e))?,
-                    );
-                }
+            ChuniNoteType::Hold | ChuniNoteType::ExHold if parts.len() > 5 => {
+                duration = Some(parse_field::<u32>(line, &parts, 5, "hold duration")?);
             }
             ChuniNoteType::AirHold => {
                 if parts.len() > 5 {
                     target_note = Some(parts[5].to_string());
                 }
                 if parts.len() > 6 {
-                    duration =
-                        Some(parts[6].parse::<u32>().map_err(|e| {
-                            format!("Invalid air hold duration '{}': {}", parts[6], e)
-                        })?);
+                    duration = Some(parse_field::<u32>(line, &parts, 6, "air hold duration")?);
                 }
             }
             ChuniNoteType::Slide
@@ -275,53 +416,34 @@ impl Note {
             | ChuniNoteType::SlideControlPoint
             | ChuniNoteType::ExSlideControlPoint => {
                 if parts.len() > 5 {
-                    duration =
-                        Some(parts[5].parse::<u32>().map_err(|e| {
-                            format!("Invalid slide duration '{}': {}", parts[5], e)
-                        })?);
+                    duration = Some(parse_field::<u32>(line, &parts, 5, "slide duration")?);
                 }
                 if parts.len() > 6 {
-                    end_cell =
-                        Some(parts[6].parse::<f32>().map_err(|e| {
-                            format!("Invalid slide end_cell '{}': {}", parts[6], e)
-                        })?);
+                    end_cell = Some(parse_field::<f32>(line, &parts, 6, "slide end cell")?);
                 }
                 if parts.len() > 7 {
-                    end_width =
-                        Some(parts[7].parse::<f32>().map_err(|e| {
-                            format!("Invalid slide end_width '{}': {}", parts[7], e)
-                        })?);
+                    end_width = Some(parse_field::<f32>(line, &parts, 7, "slide end width")?);
                 }
             }
-            ChuniNoteType::ExTap => {
-                if parts.len() > 5 {
-                    chr_modifier = Some(parts[5].to_string());
-                }
+            ChuniNoteType::ExTap if parts.len() > 5 => {
+                chr_modifier = Some(parts[5].to_string());
             }
             ChuniNoteType::Flick => {
                 flick_modifier = Some("L".to_string());
             }
-            ChuniNoteType::Air | ChuniNoteType::AirDirectional(_) => {
-                if parts.len() > 5 {
-                    target_note = Some(parts[5].to_string());
-                }
+            ChuniNoteType::Air | ChuniNoteType::AirDirectional(_) if parts.len() > 5 => {
+                target_note = Some(parts[5].to_string());
             }
             ChuniNoteType::AirSlide | ChuniNoteType::AirSlideControlPoint => {
                 // Assume ALD/ASC behave like regular slides but in air sensor region
                 if parts.len() > 5 {
-                    duration = Some(parts[5].parse::<u32>().map_err(|e| {
-                        format!("Invalid air slide duration '{}': {}", parts[5], e)
-                    })?);
+                    duration = Some(parse_field::<u32>(line, &parts, 5, "air slide duration")?);
                 }
                 if parts.len() > 6 {
-                    end_cell = Some(parts[6].parse::<f32>().map_err(|e| {
-                        format!("Invalid air slide end_cell '{}': {}", parts[6], e)
-                    })?);
+                    end_cell = Some(parse_field::<f32>(line, &parts, 6, "air slide end cell")?);
                 }
                 if parts.len() > 7 {
-                    end_width = Some(parts[7].parse::<f32>().map_err(|e| {
-                        format!("Invalid air slide end_width '{}': {}", parts[7], e)
-                    })?);
+                    end_width = Some(parse_field::<f32>(line, &parts, 7, "air slide end width")?);
                 }
             }
             ChuniNoteType::AirHoldGround => {
@@ -331,19 +453,45 @@ impl Note {
                     target_note = Some(parts[5].to_string());
                 }
                 if parts.len() > 6 {
-                    duration = Some(parts[6].parse::<u32>().map_err(|e| {
-                        format!("Invalid air hold ground duration '{}': {}", parts[6], e)
-                    })?);
+                    duration = Some(parse_field::<u32>(
+                        line,
+                        &parts,
+                        6,
+                        "air hold ground duration",
+                    )?);
                 }
-                // parts[7] appears to be a modifier (usually "DEF")
+                // parts[7] appears to be a modifier (usually "DEF"), kept in extra_fields
             }
             ChuniNoteType::Unknown(_) => {
                 // For unknown types, we don't know the field structure
-                // Just parse basic fields and ignore additional ones
+                // Just parse basic fields and keep additional ones in extra_fields
             }
             _ => {}
         }
 
+        // Number of leading fields consumed by the match above, anything after is kept as-is
+        let interpreted_fields = match note_type {
+            ChuniNoteType::Hold
+            | ChuniNoteType::ExHold
+            | ChuniNoteType::ExTap
+            | ChuniNoteType::Flick
+            | ChuniNoteType::Air
+            | ChuniNoteType::AirDirectional(_) => 6,
+            ChuniNoteType::AirHold | ChuniNoteType::AirHoldGround => 7,
+            ChuniNoteType::Slide
+            | ChuniNoteType::ExSlide
+            | ChuniNoteType::SlideControlPoint
+            | ChuniNoteType::ExSlideControlPoint
+            | ChuniNoteType::AirSlide
+            | ChuniNoteType::AirSlideControlPoint => 8,
+            _ => 5,
+        };
+        let extra_fields = parts
+            .iter()
+            .skip(interpreted_fields)
+            .map(|part| part.to_string())
+            .collect();
+
         // If the note is of type ASD or ASC (both are wrapper formats), parse the additional wrapped note information
         if (parts[0].to_uppercase() == "ASD" || parts[0].to_uppercase() == "ASC")
             && parts.len() == 12
@@ -351,12 +499,8 @@ impl Note {
             wrapped_note_info = Some(WrappedNoteInfo {
                 original_format: parts[0].to_string(),
                 wrapped_type: parts[5].to_string(),
-                param1: parts[6]
-                    .parse::<f32>()
-                    .map_err(|e| format!("Invalid param1 for ASD/ASC note '{}': {}", line, e))?,
-                param2: parts[7]
-                    .parse::<f32>()
-                    .map_err(|e| format!("Invalid param2 for ASD/ASC note '{}': {}", line, e))?,
+                param1: parse_field::<f32>(line, &parts, 6, "param1")?,
+                param2: parse_field::<f32>(line, &parts, 7, "param2")?,
                 param3: parts[11].to_string(),
             });
         }
@@ -374,67 +518,50 @@ impl Note {
             chr_modifier,
             flick_modifier,
             wrapped_note_info,
+            extra_fields,
         })
     }
 
     /// Converts an ASD (Air Special Data) or ASC (Air Slide Control) wrapper line into a regular Note
     /// Both ASD and ASC use the same 12-field wrapper format:
     /// ASD/ASC measure tick cell width type param1 duration end_cell end_width param2 param3
-    pub fn from_asd_line(line: &str) -> Result<Self, String> {
+    pub fn from_asd_line(line: &str) -> Result<Self, ParseError> {
         let parts: Vec<&str> = line.split_whitespace().collect();
 
-        if parts.len() != 12 {
-            return Err(format!(
-                "ASD/ASC line must have exactly 12 fields, got {}. Line: '{}'",
-                parts.len(),
-                line
-            ));
+        if parts.len() < 12 {
+            return Err(ParseError::missing(line, "ASD/ASC field"));
+        }
+        if let Some(extra) = parts.get(12) {
+            return Err(ParseError::invalid(line, extra, "trailing field"));
         }
 
         let note_type_prefix = parts[0].to_uppercase();
-        let measure = parts[1]
-            .parse::<u32>()
-            .map_err(|_| format!("Invalid measure: '{}'", parts[1]))?;
-        let offset = parts[2]
-            .parse::<u32>()
-            .map_err(|_| format!("Invalid offset: '{}'", parts[2]))?;
-        let cell = parts[3]
-            .parse::<u32>()
-            .map_err(|_| format!("Invalid cell: '{}'", parts[3]))?;
-        let width = parts[4]
-            .parse::<u32>()
-            .map_err(|_| format!("Invalid width: '{}'", parts[4]))?;
+        let measure = parse_field::<u32>(line, &parts, 1, "measure")?;
+        let offset = parse_field::<u32>(line, &parts, 2, "offset")?;
+        let cell = parse_field::<u32>(line, &parts, 3, "cell")?;
+        let width = parse_field::<u32>(line, &parts, 4, "width")?;
 
         let wrapped_type = parts[5].to_string();
-        let param1 = parts[6]
-            .parse::<f32>()
-            .map_err(|_| format!("Invalid param1: '{}'", parts[6]))?;
-        let duration = parts[7]
-            .parse::<u32>()
-            .map_err(|_| format!("Invalid duration: '{}'", parts[7]))?;
-        let end_cell = parts[8]
-            .parse::<u32>()
-            .map_err(|_| format!("Invalid end_cell: '{}'", parts[8]))?;
-        let end_width = parts[9]
-            .parse::<u32>()
-            .map_err(|_| format!("Invalid end_width: '{}'", parts[9]))?;
-        let param2 = parts[10]
-            .parse::<f32>()
-            .map_err(|_| format!("Invalid param2: '{}'", parts[10]))?;
+        let param1 = parse_field::<f32>(line, &parts, 6, "param1")?;
+        let duration = parse_field::<u32>(line, &parts, 7, "duration")?;
+        let end_cell = parse_field::<u32>(line, &parts, 8, "end cell")?;
+        let end_width = parse_field::<u32>(line, &parts, 9, "end width")?;
+        let param2 = parse_field::<f32>(line, &parts, 10, "param2")?;
         let param3 = parts[11].to_string();
 
         // Determine the note type based on the prefix and wrapped type
         let note_type = if note_type_prefix == "ASD" {
             // For ASD notes, use the wrapped note type
-            string_to_note_type(&wrapped_type.to_uppercase())?
+            string_to_note_type(&wrapped_type.to_uppercase())
         } else if note_type_prefix == "ASC" {
             // For ASC notes, also use the wrapped note type
             // ASC can wrap any note type, not just AirSlideControlPoint
-            string_to_note_type(&wrapped_type.to_uppercase())?
+            string_to_note_type(&wrapped_type.to_uppercase())
         } else {
-            return Err(format!(
-                "Invalid note type prefix for ASD/ASC format: {}",
-                note_type_prefix
+            return Err(ParseError::at_field(
+                line,
+                parts[0],
+                ParseErrorKind::UnknownNoteType(parts[0].to_string()),
             ));
         };
 
@@ -458,6 +585,7 @@ impl Note {
                 param2,
                 param3,
             }),
+            extra_fields: Vec::new(),
         })
     }
 }
@@ -478,6 +606,7 @@ impl Note {
             chr_modifier: None,
             flick_modifier: None,
             wrapped_note_info: None,
+            extra_fields: Vec::new(),
         }
     }
 
@@ -496,6 +625,7 @@ impl Note {
             chr_modifier: Some(modifier),
             flick_modifier: None,
             wrapped_note_info: None,
+            extra_fields: Vec::new(),
         }
     }
 
@@ -514,6 +644,7 @@ impl Note {
             chr_modifier: None,
             flick_modifier: None,
             wrapped_note_info: None,
+            extra_fields: Vec::new(),
         }
     }
 
@@ -540,6 +671,7 @@ impl Note {
             chr_modifier: None,
             flick_modifier: None,
             wrapped_note_info: None,
+            extra_fields: Vec::new(),
         }
     }
 
@@ -566,6 +698,7 @@ impl Note {
             chr_modifier: None,
             flick_modifier: None,
             wrapped_note_info: None,
+            extra_fields: Vec::new(),
         }
     }
 
@@ -584,6 +717,7 @@ impl Note {
             chr_modifier: None,
             flick_modifier: Some("L".to_string()),
             wrapped_note_info: None,
+            extra_fields: Vec::new(),
         }
     }
 
@@ -602,6 +736,7 @@ impl Note {
             chr_modifier: None,
             flick_modifier: None,
             wrapped_note_info: None,
+            extra_fields: Vec::new(),
         }
     }
 
@@ -627,6 +762,7 @@ impl Note {
             chr_modifier: None,
             flick_modifier: None,
             wrapped_note_info: None,
+            extra_fields: Vec::new(),
         }
     }
 
@@ -652,6 +788,7 @@ impl Note {
             chr_modifier: None,
             flick_modifier: None,
             wrapped_note_info: None,
+            extra_fields: Vec::new(),
         }
     }
 
@@ -670,6 +807,7 @@ impl Note {
             chr_modifier: None,
             flick_modifier: None,
             wrapped_note_info: None,
+            extra_fields: Vec::new(),
         }
     }
 
@@ -680,14 +818,19 @@ impl Note {
 
     /// Returns true if this note is an air action or a crush (ALD+NON)
     pub fn is_air_action(&self) -> bool {
-        matches!(
-            self.note_type,
-            ChuniNoteType::AirSlide | ChuniNoteType::AirSlideControlPoint
-        ) && self
-            .wrapped_note_info
-            .as_ref()
-            .map(|info| info.param3 == "NON")
-            .unwrap_or(false)
+        // ASD/ASC notes take the type of their target, only the wrapper tells them apart
+        let color = match &self.wrapped_note_info {
+            Some(info) => Some(info.param3.as_str()),
+            None if matches!(
+                self.note_type,
+                ChuniNoteType::AirSlide | ChuniNoteType::AirSlideControlPoint
+            ) =>
+            {
+                self.extra_fields.last().map(String::as_str)
+            }
+            None => None,
+        };
+        color == Some("NON")
     }
 
     /// Returns the original format if this was a wrapped note ("ASD" or "ASC")
@@ -707,166 +850,1307 @@ impl Note {
 
 impl C2SChart {
     /// Parse a complete C2S chart from a string containing both metadata and notes
-    pub fn from_string(content: &str) -> Result<Self, String> {
-        let lines: Vec<&str> = content.lines().collect();
-        let mut metadata = C2SMetadata::default();
-        let mut notes = Vec::new();
-        let mut in_notes_section = false;
+    ///
+    /// Fails on the first line that can't be parsed, see [`C2SChart::parse`] to skip them instead.
+    pub fn from_string(content: &str) -> Result<Self, ParseError> {
+        Self::parse(content, ParseMode::Strict).map(|parsed| parsed.value)
+    }
 
-        for line in lines {
-            let line = line.trim();
-            if line.is_empty() {
-                continue;
+    /// Parse a complete C2S chart, skipping bad lines in lenient mode
+    pub fn parse(content: &str, mode: ParseMode) -> Result<Parsed<Self>, ParseError> {
+        let mut chart = C2SChart {
+            metadata: C2SMetadata::default(),
+            notes: Vec::new(),
+            end_tags: None,
+            music: None,
+        };
+        let mut diagnostics = Diagnostics::new(mode);
+
+        for (index, line) in content.lines().enumerate() {
+            if let Err(error) = chart.parse_line(line) {
+                diagnostics.report(error.on_line(index + 1))?;
             }
+        }
 
-            let parts: Vec<&str> = line.split_whitespace().collect();
-            if parts.is_empty() {
-                continue;
+        Ok(diagnostics.finish(chart))
+    }
+
+    /// Adds the song metadata C2S leaves to `Music.xml`
+    ///
+    /// `file` is the name of this chart's file, the difficulty declaring it sets
+    /// the chart's difficulty and level.
+    pub fn attach_music(&mut self, music: MusicXml, file: &str) {
+        if let Some(fumen) = music.fumen_for_file(file) {
+            self.metadata.difficulty = fumen.difficulty;
+            self.metadata.level = fumen.level;
+            self.metadata.constant = Some(fumen.constant());
+        }
+        self.metadata.music = music.id;
+        self.music = Some(music);
+    }
+
+    /// Parse a single metadata, note or end tag line into this chart
+    fn parse_line(&mut self, line: &str) -> Result<(), ParseError> {
+        let parts: Vec<&str> = line.split_whitespace().collect();
+        let Some(&tag) = parts.first() else {
+            return Ok(());
+        };
+        let metadata = &mut self.metadata;
+
+        match tag {
+            "VERSION" => {
+                metadata.version = [
+                    parse_field(line, &parts, 1, "version")?,
+                    parse_field(line, &parts, 2, "version")?,
+                ];
+            }
+            "MUSIC" => metadata.music = parse_field(line, &parts, 1, "music id")?,
+            "SEQUENCEID" => metadata.sequence_id = parse_field(line, &parts, 1, "sequence id")?,
+            "DIFFICULT" => metadata.difficulty = parse_field(line, &parts, 1, "difficulty")?,
+            "LEVEL" => {
+                let level: f32 = parse_field(line, &parts, 1, "level")?;
+                metadata.level = level as u32;
+                metadata.constant = (level.fract() != 0.0).then_some(level);
+            }
+            "CREATOR" => metadata.creator = parts[1..].join(" "),
+            "BPM_DEF" => {
+                metadata.bpm_default = [
+                    parse_field(line, &parts, 1, "default BPM")?,
+                    parse_field(line, &parts, 2, "default BPM")?,
+                    parse_field(line, &parts, 3, "default BPM")?,
+                    parse_field(line, &parts, 4, "default BPM")?,
+                ];
+            }
+            "MET_DEF" => {
+                metadata.metronome_def = Some([
+                    parse_field(line, &parts, 1, "numerator")?,
+                    parse_field(line, &parts, 2, "denominator")?,
+                    0,
+                    0,
+                ]);
+            }
+            "RESOLUTION" => {
+                // a beat is a quarter of it, it can't be 0 ticks long
+                metadata.resolution = Some(parse_field(line, &parts, 1, "resolution")?)
+                    .filter(|&resolution: &u32| resolution >= 4)
+                    .ok_or_else(|| ParseError::invalid(line, parts[1], "resolution"))?;
             }
+            "CLK_DEF" => metadata.clock_default = parse_field(line, &parts, 1, "clock")?,
+            "PROGJUDGE_BPM" => {
+                metadata.progjudge_bpm = parse_field(line, &parts, 1, "progress judge BPM")?;
+            }
+            "PROGJUDGE_AER" => {
+                metadata.progjudge_aer = parse_field(line, &parts, 1, "progress judge AER")?;
+            }
+            "TUTORIAL" => {
+                metadata.tutorial = parse_field::<String>(line, &parts, 1, "tutorial flag")? == "1";
+            }
+            "BPM" => metadata.bpm.push(Bpm {
+                measure: parse_field(line, &parts, 1, "measure")?,
+                offset: parse_field(line, &parts, 2, "offset")?,
+                bpm: parse_field(line, &parts, 3, "BPM")?,
+            }),
+            "MET" => metadata.time_signatures.push(TimeSignature {
+                measure: parse_field(line, &parts, 1, "measure")?,
+                offset: parse_field(line, &parts, 2, "offset")?,
+                numerator: parse_field(line, &parts, 3, "numerator")?,
+                denominator: parse_field(line, &parts, 4, "denominator")?,
+            }),
+            "SFL" => metadata.sfl.push(Sfl {
+                measure: parse_field(line, &parts, 1, "measure")?,
+                offset: parse_field(line, &parts, 2, "offset")?,
+                duration: parse_field(line, &parts, 3, "duration")?,
+                multiplier: parse_field(line, &parts, 4, "multiplier")?,
+            }),
+            tag if tag.starts_with("T_") => {
+                self.end_tags
+                    .get_or_insert_with(C2SEndTags::default)
+                    .parse_tag(tag, parts.get(1).copied().unwrap_or_default());
+            }
+            // If it's not a metadata field, it has to be a note
+            _ => self.notes.push(Note::from_line(line)?),
+        }
 
-            // Parse metadata
-            match parts[0] {
-                "VERSION" => {
-                    if parts.len() >= 3 {
-                        metadata.version = [parts[1].to_string(), parts[2].to_string()];
-                    }
+        Ok(())
+    }
+
+    /// Absolute tick of a measure/offset pair
+    fn absolute_tick(&self, measure: u32, offset: u32) -> u32 {
+        measure * self.metadata.resolution + offset
+    }
+
+    /// Timing of this chart, in C2S ticks
+    ///
+    /// C2S measures are always `resolution` ticks long (4 beats), `MET` is purely cosmetic.
+    pub fn tempo_map(&self) -> TempoMap {
+        let ticks_per_beat = self.metadata.resolution / 4;
+        let mut tempo = TempoMap::new(ticks_per_beat, self.metadata.bpm_default[0] as f64);
+        if let Some(first) = self.metadata.bpm.first() {
+            tempo.set_bpm(0, first.bpm as f64);
+        }
+        for bpm in &self.metadata.bpm {
+            tempo.set_bpm(self.absolute_tick(bpm.measure, bpm.offset), bpm.bpm as f64);
+        }
+        tempo
+    }
+
+    /// Scroll speeds of this chart, in C2S ticks
+    ///
+    /// Each `SFL` holds its multiplier for its duration, after which the speed goes back to 1.
+    pub fn scroll_map(&self) -> ScrollMap {
+        let mut scroll = ScrollMap::new(0);
+        let start = |sfl: &Sfl| self.absolute_tick(sfl.measure, sfl.offset);
+        // resets go in first so an SFL starting right where another one ends isn't cut off
+        for sfl in &self.metadata.sfl {
+            scroll.set_speed(0, start(sfl) + sfl.duration, 1.0);
+        }
+        for sfl in &self.metadata.sfl {
+            // multipliers are written with 6 decimals, don't carry f32 noise along
+            let speed = (sfl.multiplier as f64 * 1e6).round() / 1e6;
+            scroll.set_speed(0, start(sfl), speed);
+        }
+        scroll
+    }
+
+    /// Recomputes the `T_XXX` statistics from the notes and BPM timing of this chart
+    ///
+    /// Tags we don't know how to compute (`T_JUDGE_XXX`) are left out.
+    pub fn compute_end_tags(&self) -> C2SEndTags {
+        let mut tags = C2SEndTags::default();
+        let tempo = self.tempo_map();
+
+        // (start tick, end tick) of every counted note, plus whether it starts a new note
+        let mut spans: Vec<(u32, u32, bool)> = Vec::new();
+        // where each slide/air hold segment ends, so chained segments are only counted once
+        let mut chain_ends: Vec<(ChuniNoteType, u32, u32)> = Vec::new();
+
+        for note in &self.notes {
+            // ASD/ASC rows are air slides whatever type they wrap
+            if note.wrapped_note_info.is_some() {
+                if note.is_air_action() {
+                    tags.num_aac += 1;
                 }
-                "MUSIC" => {
-                    if parts.len() >= 2 {
-                        metadata.music = parts[1].parse().unwrap_or(0);
+                continue;
+            }
+            let suffix = match note.note_type {
+                ChuniNoteType::Tap => "TAP",
+                ChuniNoteType::ExTap => "CHR",
+                ChuniNoteType::Flick => "FLK",
+                ChuniNoteType::Mine => "MNE",
+                ChuniNoteType::Hold | ChuniNoteType::ExHold => "HLD",
+                ChuniNoteType::Slide
+                | ChuniNoteType::ExSlide
+                | ChuniNoteType::SlideControlPoint
+                | ChuniNoteType::ExSlideControlPoint => "SLD",
+                ChuniNoteType::Air | ChuniNoteType::AirDirectional(_) => "AIR",
+                ChuniNoteType::AirHold | ChuniNoteType::AirHoldGround => "AHD",
+                _ => {
+                    if note.is_air_action() {
+                        tags.num_aac += 1;
                     }
+                    continue;
                 }
-                "SEQUENCEID" => {
-                    if parts.len() >= 2 {
-                        metadata.sequence_id = parts[1].parse().unwrap_or(0);
+            };
+
+            let start = self.absolute_tick(note.measure, note.offset);
+            let end = start + note.duration.unwrap_or(0);
+
+            // a sustain segment continues another one if it starts where that one ended
+            let chain_kind = match suffix {
+                "SLD" => Some(ChuniNoteType::Slide),
+                "AHD" => Some(ChuniNoteType::AirHold),
+                _ => None,
+            };
+            let is_new_note = match &chain_kind {
+                Some(kind) => {
+                    let cell = note.cell;
+                    let continued = chain_ends.iter().position(|(other, tick, end_cell)| {
+                        other == kind && *tick == start && *end_cell == cell
+                    });
+                    if let Some(index) = continued {
+                        chain_ends.swap_remove(index);
                     }
+                    let end_cell = note.end_cell.map(|cell| cell as u32).unwrap_or(cell);
+                    chain_ends.push((kind.clone(), end, end_cell));
+                    continued.is_none()
                 }
-                "DIFFICULT" => {
-                    if parts.len() >= 2 {
-                        metadata.difficulty = parts[1].parse().unwrap_or(0);
-                    }
+                None => true,
+            };
+
+            let length = (tempo.tick_to_ms(end) - tempo.tick_to_ms(start)).round() as u32;
+            match suffix {
+                "HLD" => tags.len_hld += length,
+                "SLD" => tags.len_sld += length,
+                "AHD" => tags.len_ahd += length,
+                _ => {}
+            }
+
+            if suffix == "CHR" {
+                match note.chr_modifier.as_deref() {
+                    Some("UP") => tags.chr_type_up += 1,
+                    Some("DW") => tags.chr_type_dw += 1,
+                    Some("CE") => tags.chr_type_ce += 1,
+                    _ => {}
                 }
-                "LEVEL" => {
-                    if parts.len() >= 2 {
-                        metadata.level = parts[1].parse::<f32>().unwrap_or(0.0) as u32;
-                    }
+            }
+
+            if let Some(count) = tags.rec.get_mut(suffix) {
+                *count += 1;
+            }
+            if is_new_note && let Some(count) = tags.note.get_mut(suffix) {
+                *count += 1;
+            }
+            spans.push((start, end, is_new_note));
+        }
+        tags.num = tags.note.clone();
+
+        let first = spans.iter().map(|(start, _, _)| *start).min();
+        let last = spans.iter().map(|(_, end, _)| *end).max();
+        if let (Some(first), Some(last)) = (first, last) {
+            tags.first_res = first;
+            tags.final_res = last;
+
+            let first_msec = tempo.tick_to_ms(first);
+            let final_msec = tempo.tick_to_ms(last);
+            tags.first_msec = first_msec.round() as u32;
+            tags.final_msec = final_msec.round() as u32;
+
+            let span = final_msec - first_msec;
+            for (start, _, _) in spans.iter().filter(|(_, _, is_new_note)| *is_new_note) {
+                let progress = if span > 0.0 {
+                    (tempo.tick_to_ms(*start) - first_msec) / span
+                } else {
+                    0.0
+                };
+                let slice = ((progress * 20.0) as usize).min(19);
+                tags.prog[slice] += 1;
+            }
+        }
+
+        tags
+    }
+
+    /// Replaces the `T_XXX` statistics with freshly computed ones, keeping unknown tags
+    pub fn update_end_tags(&mut self) {
+        let mut tags = self.compute_end_tags();
+        if let Some(old) = self.end_tags.take() {
+            tags.other = old.other;
+        }
+        self.end_tags = Some(tags);
+    }
+}
+
+impl fmt::Display for Note {
+    /// Writes the note as a single tab-separated C2S line, without a trailing newline
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // ASD/ASC wrappers have a fixed 12-field layout, rebuild it from the wrapped info
+        if let Some(info) = &self.wrapped_note_info {
+            return write!(
+                f,
+                "{}\t{}\t{}\t{}\t{}\t{}\t{:.1}\t{}\t{}\t{}\t{:.1}\t{}",
+                info.original_format,
+                self.measure,
+                self.offset,
+                self.cell,
+                self.width,
+                info.wrapped_type,
+                info.param1,
+                self.duration.unwrap_or(0),
+                self.end_cell.unwrap_or(self.cell as f32),
+                self.end_width.unwrap_or(self.width as f32),
+                info.param2,
+                info.param3,
+            );
+        }
+
+        write!(
+            f,
+            "{}\t{}\t{}\t{}\t{}",
+            c2s_note_type_to_string(&self.note_type),
+            self.measure,
+            self.offset,
+            self.cell,
+            self.width
+        )?;
+
+        let mut fields: Vec<String> = Vec::new();
+        match self.note_type {
+            ChuniNoteType::Hold | ChuniNoteType::ExHold => {
+                fields.extend(self.duration.map(|duration| duration.to_string()));
+            }
+            ChuniNoteType::AirHold | ChuniNoteType::AirHoldGround => {
+                fields.extend(self.target_note.clone());
+                fields.extend(self.duration.map(|duration| duration.to_string()));
+            }
+            ChuniNoteType::Slide
+            | ChuniNoteType::ExSlide
+            | ChuniNoteType::SlideControlPoint
+            | ChuniNoteType::ExSlideControlPoint
+            | ChuniNoteType::AirSlideControlPoint => {
+                fields.extend(self.duration.map(|duration| duration.to_string()));
+                fields.extend(self.end_cell.map(|end_cell| end_cell.to_string()));
+                fields.extend(self.end_width.map(|end_width| end_width.to_string()));
+            }
+            // ALD keeps its start height in `end_cell`, written with one decimal like the game does
+            ChuniNoteType::AirSlide => {
+                fields.extend(self.duration.map(|duration| duration.to_string()));
+                fields.extend(self.end_cell.map(|height| format!("{:.1}", height)));
+                fields.extend(self.end_width.map(|end_width| end_width.to_string()));
+            }
+            ChuniNoteType::ExTap => fields.extend(self.chr_modifier.clone()),
+            ChuniNoteType::Flick => fields.extend(self.flick_modifier.clone()),
+            ChuniNoteType::Air | ChuniNoteType::AirDirectional(_) => {
+                fields.extend(self.target_note.clone())
+            }
+            _ => {}
+        }
+        fields.extend(self.extra_fields.iter().cloned());
+
+        for field in fields {
+            write!(f, "\t{}", field)?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for C2SMetadata {
+    /// Writes the header block and the BPM/MET/SFL composition lines
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "VERSION\t{}\t{}", self.version[0], self.version[1])?;
+        writeln!(f, "MUSIC\t{}", self.music)?;
+        writeln!(f, "SEQUENCEID\t{}", self.sequence_id)?;
+        writeln!(f, "DIFFICULT\t{:02}", self.difficulty)?;
+        // constants can have hundredths like 13.75, other levels keep the usual single decimal
+        let level = self.constant.unwrap_or(self.level as f32);
+        let decimals = if (level * 100.0).round() % 10.0 == 0.0 {
+            1
+        } else {
+            2
+        };
+        writeln!(f, "LEVEL\t{:.*}", decimals, level)?;
+        writeln!(f, "CREATOR\t{}", self.creator)?;
+        writeln!(
+            f,
+            "BPM_DEF\t{:.3}\t{:.3}\t{:.3}\t{:.3}",
+            self.bpm_default[0], self.bpm_default[1], self.bpm_default[2], self.bpm_default[3]
+        )?;
+        if let Some(metronome_def) = self.metronome_def {
+            writeln!(f, "MET_DEF\t{}\t{}", metronome_def[0], metronome_def[1])?;
+        }
+        writeln!(f, "RESOLUTION\t{}", self.resolution)?;
+        writeln!(f, "CLK_DEF\t{}", self.clock_default)?;
+        writeln!(f, "PROGJUDGE_BPM\t{:.3}", self.progjudge_bpm)?;
+        writeln!(f, "PROGJUDGE_AER\t{:.3}", self.progjudge_aer)?;
+        writeln!(f, "TUTORIAL\t{}", if self.tutorial { 1 } else { 0 })?;
+
+        if !self.bpm.is_empty() || !self.time_signatures.is_empty() || !self.sfl.is_empty() {
+            writeln!(f)?;
+        }
+        for bpm in &self.bpm {
+            writeln!(f, "BPM\t{}\t{}\t{:.3}", bpm.measure, bpm.offset, bpm.bpm)?;
+        }
+        for met in &self.time_signatures {
+            writeln!(
+                f,
+                "MET\t{}\t{}\t{}\t{}",
+                met.measure, met.offset, met.numerator, met.denominator
+            )?;
+        }
+        for sfl in &self.sfl {
+            writeln!(
+                f,
+                "SFL\t{}\t{}\t{}\t{:.6}",
+                sfl.measure, sfl.offset, sfl.duration, sfl.multiplier
+            )?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for C2SEndTags {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (prefix, counts) in [("REC", &self.rec), ("NOTE", &self.note), ("NUM", &self.num)] {
+            for (suffix, value) in NoteTypeCounts::SUFFIXES.iter().zip(counts.values()) {
+                writeln!(f, "T_{}_{}\t{}", prefix, suffix, value)?;
+            }
+            if prefix == "NUM" {
+                writeln!(f, "T_NUM_AAC\t{}", self.num_aac)?;
+            } else {
+                writeln!(f, "T_{}_ALL\t{}", prefix, counts.total())?;
+            }
+        }
+        writeln!(f, "T_CHRTYPE_UP\t{}", self.chr_type_up)?;
+        writeln!(f, "T_CHRTYPE_DW\t{}", self.chr_type_dw)?;
+        writeln!(f, "T_CHRTYPE_CE\t{}", self.chr_type_ce)?;
+        writeln!(f, "T_LEN_HLD\t{}", self.len_hld)?;
+        writeln!(f, "T_LEN_SLD\t{}", self.len_sld)?;
+        writeln!(f, "T_LEN_AHD\t{}", self.len_ahd)?;
+        writeln!(f, "T_LEN_ALL\t{}", self.len_total())?;
+        for (tag, value) in &self.other {
+            writeln!(f, "{}\t{}", tag, value)?;
+        }
+        writeln!(f, "T_FIRST_MSEC\t{}", self.first_msec)?;
+        writeln!(f, "T_FIRST_RES\t{}", self.first_res)?;
+        writeln!(f, "T_FINAL_MSEC\t{}", self.final_msec)?;
+        writeln!(f, "T_FINAL_RES\t{}", self.final_res)?;
+        for (index, value) in self.prog.iter().enumerate() {
+            writeln!(f, "T_PROG_{:02}\t{}", index * 5, value)?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for C2SChart {
+    /// Writes the whole chart in the tab-separated layout used by the game files
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.metadata)?;
+        writeln!(f)?;
+        for note in &self.notes {
+            writeln!(f, "{}", note)?;
+        }
+        if let Some(end_tags) = &self.end_tags {
+            writeln!(f)?;
+            write!(f, "{}", end_tags)?;
+        }
+        Ok(())
+    }
+}
+
+impl Default for C2SMetadata {
+    fn default() -> Self {
+        Self {
+            version: ["1.00.00".to_string(), "1.00.00".to_string()],
+            music: 0,
+            sequence_id: 0,
+            difficulty: 0,
+            level: 0,
+            constant: None,
+            creator: "Unknown".to_string(),
+            bpm_default: [120.0, 120.0, 120.0, 120.0],
+            metronome_def: Some([4, 4, 0, 0]),
+            resolution: DEFAULT_RESOLUTION,
+            clock_default: 384.0,
+            progjudge_bpm: 240.0,
+            progjudge_aer: 0.999,
+            tutorial: false,
+            bpm: Vec::new(),
+            time_signatures: Vec::new(),
+            sfl: Vec::new(),
+        }
+    }
+}
+
+/// ExTap effects, as written after CHR notes
+static EXTAP_EFFECTS: &[(&str, ExTapEffect)] = &[
+    ("UP", ExTapEffect::Up),
+    ("DW", ExTapEffect::Down),
+    ("CE", ExTapEffect::Center),
+];
+
+/// C2S modifier of an ExTap effect, `false` when C2S doesn't have it and the default is used
+///
+/// Missing effects are [`DEFAULT_EXTAP_EFFECT`].
+fn extap_modifier(effect: Option<ExTapEffect>) -> (&'static str, bool) {
+    let code = |effect| {
+        EXTAP_EFFECTS
+            .iter()
+            .find(|(_, known)| *known == effect)
+            .map(|(code, _)| *code)
+    };
+    match code(effect.unwrap_or(DEFAULT_EXTAP_EFFECT)) {
+        Some(code) => (code, true),
+        None => (code(DEFAULT_EXTAP_EFFECT).unwrap_or_default(), false),
+    }
+}
+
+/// Effect of an SXD/SXC segment, from its trailing `[target] [modifier]` fields
+///
+/// The error describes the field that has no equivalent.
+fn ex_slide_effect(note: &Note) -> Result<Option<ExTapEffect>, String> {
+    let name = c2s_note_type_to_string(&note.note_type);
+    let mut fields = note.extra_fields.iter();
+    if let Some(target) = fields.next()
+        && target != "SLD"
+    {
+        return Err(format!("unknown {} target {}", name, target));
+    }
+    match fields.next() {
+        Some(modifier) => EXTAP_EFFECTS
+            .iter()
+            .find(|(code, _)| code == modifier)
+            .map(|(_, effect)| Some(*effect))
+            .ok_or_else(|| format!("unknown {} effect {}", name, modifier)),
+        None => Ok(None),
+    }
+}
+
+/// Colors of ALD notes, only the ones seen in official charts so far
+static AIR_CRUSH_COLORS: &[(&str, AirCrushColor)] = &[
+    ("DEF", AirCrushColor::Normal),
+    ("YEL", AirCrushColor::Yellow),
+    ("BLK", AirCrushColor::Black),
+    ("GRY", AirCrushColor::Gray),
+    // no trail at all, only the AIR-ACTIONs along the path are visible
+    ("NON", AirCrushColor::Transparent),
+];
+
+/// ALD intervals of at least this many measures only generate combo at the start
+const START_ONLY_INTERVAL_MEASURES: u32 = 100;
+
+/// Ticks per measure in the IR
+const IR_MEASURE: u32 = ir::TICKS_PER_BEAT * 4;
+
+/// C2S ticks from `start` to `end`, 0 with a warning at the IR `tick` when `end` comes first
+fn duration(start: u32, end: u32, tick: u32, warnings: &mut Vec<ConversionWarning>) -> u32 {
+    end.checked_sub(start).unwrap_or_else(|| {
+        warnings.push(ConversionWarning::at(
+            tick,
+            "a segment ends before it starts, writing it with no length",
+        ));
+        0
+    })
+}
+
+/// The C2S type an air note names as its target, for the ground note it sits on
+fn target_note_type(chart: &IrChart, parent: Option<usize>) -> String {
+    let kind = parent
+        .and_then(|index| chart.notes.get(index))
+        .map(|note| &note.kind);
+    match kind {
+        Some(IrNoteKind::ExTap { .. }) => "CHR",
+        Some(IrNoteKind::Flick) => "FLK",
+        Some(IrNoteKind::Mine) => "MNE",
+        Some(IrNoteKind::Hold { ex: false, .. }) => "HLD",
+        Some(IrNoteKind::Hold { ex: true, .. }) => "HXD",
+        Some(IrNoteKind::Slide { ex: false, .. }) => "SLD",
+        Some(IrNoteKind::Slide { ex: true, .. }) => "SXD",
+        _ => "TAP",
+    }
+    .to_string()
+}
+
+impl ChuniChart for C2SChart {
+    fn to_ir(&self) -> Conversion<IrChart> {
+        let mut warnings = Vec::new();
+        let resolution = self.metadata.resolution;
+        let to_ir = |tick: u32| rescale_tick(tick, resolution, IR_MEASURE).0;
+
+        // `LEVEL 0.0` and `DIFFICULT 00` are what charts without Music.xml carry, not a real level
+        let metadata = &self.metadata;
+        let has_level = metadata.level != 0 || metadata.constant.is_some();
+        let level_decimal = metadata.constant.map_or(0, |constant| {
+            ((constant - metadata.level as f32) * 100.0).round() as u32
+        });
+        let mut chart = IrChart {
+            metadata: IrMetadata {
+                designer: Some(metadata.creator.clone()),
+                difficulty: (has_level || metadata.difficulty != 0).then_some(metadata.difficulty),
+                level: has_level.then(|| music::display_level(metadata.level, level_decimal)),
+                constant: metadata.constant,
+                song_id: (metadata.music != 0).then(|| metadata.music.to_string()),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        if let Some(music) = &self.music {
+            let text = |text: &str| (!text.is_empty()).then(|| text.to_string());
+            let metadata = &mut chart.metadata;
+            metadata.title = text(&music.title);
+            metadata.artist = text(&music.artist);
+            metadata.genre = music.genres.first().cloned();
+            metadata.jacket = music.jacket.clone();
+            metadata.bgm = music.cue.clone();
+            if let Some(fumen) = music.fumen(self.metadata.difficulty) {
+                metadata.level = Some(fumen.display_level());
+                metadata.constant = Some(fumen.constant());
+            }
+        }
+
+        chart.set_tempo_map(&self.tempo_map());
+        if !self.metadata.time_signatures.is_empty() {
+            chart.time_signatures = self
+                .metadata
+                .time_signatures
+                .iter()
+                .map(|met| TimeSignatureChange {
+                    tick: to_ir(self.absolute_tick(met.measure, met.offset)),
+                    numerator: met.numerator,
+                    denominator: met.denominator,
+                })
+                .collect();
+        }
+        chart.scroll = self.scroll_map().map_ticks(to_ir);
+
+        // chains that can still be continued: (note index, end tick, end cell, end width)
+        let mut open_slides: Vec<(usize, u32, u32, u32)> = Vec::new();
+        let mut open_air_holds: Vec<(usize, u32, u32)> = Vec::new();
+        let mut open_air_slides: Vec<(usize, u32, u32, u32)> = Vec::new();
+        // ALD chains also keep their color and interval: (index, end tick, end position, color, interval)
+        type CrushChain = (usize, u32, (u8, u8, f32), AirCrushColor, CrushInterval);
+        let mut open_air_crushes: Vec<CrushChain> = Vec::new();
+
+        let mut notes: Vec<&Note> = self.notes.iter().collect();
+        notes.sort_by_key(|note| self.absolute_tick(note.measure, note.offset));
+
+        for note in notes {
+            let start = self.absolute_tick(note.measure, note.offset);
+            let end = start + note.duration.unwrap_or(0);
+            let tick = to_ir(start);
+            let lane = note.cell as u8;
+            let width = note.width as u8;
+            let end_cell = note.end_cell.map_or(note.cell, |cell| cell as u32);
+            let end_width = note.end_width.map_or(note.width, |width| width as u32);
+            let mut push = |kind| {
+                chart.notes.push(IrNote {
+                    tick,
+                    lane,
+                    width,
+                    timeline: 0,
+                    kind,
+                });
+                chart.notes.len() - 1
+            };
+
+            if let Some(info) = &note.wrapped_note_info {
+                if info.param3 != "DEF" {
+                    warnings.push(ConversionWarning::at(
+                        tick,
+                        format!("unknown air slide color {}, using the default", info.param3),
+                    ));
                 }
-                "CREATOR" => {
-                    if parts.len() >= 2 {
-                        metadata.creator = parts[1..].join(" ");
+                let point = AirSlidePoint {
+                    tick: to_ir(end),
+                    lane: end_cell as u8,
+                    width: end_width as u8,
+                    height: info.param2,
+                    kind: SlidePointKind::Relay,
+                };
+
+                // ASC continues the air slide ending where it starts, ASD always starts a new one
+                let continued = (info.original_format == "ASC")
+                    .then(|| {
+                        open_air_slides.iter().position(|&(_, tick, cell, width)| {
+                            tick == start && cell == note.cell && width == note.width
+                        })
+                    })
+                    .flatten();
+                let index = match continued {
+                    Some(position) => {
+                        let (index, ..) = open_air_slides.swap_remove(position);
+                        if let IrNoteKind::AirSlide { points, .. } = &mut chart.notes[index].kind {
+                            points.push(point);
+                        }
+                        index
                     }
+                    None => push(IrNoteKind::AirSlide {
+                        height: info.param1,
+                        inverted: false,
+                        points: vec![point],
+                        parent: None,
+                    }),
+                };
+                open_air_slides.push((index, end, end_cell, end_width));
+                continue;
+            }
+
+            match &note.note_type {
+                ChuniNoteType::Tap => {
+                    push(IrNoteKind::Tap);
                 }
-                "BPM_DEF" => {
-                    if parts.len() >= 5 {
-                        metadata.bpm_default = [
-                            parts[1].parse().unwrap_or(120.0),
-                            parts[2].parse().unwrap_or(120.0),
-                            parts[3].parse().unwrap_or(120.0),
-                            parts[4].parse().unwrap_or(120.0),
-                        ];
+                ChuniNoteType::ExTap => {
+                    let effect = EXTAP_EFFECTS
+                        .iter()
+                        .find(|(code, _)| Some(*code) == note.chr_modifier.as_deref())
+                        .map(|(_, effect)| *effect);
+                    if effect.is_none()
+                        && let Some(modifier) = &note.chr_modifier
+                    {
+                        warnings.push(ConversionWarning::at(
+                            tick,
+                            format!("unknown CHR effect {}, using the default", modifier),
+                        ));
                     }
+                    push(IrNoteKind::ExTap { effect });
                 }
-                "MET_DEF" => {
-                    if parts.len() >= 3 {
-                        metadata.metronome_def = Some([
-                            parts[1].parse().unwrap_or(4),
-                            parts[2].parse().unwrap_or(4),
-                            0,
-                            0,
-                        ]);
-                    }
+                ChuniNoteType::Flick => {
+                    push(IrNoteKind::Flick);
                 }
-                "RESOLUTION" => {
-                    if parts.len() >= 2 {
-                        metadata.resolution = parts[1].parse().unwrap_or(384);
-                    }
+                ChuniNoteType::Mine => {
+                    push(IrNoteKind::Mine);
                 }
-                "CLK_DEF" => {
-                    if parts.len() >= 2 {
-                        metadata.clock_default = parts[1].parse().unwrap_or(384.0);
-                    }
+                ChuniNoteType::Hold | ChuniNoteType::ExHold => {
+                    push(IrNoteKind::Hold {
+                        end_tick: to_ir(end),
+                        ex: note.note_type == ChuniNoteType::ExHold,
+                        effect: None,
+                    });
+                }
+                ChuniNoteType::Slide
+                | ChuniNoteType::ExSlide
+                | ChuniNoteType::SlideControlPoint
+                | ChuniNoteType::ExSlideControlPoint => {
+                    let point = SlidePoint {
+                        tick: to_ir(end),
+                        lane: end_cell as u8,
+                        width: end_width as u8,
+                        kind: SlidePointKind::Relay,
+                    };
+                    // SLD segments start with a visible relay, SLC ones just bend the path
+                    let relay = matches!(
+                        note.note_type,
+                        ChuniNoteType::Slide | ChuniNoteType::ExSlide
+                    );
+                    let ex = matches!(
+                        note.note_type,
+                        ChuniNoteType::ExSlide | ChuniNoteType::ExSlideControlPoint
+                    );
+                    let (effect, unknown) = match ex.then(|| ex_slide_effect(note)) {
+                        Some(Ok(effect)) => (effect, None),
+                        Some(Err(message)) => (None, Some(message)),
+                        None => (None, None),
+                    };
+
+                    let continued = open_slides.iter().position(|&(_, tick, cell, width)| {
+                        tick == start && cell == note.cell && width == note.width
+                    });
+                    let index = match continued {
+                        Some(position) => {
+                            let (index, ..) = open_slides.swap_remove(position);
+                            if let IrNoteKind::Slide {
+                                ex: head_ex,
+                                effect: head_effect,
+                                points,
+                                ..
+                            } = &mut chart.notes[index].kind
+                            {
+                                // the whole slide is written with the ExTap of its head
+                                if (*head_ex, *head_effect) != (ex, effect) {
+                                    warnings.push(ConversionWarning::at(
+                                        tick,
+                                        "slide segments with different ExTaps, using the head's",
+                                    ));
+                                }
+                                if !relay && let Some(last) = points.last_mut() {
+                                    last.kind = SlidePointKind::Invisible;
+                                }
+                                points.push(point);
+                            }
+                            index
+                        }
+                        None => {
+                            if let Some(message) = unknown {
+                                warnings.push(ConversionWarning::at(
+                                    tick,
+                                    format!("{}, using the default", message),
+                                ));
+                            }
+                            push(IrNoteKind::Slide {
+                                ex,
+                                effect,
+                                head: if relay {
+                                    SlidePointKind::Relay
+                                } else {
+                                    SlidePointKind::Invisible
+                                },
+                                points: vec![point],
+                            })
+                        }
+                    };
+                    open_slides.push((index, end, end_cell, end_width));
                 }
-                "PROGJUDGE_BPM" => {
-                    if parts.len() >= 2 {
-                        metadata.progjudge_bpm = parts[1].parse().unwrap_or(240.0);
+                ChuniNoteType::Air | ChuniNoteType::AirDirectional(_) => {
+                    let direction = match &note.note_type {
+                        ChuniNoteType::AirDirectional(AirDirection::UpRight) => {
+                            ir::AirDirection::UpRight
+                        }
+                        ChuniNoteType::AirDirectional(AirDirection::UpLeft) => {
+                            ir::AirDirection::UpLeft
+                        }
+                        ChuniNoteType::AirDirectional(AirDirection::Down) => ir::AirDirection::Down,
+                        ChuniNoteType::AirDirectional(AirDirection::DownRight) => {
+                            ir::AirDirection::DownRight
+                        }
+                        ChuniNoteType::AirDirectional(AirDirection::DownLeft) => {
+                            ir::AirDirection::DownLeft
+                        }
+                        _ => ir::AirDirection::Up,
+                    };
+                    push(IrNoteKind::Air {
+                        direction,
+                        inverted: false,
+                        parent: None,
+                    });
+                }
+                ChuniNoteType::AirHold | ChuniNoteType::AirHoldGround => {
+                    let point = AirHoldPoint {
+                        tick: to_ir(end),
+                        action: true,
+                    };
+                    let continued = open_air_holds
+                        .iter()
+                        .position(|&(_, tick, cell)| tick == start && cell == note.cell);
+                    let index = match continued {
+                        Some(position) => {
+                            let (index, ..) = open_air_holds.swap_remove(position);
+                            if let IrNoteKind::AirHold { points, .. } = &mut chart.notes[index].kind
+                            {
+                                points.push(point);
+                            }
+                            index
+                        }
+                        None => push(IrNoteKind::AirHold {
+                            inverted: false,
+                            ground: note.note_type == ChuniNoteType::AirHoldGround,
+                            points: vec![point],
+                            parent: None,
+                        }),
+                    };
+                    open_air_holds.push((index, end, note.cell));
+                }
+                ChuniNoteType::AirSlide => {
+                    // ALD measure offset cell width interval height duration end_cell end_width end_height color,
+                    // the parser reads interval/height/duration into duration/end_cell/end_width
+                    let interval = note.duration.unwrap_or(0);
+                    let height = note.end_cell.unwrap_or(1.0);
+                    let end = start + note.end_width.unwrap_or(0.0) as u32;
+                    let extra = |index: usize| note.extra_fields.get(index).map(String::as_str);
+                    let end_cell = extra(0).and_then(|cell| cell.parse().ok()).unwrap_or(lane);
+                    let end_width = extra(1)
+                        .and_then(|width| width.parse().ok())
+                        .unwrap_or(width);
+                    let end_height = extra(2)
+                        .and_then(|height| height.parse().ok())
+                        .unwrap_or(height);
+                    let color = extra(3).unwrap_or("DEF");
+                    let color = match AIR_CRUSH_COLORS.iter().find(|(code, _)| *code == color) {
+                        Some((_, color)) => *color,
+                        None => {
+                            warnings.push(ConversionWarning::at(
+                                tick,
+                                format!("unknown ALD color {}, using the default", color),
+                            ));
+                            AirCrushColor::Normal
+                        }
+                    };
+                    let interval = if interval >= START_ONLY_INTERVAL_MEASURES * resolution {
+                        CrushInterval::StartOnly
+                    } else {
+                        CrushInterval::Ticks(
+                            interval as f32 * IR_MEASURE as f32 / resolution as f32,
+                        )
+                    };
+
+                    let point = AirSlidePoint {
+                        tick: to_ir(end),
+                        lane: end_cell,
+                        width: end_width,
+                        height: end_height,
+                        kind: SlidePointKind::Relay,
+                    };
+
+                    let continued = open_air_crushes.iter().position(
+                        |&(_, tick, position, chain_color, chain_interval)| {
+                            tick == start
+                                && position == (lane, width, height)
+                                && (chain_color, chain_interval) == (color, interval)
+                        },
+                    );
+                    let index = match continued {
+                        Some(position) => {
+                            let (index, ..) = open_air_crushes.swap_remove(position);
+                            if let IrNoteKind::AirCrush { points, .. } =
+                                &mut chart.notes[index].kind
+                            {
+                                points.push(point);
+                            }
+                            index
+                        }
+                        None => push(IrNoteKind::AirCrush {
+                            height,
+                            color,
+                            interval,
+                            points: vec![point],
+                        }),
+                    };
+                    open_air_crushes.push((
+                        index,
+                        end,
+                        (end_cell, end_width, end_height),
+                        color,
+                        interval,
+                    ));
+                }
+                other => warnings.push(ConversionWarning::at(
+                    tick,
+                    format!(
+                        "skipping {} note, it has no equivalent",
+                        c2s_note_type_to_string(other)
+                    ),
+                )),
+            }
+        }
+
+        chart.sort_notes();
+        chart.link_air_parents();
+        Conversion::new(chart, warnings)
+    }
+
+    fn from_ir(chart: &IrChart) -> Conversion<Self> {
+        let mut warnings = Vec::new();
+        let mut metadata = C2SMetadata::default();
+        let resolution = metadata.resolution;
+
+        // positions that don't land on a C2S tick, reported once at the end
+        let mut rounded = 0;
+        let mut to_c2s = |tick: u32| {
+            let (tick, was_rounded) = rescale_tick(tick, IR_MEASURE, resolution);
+            rounded += was_rounded as usize;
+            tick
+        };
+
+        if let Some(designer) = &chart.metadata.designer {
+            metadata.creator = designer.clone();
+        }
+        metadata.difficulty = chart.metadata.difficulty.unwrap_or_default();
+        metadata.level = chart
+            .metadata
+            .level
+            .as_deref()
+            .and_then(|level| level.trim_end_matches('+').parse().ok())
+            .or(chart.metadata.constant.map(|constant| constant as u32))
+            .unwrap_or_default();
+        metadata.constant = chart.metadata.constant;
+        metadata.music = chart
+            .metadata
+            .song_id
+            .as_deref()
+            .and_then(|id| id.parse().ok())
+            .unwrap_or_default();
+
+        for change in &chart.bpm_changes {
+            let tick = to_c2s(change.tick);
+            metadata.bpm.push(Bpm {
+                measure: tick / resolution,
+                offset: tick % resolution,
+                bpm: change.bpm as f32,
+            });
+        }
+        if let Some(first) = metadata.bpm.first() {
+            metadata.bpm_default = [first.bpm; 4];
+        }
+        for change in &chart.time_signatures {
+            let tick = to_c2s(change.tick);
+            metadata.time_signatures.push(TimeSignature {
+                measure: tick / resolution,
+                offset: tick % resolution,
+                numerator: change.numerator,
+                denominator: change.denominator,
+            });
+        }
+        if let Some(first) = metadata.time_signatures.first() {
+            metadata.metronome_def = Some([first.numerator, first.denominator, 0, 0]);
+        }
+
+        let speeds = chart.single_timeline_speeds("C2S");
+        warnings.extend(speeds.warnings);
+        // a speed change that lasts until the end needs a duration, cover every note
+        let chart_end = chart.notes.iter().map(IrNote::end_tick).max().unwrap_or(0);
+        for (index, change) in speeds.value.iter().enumerate() {
+            if change.speed == 1.0 {
+                continue;
+            }
+            let end = speeds
+                .value
+                .get(index + 1)
+                .map_or(chart_end.max(change.tick + IR_MEASURE), |next| next.tick);
+            let (start, end) = (to_c2s(change.tick), to_c2s(end));
+            metadata.sfl.push(Sfl {
+                measure: start / resolution,
+                offset: start % resolution,
+                duration: duration(start, end, change.tick, &mut warnings),
+                multiplier: change.speed as f32,
+            });
+        }
+
+        let mut notes = Vec::new();
+        let mut skipped_controls = false;
+        for ir_note in &chart.notes {
+            let start = to_c2s(ir_note.tick);
+            let (measure, offset) = (start / resolution, start % resolution);
+            let cell = ir_note.lane as u32;
+            let width = ir_note.width as u32;
+
+            match &ir_note.kind {
+                IrNoteKind::Tap => notes.push(Note::tap(measure, offset, cell, width)),
+                IrNoteKind::ExTap { effect } => {
+                    let (modifier, kept) = extap_modifier(*effect);
+                    if !kept {
+                        warnings.push(ConversionWarning::at(
+                            ir_note.tick,
+                            format!("C2S only has UP/DW/CE ExTap effects, using {}", modifier),
+                        ));
                     }
+                    notes.push(Note::chr(
+                        measure,
+                        offset,
+                        cell,
+                        width,
+                        modifier.to_string(),
+                    ));
                 }
-                "PROGJUDGE_AER" => {
-                    if parts.len() >= 2 {
-                        metadata.progjudge_aer = parts[1].parse().unwrap_or(0.999);
+                IrNoteKind::Flick => notes.push(Note::flick(measure, offset, cell, width)),
+                IrNoteKind::Mine => notes.push(Note::mine(measure, offset, cell, width)),
+                IrNoteKind::Hold {
+                    end_tick,
+                    ex,
+                    effect,
+                } => {
+                    let length = duration(start, to_c2s(*end_tick), ir_note.tick, &mut warnings);
+                    let mut note = Note::hold(measure, offset, cell, width, length);
+                    if *ex {
+                        note.note_type = ChuniNoteType::ExHold;
+                    }
+                    // HXD has no effect field, it reads back without one, which is written as the
+                    // default, so only other effects are lost
+                    if *ex && effect.is_some_and(|effect| effect != DEFAULT_EXTAP_EFFECT) {
+                        warnings.push(ConversionWarning::at(
+                            ir_note.tick,
+                            "HXD notes have no ExTap effect, dropping it",
+                        ));
                     }
+                    notes.push(note);
                 }
-                "TUTORIAL" => {
-                    if parts.len() >= 2 {
-                        metadata.tutorial = parts[1] == "1";
+                IrNoteKind::Slide {
+                    ex,
+                    effect,
+                    head,
+                    points,
+                } => {
+                    // every segment of an ex slide repeats the SLD target and the effect
+                    let (modifier, kept) = extap_modifier(*effect);
+                    if *ex && !kept {
+                        warnings.push(ConversionWarning::at(
+                            ir_note.tick,
+                            format!("C2S only has UP/DW/CE ExTap effects, using {}", modifier),
+                        ));
+                    }
+
+                    let (mut tick, mut cell, mut width) = (start, cell, width);
+                    let mut relay = *head == SlidePointKind::Relay;
+                    for point in points {
+                        let end = to_c2s(point.tick);
+                        let (end_cell, end_width) = (point.lane as u32, point.width as u32);
+
+                        let mut note = Note::slide(
+                            tick / resolution,
+                            tick % resolution,
+                            cell,
+                            width,
+                            duration(tick, end, ir_note.tick, &mut warnings),
+                            end_cell as f32,
+                            end_width as f32,
+                        );
+                        note.note_type = match (relay, *ex) {
+                            (true, false) => ChuniNoteType::Slide,
+                            (false, false) => ChuniNoteType::SlideControlPoint,
+                            (true, true) => ChuniNoteType::ExSlide,
+                            (false, true) => ChuniNoteType::ExSlideControlPoint,
+                        };
+                        if *ex {
+                            note.extra_fields = vec!["SLD".to_string(), modifier.to_string()];
+                        }
+                        notes.push(note);
+
+                        skipped_controls |= point.kind == SlidePointKind::Control;
+                        relay = point.kind == SlidePointKind::Relay;
+                        (tick, cell, width) = (end, end_cell, end_width);
                     }
                 }
-                "BPM" => {
-                    if parts.len() >= 4 {
-                        metadata.bpm.push(Bpm {
-                            measure: parts[1].parse().unwrap_or(0),
-                            offset: parts[2].parse().unwrap_or(0),
-                            bpm: parts[3].parse().unwrap_or(120.0),
-                        });
+                IrNoteKind::Air {
+                    direction,
+                    inverted,
+                    parent,
+                } => {
+                    if *inverted {
+                        warnings.push(ConversionWarning::at(
+                            ir_note.tick,
+                            "C2S has no inverted air notes, writing a normal one",
+                        ));
                     }
+                    let target = target_note_type(chart, *parent);
+                    let direction = match direction {
+                        ir::AirDirection::Up => None,
+                        ir::AirDirection::UpRight => Some(AirDirection::UpRight),
+                        ir::AirDirection::UpLeft => Some(AirDirection::UpLeft),
+                        ir::AirDirection::Down => Some(AirDirection::Down),
+                        ir::AirDirection::DownRight => Some(AirDirection::DownRight),
+                        ir::AirDirection::DownLeft => Some(AirDirection::DownLeft),
+                    };
+                    notes.push(match direction {
+                        Some(direction) => {
+                            Note::air_directional(measure, offset, cell, width, target, direction)
+                        }
+                        None => Note::air(measure, offset, cell, width, target),
+                    });
                 }
-                "MET" => {
-                    if parts.len() >= 5 {
-                        metadata.time_signatures.push(TimeSignature {
-                            measure: parts[1].parse().unwrap_or(0),
-                            offset: parts[2].parse().unwrap_or(0),
-                            numerator: parts[3].parse().unwrap_or(4),
-                            denominator: parts[4].parse().unwrap_or(4),
-                        });
+                IrNoteKind::AirHold {
+                    inverted,
+                    ground,
+                    points,
+                    parent,
+                } => {
+                    if *inverted {
+                        warnings.push(ConversionWarning::at(
+                            ir_note.tick,
+                            "C2S has no inverted air holds, writing a normal one",
+                        ));
+                    }
+                    if points.iter().any(|point| !point.action) {
+                        warnings.push(ConversionWarning::at(
+                            ir_note.tick,
+                            "C2S air holds always have AIR-ACTIONs, points without one get one",
+                        ));
+                    }
+
+                    let mut tick = start;
+                    let mut target = target_note_type(chart, *parent);
+                    for point in points {
+                        let end = to_c2s(point.tick);
+                        let mut note = Note::air_hold(
+                            tick / resolution,
+                            tick % resolution,
+                            cell,
+                            width,
+                            target,
+                            duration(tick, end, ir_note.tick, &mut warnings),
+                        );
+                        if *ground {
+                            note.note_type = ChuniNoteType::AirHoldGround;
+                            note.extra_fields.push("DEF".to_string());
+                        }
+                        notes.push(note);
+                        tick = end;
+                        target = "AHD".to_string();
                     }
                 }
-                "SFL" => {
-                    if parts.len() >= 5 {
-                        metadata.sfl.push(Sfl {
-                            measure: parts[1].parse().unwrap_or(0),
-                            offset: parts[2].parse().unwrap_or(0),
-                            duration: parts[3].parse().unwrap_or(0),
-                            multiplier: parts[4].parse().unwrap_or(1.0),
+                IrNoteKind::AirSlide {
+                    height,
+                    inverted,
+                    points,
+                    parent,
+                } => {
+                    if *inverted {
+                        warnings.push(ConversionWarning::at(
+                            ir_note.tick,
+                            "C2S has no inverted air slides, writing a normal one",
+                        ));
+                    }
+
+                    let (mut tick, mut cell, mut width, mut height) = (start, cell, width, *height);
+                    let mut wrapped = ("ASD", target_note_type(chart, *parent));
+                    for point in points {
+                        let end = to_c2s(point.tick);
+                        let (end_cell, end_width) = (point.lane as u32, point.width as u32);
+                        let (original_format, wrapped_type) = wrapped;
+                        let mut note = Note::slide(
+                            tick / resolution,
+                            tick % resolution,
+                            cell,
+                            width,
+                            duration(tick, end, ir_note.tick, &mut warnings),
+                            end_cell as f32,
+                            end_width as f32,
+                        );
+                        note.note_type = string_to_note_type(&wrapped_type);
+                        note.wrapped_note_info = Some(WrappedNoteInfo {
+                            original_format: original_format.to_string(),
+                            wrapped_type,
+                            param1: height,
+                            param2: point.height,
+                            param3: "DEF".to_string(),
                         });
+                        notes.push(note);
+
+                        (tick, cell, width, height) = (end, end_cell, end_width, point.height);
+                        wrapped = ("ASC", "ASC".to_string());
                     }
                 }
-                // If it's not a metadata field, try to parse it as a note
-                _ => {
-                    in_notes_section = true;
-                    match Note::from_line(line) {
-                        Ok(note) => notes.push(note),
-                        Err(_) => {
-                            // Skip lines that can't be parsed as notes (might be comments or other metadata)
+                IrNoteKind::AirCrush {
+                    height,
+                    color,
+                    interval,
+                    points,
+                } => {
+                    let color = match AIR_CRUSH_COLORS.iter().find(|(_, known)| known == color) {
+                        Some((code, _)) => *code,
+                        None => {
+                            warnings.push(ConversionWarning::at(
+                                ir_note.tick,
+                                format!(
+                                    "C2S has no {:?} air crushes, using the default color",
+                                    color
+                                ),
+                            ));
+                            "DEF"
                         }
+                    };
+                    let interval = match interval {
+                        CrushInterval::StartOnly => START_ONLY_INTERVAL_MEASURES * resolution,
+                        CrushInterval::Ticks(ticks) => {
+                            (ticks * resolution as f32 / IR_MEASURE as f32).round() as u32
+                        }
+                    };
+
+                    let (mut tick, mut cell, mut width, mut height) = (start, cell, width, *height);
+                    for point in points {
+                        let end = to_c2s(point.tick);
+                        let mut note = Note::slide(
+                            tick / resolution,
+                            tick % resolution,
+                            cell,
+                            width,
+                            interval,
+                            height,
+                            duration(tick, end, ir_note.tick, &mut warnings) as f32,
+                        );
+                        note.note_type = ChuniNoteType::AirSlide;
+                        note.extra_fields = vec![
+                            point.lane.to_string(),
+                            point.width.to_string(),
+                            format!("{:.1}", point.height),
+                            color.to_string(),
+                        ];
+                        notes.push(note);
+
+                        (tick, cell, width, height) =
+                            (end, point.lane as u32, point.width as u32, point.height);
                     }
                 }
             }
         }
 
-        Ok(C2SChart { metadata, notes })
-    }
-}
-
-impl Default for C2SMetadata {
-    fn default() -> Self {
-        Self {
-            version: ["1.00.00".to_string(), "1.00.00".to_string()],
-            music: 0,
-            sequence_id: 0,
-            difficulty: 0,
-            level: 0,
-            creator: "Unknown".to_string(),
-            bpm_default: [120.0, 120.0, 120.0, 120.0],
-            metronome_def: Some([4, 4, 0, 0]),
-            resolution: 384,
-            clock_default: 384.0,
-            progjudge_bpm: 240.0,
-            progjudge_aer: 0.999,
-            tutorial: false,
-            bpm: Vec::new(),
-            time_signatures: Vec::new(),
-            sfl: Vec::new(),
+        if skipped_controls {
+            warnings.push(ConversionWarning::new(
+                "C2S slides can't curve, control points were written as SLC corners",
+            ));
+        }
+        if rounded > 0 {
+            warnings.push(ConversionWarning::new(format!(
+                "{} positions were rounded to the nearest C2S tick",
+                rounded
+            )));
         }
+
+        notes.sort_by_key(|note| (note.measure, note.offset));
+        Conversion::new(
+            C2SChart {
+                metadata,
+                notes,
+                end_tags: None,
+                music: None,
+            },
+            warnings,
+        )
     }
 }
 
@@ -1075,6 +2359,8 @@ TAP	4	0	0	16"#;
         assert_eq!(chart.metadata.sequence_id, 0);
         assert_eq!(chart.metadata.difficulty, 0);
         assert_eq!(chart.metadata.level, 0);
+        let metadata = chart.to_ir().value.metadata;
+        assert_eq!((metadata.level, metadata.difficulty), (None, None));
         assert_eq!(chart.metadata.creator, "みぞれヤナギ");
         assert_eq!(chart.metadata.bpm_default, [135.0, 135.0, 135.0, 135.0]);
         assert_eq!(chart.metadata.metronome_def, Some([4, 4, 0, 0]));
@@ -1257,6 +2543,11 @@ AHX	10	0	8	4	TAP	192	DEF"#;
         assert_eq!(chart.metadata.sequence_id, 3);
         assert_eq!(chart.metadata.difficulty, 3);
         assert_eq!(chart.metadata.level, 13); // Should be truncated to integer
+        assert_eq!(chart.metadata.constant, Some(13.2));
+        assert_eq!(chart.to_ir().value.metadata.level.as_deref(), Some("13"));
+        let plus = C2SChart::from_string("LEVEL\t13.75\nTAP\t0\t0\t8\t4").unwrap();
+        assert_eq!(plus.to_ir().value.metadata.level.as_deref(), Some("13+"));
+        assert!(plus.to_string().contains("LEVEL\t13.75\n"));
         assert_eq!(chart.metadata.creator, "SOMEONE");
         assert_eq!(chart.metadata.bpm_default, [120.0, 120.0, 120.0, 120.0]);
         assert_eq!(chart.metadata.metronome_def, Some([4, 4, 0, 0]));
@@ -1388,4 +2679,405 @@ AHX	10	0	8	4	TAP	192	DEF"#;
         assert_eq!(chart.notes[17].target_note, Some("TAP".to_string()));
         assert_eq!(chart.notes[17].duration, Some(192));
     }
+
+    #[test]
+    fn test_write_note_lines() {
+        let lines = [
+            "TAP\t8\t0\t6\t4",
+            "CHR\t9\t0\t4\t8\tCE",
+            "HLD\t18\t0\t0\t3\t192",
+            "SLD\t8\t148\t0\t4\t236\t0\t4",
+            "SXC\t14\t288\t0\t4\t12\t1\t4\tSLD\tUP",
+            "FLK\t14\t0\t0\t4\tL",
+            "AUL\t14\t288\t0\t4\tFLK\tDEF",
+            "AHD\t9\t0\t0\t4\tSLD\t192",
+            "AHX\t65\t192\t12\t4\tSLD\t96\tDEF",
+            "ALD\t6\t96\t4\t8\t38400\t5.0\t1\t4\t8\t5.0\tNON",
+            "MNE\t15\t192\t8\t2",
+            "ASD\t12\t0\t0\t6\tCHR\t5.0\t384\t0\t3\t5.0\tDEF",
+            "ASC\t5\t336\t9\t4\tASC\t5.0\t22\t9\t5\t5.0\tDEF",
+        ];
+
+        for line in lines {
+            let note = Note::from_line(line).unwrap();
+            assert_eq!(note.to_string(), line);
+        }
+    }
+
+    /// Notes of a chart as sorted lines, so charts can be compared regardless of note order
+    fn sorted_lines(chart: &C2SChart) -> Vec<String> {
+        let mut lines: Vec<String> = chart.notes.iter().map(Note::to_string).collect();
+        lines.sort();
+        lines
+    }
+
+    #[test]
+    fn test_ir_round_trip() {
+        let content = format!(
+            "{}\nCHR\t12\t0\t0\t6\tUP\nASD\t12\t0\t0\t6\tCHR\t5.0\t384\t0\t3\t5.0\tDEF\n\
+             ALD\t13\t96\t4\t8\t38400\t5.0\t1\t4\t8\t5.0\tNON\n\
+             ALD\t13\t192\t5\t6\t6\t3.0\t1\t5\t6\t3.0\tNON",
+            CYAEGHA_NOTES
+        );
+        let chart = C2SChart::from_string(&content).unwrap();
+
+        let ir = chart.to_ir();
+        assert!(ir.warnings.is_empty(), "{:?}", ir.warnings);
+        let converted = C2SChart::from_ir(&ir.value);
+        assert!(converted.warnings.is_empty(), "{:?}", converted.warnings);
+
+        assert_eq!(sorted_lines(&converted.value), sorted_lines(&chart));
+    }
+
+    #[test]
+    fn test_air_crush_chains() {
+        let chart = C2SChart::from_string(
+            "RESOLUTION\t384\nBPM\t0\t0\t120.000\n\n\
+             ALD\t1\t0\t0\t4\t6\t3.0\t96\t4\t4\t3.0\tGRY\n\
+             ALD\t1\t96\t4\t4\t6\t3.0\t96\t8\t4\t5.0\tGRY\n\
+             ALD\t2\t0\t0\t16\t38400\t5.0\t1\t0\t16\t5.0\tRED",
+        )
+        .unwrap();
+        let ir = chart.to_ir();
+        let messages: Vec<_> = ir.warnings.iter().map(|warning| &warning.message).collect();
+        assert_eq!(messages, vec!["unknown ALD color RED, using the default"]);
+
+        // the two GRY segments are a single air crush
+        let crushes: Vec<_> = ir
+            .value
+            .notes
+            .iter()
+            .filter_map(|note| match &note.kind {
+                IrNoteKind::AirCrush { color, points, .. } => Some((*color, points.len())),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(
+            crushes,
+            vec![(AirCrushColor::Gray, 2), (AirCrushColor::Normal, 1)]
+        );
+
+        let converted = C2SChart::from_ir(&ir.value).value;
+        let written = converted.to_string();
+        assert!(written.contains(
+            "ALD\t1\t0\t0\t4\t6\t3.0\t96\t4\t4\t3.0\tGRY\n\
+             ALD\t1\t96\t4\t4\t6\t3.0\t96\t8\t4\t5.0\tGRY\n"
+        ));
+    }
+
+    #[test]
+    fn test_to_ir_chains() {
+        let chart = C2SChart::from_string(CYAEGHA_NOTES).unwrap();
+        let ir = chart.to_ir().value;
+
+        // 5 SLC + 1 SLD, then 5 SLC + 1 SLD, each chained into a single slide
+        let slides: Vec<&IrNote> = ir
+            .notes
+            .iter()
+            .filter(|note| matches!(note.kind, IrNoteKind::Slide { .. }))
+            .collect();
+        assert_eq!(slides.len(), 2);
+        assert_eq!(slides[0].tick, (8 * 384 + 96) * 5);
+        let IrNoteKind::Slide { points, ex, .. } = &slides[0].kind else {
+            unreachable!()
+        };
+        assert!(!ex);
+        assert_eq!(points.len(), 5);
+        assert_eq!(points[3].kind, SlidePointKind::Relay);
+        assert_eq!(points[2].kind, SlidePointKind::Invisible);
+        assert_eq!(points.last().unwrap().tick, 9 * 1920);
+
+        // both air holds sit on the ends of the slides, the air note on the CHR
+        let parents: Vec<Option<usize>> = ir
+            .notes
+            .iter()
+            .filter_map(|note| match note.kind {
+                IrNoteKind::AirHold { parent, .. } | IrNoteKind::Air { parent, .. } => Some(parent),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(parents.len(), 3);
+        for parent in parents {
+            let parent = &ir.notes[parent.expect("air note without parent")];
+            assert!(matches!(
+                parent.kind,
+                IrNoteKind::Slide { .. } | IrNoteKind::ExTap { .. }
+            ));
+        }
+    }
+
+    #[test]
+    fn test_chronomia_adv_round_trip() {
+        let chart = C2SChart::from_string(include_str!(
+            "../../../test/chuni/c2s/chronomia_advanced.notesonly.c2s"
+        ))
+        .unwrap();
+        let written = chart.to_string();
+        let reparsed = C2SChart::from_string(&written).unwrap();
+
+        assert_eq!(chart.notes.len(), 35);
+        assert_eq!(reparsed, chart);
+        // the trailing SLD/BS fields on SXD notes must survive the trip
+        assert!(written.contains("SXD\t4\t0\t12\t4\t384\t4\t4\tSLD\tBS"));
+    }
+
+    #[test]
+    fn test_chronomia_adv_ir_round_trip() {
+        let chart = C2SChart::from_string(include_str!(
+            "../../../test/chuni/c2s/chronomia_advanced.notesonly.c2s"
+        ))
+        .unwrap();
+        let ir = chart.to_ir();
+        let messages: Vec<_> = ir.warnings.iter().map(|warning| &warning.message).collect();
+        assert_eq!(
+            messages,
+            vec![
+                "unknown CHR effect BS, using the default",
+                // the four SXD segments form a single slide
+                "unknown SXD effect BS, using the default",
+            ]
+        );
+
+        let converted = C2SChart::from_ir(&ir.value);
+        assert!(converted.warnings.is_empty(), "{:?}", converted.warnings);
+        let written = converted.value.to_string();
+        // SXD heads stay SXD, and every segment of an SXC chain keeps its ExTap
+        assert!(written.contains("SXD\t4\t0\t12\t4\t384\t4\t4\tSLD\tUP\n"));
+        assert!(written.contains("SXC\t14\t288\t0\t4\t12\t1\t4\tSLD\tUP\n"));
+        assert!(written.contains("SXC\t14\t300\t1\t4\t14\t2\t4\tSLD\tUP\n"));
+        assert!(written.contains("SXD\t15\t75\t6\t4\t117\t6\t4\tSLD\tUP\n"));
+        assert_eq!(converted.value.notes.len(), chart.notes.len());
+    }
+
+    #[test]
+    fn test_metadata_round_trip() {
+        let chart_content = r#"VERSION	1.13.00	1.13.00
+MUSIC	2699
+SEQUENCEID	3
+DIFFICULT	03
+LEVEL	13.0
+CREATOR	SOMEONE ELSE
+BPM_DEF	175.000	150.000	150.000	175.000
+MET_DEF	4	4
+RESOLUTION	384
+CLK_DEF	384
+PROGJUDGE_BPM	240.000
+PROGJUDGE_AER	0.999
+TUTORIAL	0
+
+BPM	0	0	175.000
+BPM	8	192	150.000
+MET	0	0	4	4
+SFL	4	0	384	1.500000
+SFL	10	1	23	-2.000000
+
+TAP	5	0	8	4
+"#;
+
+        let chart = C2SChart::from_string(chart_content).unwrap();
+        assert_eq!(chart.to_string(), chart_content);
+    }
+
+    #[test]
+    fn test_end_tags_parsing() {
+        let chart_content = "TAP\t0\t0\t8\t4\n\nT_REC_TAP\t1\nT_REC_ALL\t1\nT_NUM_AAC\t3\nT_CHRTYPE_CE\t2\nT_LEN_HLD\t1500\nT_JUDGE_TAP\t7\nT_JUDGE_ALL\t7\nT_FIRST_MSEC\t1234\nT_FINAL_RES\t768\nT_PROG_95\t4\n";
+        let chart = C2SChart::from_string(chart_content).unwrap();
+
+        assert_eq!(chart.notes.len(), 1);
+        let tags = chart.end_tags.as_ref().unwrap();
+        assert_eq!(tags.rec.tap, 1);
+        assert_eq!(tags.rec.total(), 1);
+        assert_eq!(tags.num_aac, 3);
+        assert_eq!(tags.chr_type_ce, 2);
+        assert_eq!(tags.len_hld, 1500);
+        assert_eq!(tags.first_msec, 1234);
+        assert_eq!(tags.final_res, 768);
+        assert_eq!(tags.prog[19], 4);
+        assert_eq!(
+            tags.other,
+            vec![
+                ("T_JUDGE_TAP".to_string(), "7".to_string()),
+                ("T_JUDGE_ALL".to_string(), "7".to_string())
+            ]
+        );
+
+        let reparsed = C2SChart::from_string(&chart.to_string()).unwrap();
+        assert_eq!(reparsed, chart);
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        let content = "RESOLUTION\t384\nTAP\t0\t0\t8\t4\nHLD\t1\t0\t4\t4\tlong\nTAP\t2\t0\n";
+
+        let error = C2SChart::from_string(content).unwrap_err();
+        assert_eq!(error.location.line, 3);
+        assert_eq!(error.location.column, 13);
+        assert_eq!(
+            error.kind,
+            ParseErrorKind::InvalidField {
+                field: "hold duration",
+                value: "long".to_string()
+            }
+        );
+
+        let parsed = C2SChart::parse(content, ParseMode::Lenient).unwrap();
+        assert_eq!(parsed.value.notes.len(), 1);
+        assert_eq!(parsed.warnings.len(), 2);
+        assert_eq!(parsed.warnings[0], error);
+        assert_eq!(
+            parsed.warnings[1].to_string(),
+            "line 4, column 8: missing cell"
+        );
+
+        // a beat would be 0 ticks long
+        let error = C2SChart::from_string("RESOLUTION\t2\nTAP\t0\t0\t8\t4").unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "line 1, column 12: invalid resolution `2`"
+        );
+    }
+
+    #[test]
+    fn test_tempo_map() {
+        let chart = C2SChart::from_string(
+            "RESOLUTION\t384\nBPM\t0\t0\t120.000\nBPM\t1\t192\t240.000\nMET\t0\t0\t3\t4\nTAP\t2\t0\t0\t4",
+        )
+        .unwrap();
+        let tempo = chart.tempo_map();
+
+        // MET doesn't change measure lengths in C2S
+        assert_eq!(tempo.measure_to_tick(2, 0), 768);
+        assert_eq!(tempo.measure_to_ms(1, 192), 3000.0);
+        assert_eq!(tempo.measure_to_ms(2, 0), 3500.0);
+        assert_eq!(tempo.ms_to_measure(3250.0), (1, 288));
+    }
+
+    #[test]
+    fn test_speed_changes() {
+        let chart = C2SChart::from_string(
+            "RESOLUTION\t384\nSFL\t1\t0\t192\t1.5\nSFL\t1\t192\t96\t0.8\n\
+             SFL\t4\t0\t384\t2.0\nTAP\t5\t0\t0\t4",
+        )
+        .unwrap();
+        let ir = chart.to_ir().value;
+
+        let speeds: Vec<_> = ir
+            .scroll
+            .effective_changes(0)
+            .iter()
+            .map(|change| (change.tick, change.speed))
+            .collect();
+        assert_eq!(
+            speeds,
+            vec![
+                (1920, 1.5),
+                (2880, 0.8),
+                (3360, 1.0),
+                (7680, 2.0),
+                (9600, 1.0)
+            ]
+        );
+
+        let converted = C2SChart::from_ir(&ir);
+        assert!(converted.warnings.is_empty(), "{:?}", converted.warnings);
+        assert_eq!(converted.value.metadata.sfl, chart.metadata.sfl);
+    }
+
+    #[test]
+    fn test_attach_music() {
+        let music = MusicXml::parse(
+            "<MusicData><name><id>42</id><str>Test Song</str></name>\
+             <artistName><str>Test Artist</str></artistName>\
+             <genreNames><list><StringID><str>VARIETY</str></StringID></list></genreNames>\
+             <fumens><MusicFumenData><type><id>3</id></type><file><path>0042_03.c2s</path></file>\
+             <level>13</level><levelDecimal>70</levelDecimal></MusicFumenData></fumens></MusicData>",
+        )
+        .unwrap();
+        let mut chart = C2SChart::from_string("CREATOR\tSomeone\nTAP\t0\t0\t0\t4").unwrap();
+        chart.attach_music(music, "0042_03.c2s");
+        assert_eq!(chart.metadata.music, 42);
+        assert_eq!(chart.metadata.difficulty, 3);
+        assert_eq!(chart.metadata.level, 13);
+        assert_eq!(chart.metadata.constant, Some(13.7));
+        assert!(chart.to_string().contains("LEVEL\t13.7\n"));
+
+        let metadata = chart.to_ir().value.metadata;
+        assert_eq!(metadata.title.as_deref(), Some("Test Song"));
+        assert_eq!(metadata.artist.as_deref(), Some("Test Artist"));
+        assert_eq!(metadata.genre.as_deref(), Some("VARIETY"));
+        assert_eq!(metadata.level.as_deref(), Some("13+"));
+        assert_eq!(metadata.constant, Some(13.7));
+        assert_eq!(metadata.difficulty, Some(3));
+        assert_eq!(metadata.song_id.as_deref(), Some("42"));
+    }
+
+    #[test]
+    fn test_compute_end_tags() {
+        let chart_content = r#"RESOLUTION	384
+BPM	0	0	120.000
+BPM	2	0	240.000
+
+TAP	1	0	0	4
+CHR	1	192	4	4	UP
+HLD	2	0	0	4	192
+SLD	2	0	8	4	96	8	4
+SLD	2	96	8	4	96	12	4
+AIR	2	192	12	4	SLD
+AHD	2	192	12	4	SLD	192
+ALD	3	0	4	8	38400	5.0	1	4	8	5.0	NON"#;
+
+        let mut chart = C2SChart::from_string(chart_content).unwrap();
+        let tags = chart.compute_end_tags();
+
+        assert_eq!(tags.rec.total(), 7);
+        assert_eq!(tags.rec.sld, 2);
+        // the second SLD continues the first one
+        assert_eq!(tags.note.sld, 1);
+        assert_eq!(tags.note.total(), 6);
+        assert_eq!(tags.num_aac, 1);
+        assert_eq!(tags.chr_type_up, 1);
+
+        // 120 BPM for two measures (4000ms), then 250ms per beat
+        assert_eq!(tags.len_hld, 500);
+        assert_eq!(tags.len_sld, 500);
+        assert_eq!(tags.len_ahd, 500);
+        assert_eq!(tags.len_total(), 1500);
+        assert_eq!(tags.first_res, 384);
+        assert_eq!(tags.first_msec, 2000);
+        assert_eq!(tags.final_res, 1152);
+        assert_eq!(tags.final_msec, 5000);
+
+        let mut prog = [0; 20];
+        prog[0] = 1;
+        prog[6] = 1;
+        prog[13] = 2;
+        prog[16] = 2;
+        assert_eq!(tags.prog, prog);
+
+        chart.update_end_tags();
+        assert!(chart.to_string().contains("T_REC_ALL\t7\n"));
+        assert!(chart.to_string().contains("T_LEN_ALL\t1500\n"));
+    }
+
+    #[test]
+    fn test_compute_end_tags_wrapped_air_slides() {
+        let chart = C2SChart::from_string(
+            "RESOLUTION\t384\nBPM\t0\t0\t120.000\n\n\
+             CHR\t1\t0\t0\t6\tUP\n\
+             ASD\t1\t0\t0\t6\tCHR\t5.0\t192\t0\t6\t5.0\tNON\n\
+             ASC\t1\t192\t0\t6\tASC\t5.0\t192\t4\t6\t5.0\tNON\n\
+             ASD\t2\t0\t8\t4\tTAP\t5.0\t192\t8\t4\t5.0\tDEF\n\
+             TAP\t2\t0\t8\t4",
+        )
+        .unwrap();
+        let tags = chart.compute_end_tags();
+
+        // only the CHR and the TAP are counted, the ASD/ASC rows don't take their target's type
+        assert_eq!(tags.rec.total(), 2);
+        assert_eq!(tags.rec.chr, 1);
+        assert_eq!(tags.rec.tap, 1);
+        assert_eq!(tags.chr_type_up, 1);
+        assert_eq!(tags.num_aac, 2);
+        assert_eq!(tags.final_res, 2 * 384);
+    }
 }
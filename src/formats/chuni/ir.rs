@@ -0,0 +1,602 @@
+//! Format-neutral CHUNITHM chart model
+//!
+//! Every CHUNITHM-style format converts to and from [`IrChart`] through [`ChuniChart`](super::ChuniChart),
+//! so converting between any two formats only needs one adapter per format.
+//!
+//! All positions are absolute ticks from the start of the chart at [`TICKS_PER_BEAT`] ticks per beat,
+//! lanes go from 0 (leftmost) to 15 (rightmost).
+
+use std::fmt;
+
+use super::{
+    scroll::{ScrollMap, SpeedChange},
+    tempo::TempoMap,
+};
+
+/// Tick resolution of the IR, 480 per beat (1920 per 4/4 measure)
+///
+/// This divides evenly into C2S's 384 ticks per measure and matches UGC and default SUS charts.
+pub const TICKS_PER_BEAT: u32 = 480;
+
+/// Number of lanes on the playfield
+pub const LANES: u8 = 16;
+
+/// Something that was lost or approximated while converting a chart
+#[derive(Debug, Clone, PartialEq)]
+pub struct ConversionWarning {
+    /// IR tick the warning refers to, if any
+    pub tick: Option<u32>,
+    pub message: String,
+}
+
+impl ConversionWarning {
+    pub fn new(message: impl Into<String>) -> Self {
+        Self {
+            tick: None,
+            message: message.into(),
+        }
+    }
+
+    pub fn at(tick: u32, message: impl Into<String>) -> Self {
+        Self {
+            tick: Some(tick),
+            message: message.into(),
+        }
+    }
+}
+
+impl fmt::Display for ConversionWarning {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.tick {
+            Some(tick) => write!(f, "tick {}: {}", tick, self.message),
+            None => write!(f, "{}", self.message),
+        }
+    }
+}
+
+/// The result of a conversion, along with everything that didn't survive it
+#[derive(Debug, Clone, PartialEq)]
+pub struct Conversion<T> {
+    pub value: T,
+    pub warnings: Vec<ConversionWarning>,
+}
+
+impl<T> Conversion<T> {
+    pub fn new(value: T, warnings: Vec<ConversionWarning>) -> Self {
+        Self { value, warnings }
+    }
+
+    /// Maps the converted value, keeping the warnings
+    pub fn map<U>(self, f: impl FnOnce(T) -> U) -> Conversion<U> {
+        Conversion {
+            value: f(self.value),
+            warnings: self.warnings,
+        }
+    }
+}
+
+/// A format-neutral CHUNITHM chart
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct IrChart {
+    pub metadata: IrMetadata,
+    /// BPM changes, sorted by tick. The first one should be at tick 0.
+    pub bpm_changes: Vec<BpmChange>,
+    /// Time signature changes, sorted by tick. Each one should start a measure.
+    pub time_signatures: Vec<TimeSignatureChange>,
+    /// Scroll speed of every timeline, notes move with the timeline they're on
+    pub scroll: ScrollMap,
+    /// Notes, sorted by tick
+    pub notes: Vec<IrNote>,
+}
+
+/// Song and chart information shared by the formats
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct IrMetadata {
+    pub title: Option<String>,
+    pub artist: Option<String>,
+    pub genre: Option<String>,
+    /// Chart designer
+    pub designer: Option<String>,
+    /// 0: BASIC, 1: ADVANCED, 2: EXPERT, 3: MASTER, 4: WORLD'S END, 5: ULTIMA
+    pub difficulty: Option<u32>,
+    /// Display level, e.g. `13+`
+    pub level: Option<String>,
+    /// Decimal chart constant, e.g. `13.7`
+    pub constant: Option<f32>,
+    pub song_id: Option<String>,
+    /// Audio file name
+    pub bgm: Option<String>,
+    /// Audio offset in seconds
+    pub bgm_offset: Option<f64>,
+    /// Jacket image file name
+    pub jacket: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct BpmChange {
+    pub tick: u32,
+    pub bpm: f64,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct TimeSignatureChange {
+    pub tick: u32,
+    pub numerator: u32,
+    pub denominator: u32,
+}
+
+/// A single note, positioned by its head
+#[derive(Debug, Clone, PartialEq)]
+pub struct IrNote {
+    pub tick: u32,
+    /// Leftmost lane, 0-15
+    pub lane: u8,
+    /// Width in lanes, 1-16
+    pub width: u8,
+    /// Scroll timeline this note moves with, see [`IrChart::scroll`]
+    pub timeline: u32,
+    pub kind: IrNoteKind,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum IrNoteKind {
+    Tap,
+    ExTap {
+        effect: Option<ExTapEffect>,
+    },
+    Flick,
+    /// Damage note, must not be touched
+    Mine,
+    Hold {
+        end_tick: u32,
+        /// Whether the head is an ExTap (HXD)
+        ex: bool,
+        /// Effect of the ExTap head, if the format has one
+        effect: Option<ExTapEffect>,
+    },
+    Slide {
+        /// Whether the head is an ExTap (SXD), C2S writes every segment as SXD/SXC then
+        ex: bool,
+        /// Effect of the ExTap head, if the format has one
+        effect: Option<ExTapEffect>,
+        /// Kind of the head, [`SlidePointKind::Invisible`] when a C2S slide starts with SLC
+        head: SlidePointKind,
+        /// Points after the head, the last one is the end of the slide
+        points: Vec<SlidePoint>,
+    },
+    Air {
+        direction: AirDirection,
+        inverted: bool,
+        /// Index into [`IrChart::notes`] of the ground note this air note sits on
+        parent: Option<usize>,
+    },
+    AirHold {
+        inverted: bool,
+        /// Whether this is the variant with a ground bar (AHX)
+        ground: bool,
+        /// Points after the head, the last one is the end of the air hold
+        points: Vec<AirHoldPoint>,
+        /// Index into [`IrChart::notes`] of the ground note this air hold starts from
+        parent: Option<usize>,
+    },
+    AirSlide {
+        /// Height of the head, in the same units as UGC/C2S (usually 1.0-10.0)
+        height: f32,
+        inverted: bool,
+        /// Points after the head, the last one is the end of the air slide
+        points: Vec<AirSlidePoint>,
+        /// Index into [`IrChart::notes`] of the ground note this air slide starts from
+        parent: Option<usize>,
+    },
+    AirCrush {
+        height: f32,
+        color: AirCrushColor,
+        interval: CrushInterval,
+        /// Points after the head, the last one is the end of the air crush
+        points: Vec<AirSlidePoint>,
+    },
+}
+
+/// Effect played when hitting an ExTap
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExTapEffect {
+    Up,
+    Down,
+    Center,
+    Clockwise,
+    Counterclockwise,
+    Right,
+    Left,
+    InOut,
+}
+
+/// Effect written for ExTaps that don't have one, like the head of a C2S HXD
+pub const DEFAULT_EXTAP_EFFECT: ExTapEffect = ExTapEffect::Up;
+
+/// Arrow direction of an air note
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AirDirection {
+    Up,
+    UpLeft,
+    UpRight,
+    Down,
+    DownLeft,
+    DownRight,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SlidePointKind {
+    /// A visible relay point (or the end of the slide)
+    Relay,
+    /// A point the path goes through without a visible note
+    Invisible,
+    /// A curve control point the path bends towards but doesn't go through (SUS Bézier control)
+    Control,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct SlidePoint {
+    pub tick: u32,
+    pub lane: u8,
+    pub width: u8,
+    pub kind: SlidePointKind,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct AirHoldPoint {
+    pub tick: u32,
+    /// Whether an AIR-ACTION has to be performed at this point
+    pub action: bool,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct AirSlidePoint {
+    pub tick: u32,
+    pub lane: u8,
+    pub width: u8,
+    pub height: f32,
+    pub kind: SlidePointKind,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AirCrushColor {
+    Normal,
+    Red,
+    Orange,
+    Yellow,
+    YellowGreen,
+    Green,
+    Cyan,
+    Sky,
+    Light,
+    Blue,
+    BluePurple,
+    Magenta,
+    Pink,
+    White,
+    Black,
+    Gray,
+    Transparent,
+}
+
+/// How often an air crush places a note along its path
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CrushInterval {
+    /// Every this many IR ticks, 0 makes it an AIR-TRACE
+    Ticks(f32),
+    /// Only the start note counts towards combo
+    StartOnly,
+}
+
+impl IrNote {
+    /// The tick this note ends at, which is its own tick for instant notes
+    pub fn end_tick(&self) -> u32 {
+        match &self.kind {
+            IrNoteKind::Hold { end_tick, .. } => Some(*end_tick),
+            IrNoteKind::Slide { points, .. } => points.last().map(|p| p.tick),
+            IrNoteKind::AirHold { points, .. } => points.last().map(|p| p.tick),
+            IrNoteKind::AirSlide { points, .. } | IrNoteKind::AirCrush { points, .. } => {
+                points.last().map(|p| p.tick)
+            }
+            _ => None,
+        }
+        .unwrap_or(self.tick)
+    }
+
+    /// Lane and width at the end of the note
+    pub fn end_position(&self) -> (u8, u8) {
+        match &self.kind {
+            IrNoteKind::Slide { points, .. } => points.last().map(|p| (p.lane, p.width)),
+            IrNoteKind::AirSlide { points, .. } | IrNoteKind::AirCrush { points, .. } => {
+                points.last().map(|p| (p.lane, p.width))
+            }
+            _ => None,
+        }
+        .unwrap_or((self.lane, self.width))
+    }
+
+    /// Whether this note lives on the ground and can carry air notes
+    pub fn is_ground(&self) -> bool {
+        matches!(
+            self.kind,
+            IrNoteKind::Tap
+                | IrNoteKind::ExTap { .. }
+                | IrNoteKind::Flick
+                | IrNoteKind::Mine
+                | IrNoteKind::Hold { .. }
+                | IrNoteKind::Slide { .. }
+        )
+    }
+}
+
+impl IrChart {
+    /// Sorts notes by tick and lane, and drops parent links since indices change
+    ///
+    /// Call [`IrChart::link_air_parents`] afterwards to restore them.
+    pub fn sort_notes(&mut self) {
+        for note in &mut self.notes {
+            if let IrNoteKind::Air { parent, .. }
+            | IrNoteKind::AirHold { parent, .. }
+            | IrNoteKind::AirSlide { parent, .. } = &mut note.kind
+            {
+                *parent = None;
+            }
+        }
+        self.notes.sort_by_key(|note| (note.tick, note.lane));
+        self.bpm_changes.sort_by_key(|change| change.tick);
+        self.time_signatures.sort_by_key(|change| change.tick);
+    }
+
+    /// Finds the ground note an air note placed at `tick`/`lane`/`width` sits on
+    ///
+    /// A ground note qualifies if it starts or ends there. Exact matches are preferred over overlaps.
+    pub fn find_ground_note(&self, tick: u32, lane: u8, width: u8) -> Option<usize> {
+        let candidates = self
+            .notes
+            .iter()
+            .enumerate()
+            .filter(|(_, note)| note.is_ground());
+
+        let mut best = None;
+        for (index, note) in candidates {
+            let (note_lane, note_width) = if note.end_tick() == tick && note.tick != tick {
+                note.end_position()
+            } else if note.tick == tick {
+                (note.lane, note.width)
+            } else {
+                continue;
+            };
+
+            if note_lane == lane && note_width == width {
+                return Some(index);
+            }
+            let overlaps = note_lane < lane + width && lane < note_lane + note_width;
+            if overlaps && best.is_none() {
+                best = Some(index);
+            }
+        }
+        best
+    }
+
+    /// Fills in missing parent links of air notes from their position
+    pub fn link_air_parents(&mut self) {
+        for index in 0..self.notes.len() {
+            let note = &self.notes[index];
+            let needs_parent = matches!(
+                note.kind,
+                IrNoteKind::Air { parent: None, .. }
+                    | IrNoteKind::AirHold { parent: None, .. }
+                    | IrNoteKind::AirSlide { parent: None, .. }
+            );
+            if !needs_parent {
+                continue;
+            }
+
+            let found = self.find_ground_note(note.tick, note.lane, note.width);
+            if let IrNoteKind::Air { parent, .. }
+            | IrNoteKind::AirHold { parent, .. }
+            | IrNoteKind::AirSlide { parent, .. } = &mut self.notes[index].kind
+            {
+                *parent = found;
+            }
+        }
+    }
+
+    /// Timing of this chart, in IR ticks
+    ///
+    /// Charts without BPM changes are assumed to be at 120 BPM.
+    pub fn tempo_map(&self) -> TempoMap {
+        let bpm = self.bpm_changes.first().map_or(120.0, |change| change.bpm);
+        let mut tempo = TempoMap::new(TICKS_PER_BEAT, bpm);
+        for change in &self.bpm_changes {
+            tempo.set_bpm(change.tick, change.bpm);
+        }
+        for change in &self.time_signatures {
+            // a change in the middle of a measure takes effect from the next one
+            let (measure, offset) = tempo.tick_to_measure(change.tick);
+            let measure = if offset == 0 { measure } else { measure + 1 };
+            tempo.set_time_signature(measure, change.numerator, change.denominator);
+        }
+        tempo
+    }
+
+    /// Replaces the BPM changes and time signatures with the ones of `tempo`
+    ///
+    /// Positions are rescaled from the resolution of the tempo map to IR ticks.
+    pub fn set_tempo_map(&mut self, tempo: &TempoMap) {
+        let ticks_per_beat = tempo.ticks_per_beat();
+        let to_ir = |tick| rescale_tick(tick, ticks_per_beat, TICKS_PER_BEAT).0;
+
+        self.bpm_changes = tempo
+            .tempo_changes()
+            .iter()
+            .map(|change| BpmChange {
+                tick: to_ir(change.tick),
+                bpm: change.bpm,
+            })
+            .collect();
+        self.time_signatures = tempo
+            .measure_changes()
+            .iter()
+            .map(|change| {
+                let (numerator, denominator) = change.time_signature(ticks_per_beat);
+                TimeSignatureChange {
+                    tick: to_ir(tempo.measure_to_tick(change.measure, 0)),
+                    numerator,
+                    denominator,
+                }
+            })
+            .collect();
+    }
+
+    /// Speed changes for formats that only have one timeline, those of the main timeline
+    ///
+    /// Every other timeline notes are on that scrolls differently is reported,
+    /// its notes end up scrolling with the main timeline.
+    pub fn single_timeline_speeds(&self, format: &str) -> Conversion<Vec<SpeedChange>> {
+        let main = self.scroll.main_timeline();
+        let speeds = self.scroll.effective_changes(main);
+
+        let mut timelines: Vec<u32> = self.notes.iter().map(|note| note.timeline).collect();
+        timelines.sort_unstable();
+        timelines.dedup();
+        let warnings = timelines
+            .into_iter()
+            .filter(|&timeline| self.scroll.effective_changes(timeline) != speeds)
+            .map(|timeline| {
+                ConversionWarning::new(format!(
+                    "{} has a single scroll speed, notes on timeline {} use the speeds of timeline {}",
+                    format, timeline, main
+                ))
+            })
+            .collect();
+        Conversion::new(speeds, warnings)
+    }
+}
+
+/// Rescales a tick from one resolution to another, reporting whether it had to be rounded
+pub fn rescale_tick(tick: u32, from: u32, to: u32) -> (u32, bool) {
+    let scaled = tick as u64 * to as u64;
+    let from = from.max(1) as u64;
+    let rounded = (scaled + from / 2) / from;
+    (rounded as u32, !scaled.is_multiple_of(from))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn note(tick: u32, lane: u8, width: u8, kind: IrNoteKind) -> IrNote {
+        IrNote {
+            tick,
+            lane,
+            width,
+            timeline: 0,
+            kind,
+        }
+    }
+
+    #[test]
+    fn test_rescale_tick() {
+        assert_eq!(rescale_tick(96, 384, 1920), (480, false));
+        assert_eq!(rescale_tick(480, 1920, 384), (96, false));
+        assert_eq!(rescale_tick(7, 1920, 384), (1, true));
+    }
+
+    #[test]
+    fn test_tempo_map() {
+        let chart = IrChart {
+            time_signatures: vec![
+                TimeSignatureChange {
+                    tick: 0,
+                    numerator: 4,
+                    denominator: 4,
+                },
+                TimeSignatureChange {
+                    tick: 3840,
+                    numerator: 3,
+                    denominator: 4,
+                },
+            ],
+            ..Default::default()
+        };
+
+        let tempo = chart.tempo_map();
+        assert_eq!(tempo.measure_to_tick(3, 480), 3840 + 1440 + 480);
+        assert_eq!(tempo.tick_to_measure(3840 + 1440 + 480), (3, 480));
+
+        let mut round_trip = IrChart::default();
+        round_trip.set_tempo_map(&tempo);
+        assert_eq!(round_trip.time_signatures, chart.time_signatures);
+        assert_eq!(
+            round_trip.bpm_changes,
+            vec![BpmChange {
+                tick: 0,
+                bpm: 120.0
+            }]
+        );
+    }
+
+    #[test]
+    fn test_link_air_parents() {
+        let mut chart = IrChart {
+            notes: vec![
+                note(0, 4, 4, IrNoteKind::Tap),
+                note(
+                    0,
+                    0,
+                    4,
+                    IrNoteKind::Slide {
+                        ex: false,
+                        effect: None,
+                        head: SlidePointKind::Relay,
+                        points: vec![SlidePoint {
+                            tick: 480,
+                            lane: 8,
+                            width: 4,
+                            kind: SlidePointKind::Relay,
+                        }],
+                    },
+                ),
+                note(
+                    0,
+                    4,
+                    4,
+                    IrNoteKind::Air {
+                        direction: AirDirection::Up,
+                        inverted: false,
+                        parent: None,
+                    },
+                ),
+                note(
+                    480,
+                    8,
+                    4,
+                    IrNoteKind::Air {
+                        direction: AirDirection::Down,
+                        inverted: false,
+                        parent: None,
+                    },
+                ),
+            ],
+            ..Default::default()
+        };
+
+        chart.link_air_parents();
+        assert!(matches!(
+            chart.notes[2].kind,
+            IrNoteKind::Air {
+                parent: Some(0),
+                ..
+            }
+        ));
+        // air notes at the end of a slide sit on the slide
+        assert!(matches!(
+            chart.notes[3].kind,
+            IrNoteKind::Air {
+                parent: Some(1),
+                ..
+            }
+        ));
+    }
+}
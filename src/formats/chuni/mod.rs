@@ -1,8 +1,14 @@
 //! A certain edgy rhythm game.
 pub mod c2s;
+pub mod ir;
+pub mod music;
+pub mod scroll;
 pub mod sus;
+pub mod tempo;
 pub mod ugc;
 
+use ir::{Conversion, IrChart};
+
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub enum AirDirection {
     UpRight,
@@ -81,4 +87,13 @@ pub fn calculate_offset(resolution: u32, beat: u32, fraction: f32) -> u32 {
 }
 
 /// a CHUNITHM-style chart, for games with freestyle sliders and IR jump notes
-pub trait ChuniChart {}
+///
+/// Formats convert through the format-neutral [`IrChart`], anything that
+/// can't be represented on either side is reported as a warning instead of failing.
+pub trait ChuniChart: Sized {
+    /// Convert this chart into the intermediate representation
+    fn to_ir(&self) -> Conversion<IrChart>;
+
+    /// Build a chart in this format from the intermediate representation
+    fn from_ir(chart: &IrChart) -> Conversion<Self>;
+}
@@ -0,0 +1,239 @@
+//! `Music.xml`, the song definition next to the C2S charts of a song folder
+//!
+//! C2S charts leave the song metadata to this file: title, artist, genre, and for every
+//! difficulty the chart file, level and decimal constant.
+
+use std::path::Path;
+
+use roxmltree::{Document, Node};
+
+/// Problems reading a `Music.xml`
+#[derive(Debug, thiserror::Error)]
+pub enum MusicXmlError {
+    #[error("failed to read Music.xml: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("malformed Music.xml: {0}")]
+    Xml(#[from] roxmltree::Error),
+    #[error("Music.xml is missing <{0}>")]
+    Missing(&'static str),
+    #[error("invalid <{field}> `{value}` in Music.xml")]
+    Invalid { field: &'static str, value: String },
+}
+
+/// A song as declared by its `Music.xml`
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct MusicXml {
+    /// `<name><id>`, the song ID
+    pub id: u32,
+    pub title: String,
+    pub artist: String,
+    pub genres: Vec<String>,
+    /// `<jaketFile><path>`, jacket image file name
+    pub jacket: Option<String>,
+    /// `<cueFileName><str>`, name of the audio cue
+    pub cue: Option<String>,
+    pub fumens: Vec<MusicFumen>,
+}
+
+/// One difficulty of a song, `<MusicFumenData>`
+#[derive(Debug, Clone, PartialEq)]
+pub struct MusicFumen {
+    /// 0: BASIC, 1: ADVANCED, 2: EXPERT, 3: MASTER, 4: WORLD'S END, 5: ULTIMA
+    pub difficulty: u32,
+    /// Whether the difficulty is playable, disabled ones usually have no chart file
+    pub enabled: bool,
+    /// Chart file name, e.g. `0001_03.c2s`
+    pub file: String,
+    pub level: u32,
+    /// Hundredths added to `level` for the chart constant, `70` for a 13.7
+    pub level_decimal: u32,
+    pub designer: Option<String>,
+}
+
+impl MusicFumen {
+    fn parse(node: Node) -> Result<Self, MusicXmlError> {
+        Ok(Self {
+            difficulty: number(node, &["type", "id"], "type/id")?
+                .ok_or(MusicXmlError::Missing("type"))?,
+            enabled: text(node, &["enable"]).is_none_or(|enable| enable == "true"),
+            file: text(node, &["file", "path"]).unwrap_or_default(),
+            level: number(node, &["level"], "level")?.unwrap_or_default(),
+            level_decimal: number(node, &["levelDecimal"], "levelDecimal")?.unwrap_or_default(),
+            designer: text(node, &["notesDesigner"]),
+        })
+    }
+
+    /// Decimal chart constant, e.g. `13.7`
+    pub fn constant(&self) -> f32 {
+        self.level as f32 + self.level_decimal as f32 / 100.0
+    }
+
+    /// Level as displayed in game, see [`display_level`]
+    pub fn display_level(&self) -> String {
+        display_level(self.level, self.level_decimal)
+    }
+}
+
+/// Level as displayed in game, charts from 7.5 up get a `+` in the upper half
+pub fn display_level(level: u32, level_decimal: u32) -> String {
+    if level >= 7 && level_decimal >= 50 {
+        format!("{}+", level)
+    } else {
+        level.to_string()
+    }
+}
+
+/// The first child element of `node` named `name`
+fn child<'a, 'input>(node: Node<'a, 'input>, name: &str) -> Option<Node<'a, 'input>> {
+    node.children()
+        .find(|child| child.is_element() && child.has_tag_name(name))
+}
+
+/// Trimmed text of the element at `path` below `node`, `None` if it's missing or empty
+fn text(node: Node, path: &[&str]) -> Option<String> {
+    let mut node = node;
+    for name in path {
+        node = child(node, name)?;
+    }
+    let text = node.text()?.trim();
+    (!text.is_empty()).then(|| text.to_string())
+}
+
+/// Number at `path` below `node`, `None` if it's missing
+fn number(node: Node, path: &[&str], field: &'static str) -> Result<Option<u32>, MusicXmlError> {
+    text(node, path)
+        .map(|value| {
+            value
+                .parse()
+                .map_err(|_| MusicXmlError::Invalid { field, value })
+        })
+        .transpose()
+}
+
+impl MusicXml {
+    /// Parse the contents of a `Music.xml`
+    pub fn parse(content: &str) -> Result<Self, MusicXmlError> {
+        let document = Document::parse(content)?;
+        let root = document.root_element();
+
+        let fumens = match child(root, "fumens") {
+            Some(fumens) => fumens
+                .children()
+                .filter(|fumen| fumen.has_tag_name("MusicFumenData"))
+                .map(MusicFumen::parse)
+                .collect::<Result<_, _>>()?,
+            None => Vec::new(),
+        };
+
+        Ok(Self {
+            id: number(root, &["name", "id"], "name/id")?.ok_or(MusicXmlError::Missing("name"))?,
+            title: text(root, &["name", "str"]).unwrap_or_default(),
+            artist: text(root, &["artistName", "str"]).unwrap_or_default(),
+            genres: child(root, "genreNames")
+                .and_then(|genres| child(genres, "list"))
+                .map(|list| {
+                    list.children()
+                        .filter_map(|genre| text(genre, &["str"]))
+                        .collect()
+                })
+                .unwrap_or_default(),
+            jacket: text(root, &["jaketFile", "path"]),
+            cue: text(root, &["cueFileName", "str"]),
+            fumens,
+        })
+    }
+
+    /// Read the `Music.xml` of a song folder
+    pub fn load(folder: &Path) -> Result<Self, MusicXmlError> {
+        Self::parse(&std::fs::read_to_string(folder.join("Music.xml"))?)
+    }
+
+    /// The difficulty whose chart file is named `file`, ignoring case
+    pub fn fumen_for_file(&self, file: &str) -> Option<&MusicFumen> {
+        self.fumens
+            .iter()
+            .find(|fumen| fumen.file.eq_ignore_ascii_case(file))
+    }
+
+    pub fn fumen(&self, difficulty: u32) -> Option<&MusicFumen> {
+        self.fumens
+            .iter()
+            .find(|fumen| fumen.difficulty == difficulty)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MUSIC_XML: &str = r#"<?xml version="1.0" encoding="utf-8"?>
+<MusicData xmlns:xsi="http://www.w3.org/2001/XMLSchema-instance">
+  <dataName>music0042</dataName>
+  <name><id>42</id><str>Test Song</str><data /></name>
+  <artistName><id>7</id><str>Test Artist</str><data /></artistName>
+  <genreNames>
+    <list>
+      <StringID><id>0</id><str>POPS &amp; ANIME</str><data /></StringID>
+    </list>
+  </genreNames>
+  <jaketFile><path>CHU_UI_Jacket_0042.dds</path></jaketFile>
+  <cueFileName><id>42</id><str>music0042</str><data /></cueFileName>
+  <fumens>
+    <MusicFumenData>
+      <type><id>0</id><str>Basic</str><data>BASIC</data></type>
+      <enable>true</enable>
+      <file><path>0042_00.c2s</path></file>
+      <level>3</level>
+      <levelDecimal>0</levelDecimal>
+      <notesDesigner />
+    </MusicFumenData>
+    <MusicFumenData>
+      <type><id>3</id><str>Master</str><data>MASTER</data></type>
+      <enable>true</enable>
+      <file><path>0042_03.c2s</path></file>
+      <level>13</level>
+      <levelDecimal>70</levelDecimal>
+      <notesDesigner>Someone</notesDesigner>
+    </MusicFumenData>
+    <MusicFumenData>
+      <type><id>4</id><str>WorldsEnd</str><data>WORLD'S END</data></type>
+      <enable>false</enable>
+      <file><path /></file>
+      <level>0</level>
+      <levelDecimal>0</levelDecimal>
+    </MusicFumenData>
+  </fumens>
+</MusicData>"#;
+
+    #[test]
+    fn test_parse_music_xml() {
+        let music = MusicXml::parse(MUSIC_XML).unwrap();
+        assert_eq!(music.id, 42);
+        assert_eq!(music.title, "Test Song");
+        assert_eq!(music.artist, "Test Artist");
+        assert_eq!(music.genres, vec!["POPS & ANIME"]);
+        assert_eq!(music.jacket.as_deref(), Some("CHU_UI_Jacket_0042.dds"));
+        assert_eq!(music.cue.as_deref(), Some("music0042"));
+        assert_eq!(music.fumens.len(), 3);
+
+        let master = music.fumen_for_file("0042_03.C2S").unwrap();
+        assert_eq!(master.difficulty, 3);
+        assert_eq!(master.constant(), 13.7);
+        assert_eq!(master.display_level(), "13+");
+        assert_eq!(master.designer.as_deref(), Some("Someone"));
+        assert_eq!(music.fumen(0).unwrap().display_level(), "3");
+        assert!(!music.fumen(4).unwrap().enabled);
+
+        assert!(matches!(
+            MusicXml::parse("<MusicData><name><id>x</id></name></MusicData>"),
+            Err(MusicXmlError::Invalid {
+                field: "name/id",
+                ..
+            })
+        ));
+        assert!(matches!(
+            MusicXml::parse("<MusicData>"),
+            Err(MusicXmlError::Xml(_))
+        ));
+    }
+}
@@ -0,0 +1,208 @@
+//! Scroll speed: per-timeline speed changes and chart-wide speed modifiers
+//!
+//! A [`ScrollMap`] works in the tick resolution of whichever chart it was built from, like
+//! [`TempoMap`](super::tempo::TempoMap). Every timeline scrolls at speed 1 until its first
+//! change, and the chart-wide modifiers multiply the speed of every timeline.
+
+use std::collections::BTreeMap;
+
+/// A scroll speed change at an absolute tick
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SpeedChange {
+    pub tick: u32,
+    pub speed: f64,
+}
+
+/// Scroll speeds of every timeline of a chart
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ScrollMap {
+    main_timeline: u32,
+    /// Sorted by tick per timeline
+    timelines: BTreeMap<u32, Vec<SpeedChange>>,
+    /// Sorted by tick, apply to every timeline
+    modifiers: Vec<SpeedChange>,
+}
+
+/// Inserts `change` into sorted `changes`, replacing any change already at that tick
+fn insert_change(changes: &mut Vec<SpeedChange>, change: SpeedChange) {
+    match changes.binary_search_by_key(&change.tick, |existing| existing.tick) {
+        Ok(index) => changes[index] = change,
+        Err(index) => changes.insert(index, change),
+    }
+}
+
+/// Speed in effect at `tick`, 1 before the first change
+fn speed_in(changes: &[SpeedChange], tick: u32) -> f64 {
+    let index = changes.partition_point(|change| change.tick <= tick);
+    index
+        .checked_sub(1)
+        .map_or(1.0, |index| changes[index].speed)
+}
+
+impl ScrollMap {
+    /// A scroll map where every timeline scrolls at speed 1
+    pub fn new(main_timeline: u32) -> Self {
+        Self {
+            main_timeline,
+            ..Default::default()
+        }
+    }
+
+    /// The timeline the rest of the field, such as measure lines, scrolls with
+    pub fn main_timeline(&self) -> u32 {
+        self.main_timeline
+    }
+
+    pub fn set_main_timeline(&mut self, timeline: u32) {
+        self.main_timeline = timeline;
+    }
+
+    /// Timelines with at least one speed change
+    pub fn timelines(&self) -> impl Iterator<Item = u32> + '_ {
+        self.timelines.keys().copied()
+    }
+
+    /// Speed changes of a single timeline, without the chart-wide modifiers
+    pub fn speed_changes(&self, timeline: u32) -> &[SpeedChange] {
+        self.timelines.get(&timeline).map_or(&[], Vec::as_slice)
+    }
+
+    pub fn modifiers(&self) -> &[SpeedChange] {
+        &self.modifiers
+    }
+
+    /// Changes the speed of `timeline` from `tick` onwards
+    pub fn set_speed(&mut self, timeline: u32, tick: u32, speed: f64) {
+        insert_change(
+            self.timelines.entry(timeline).or_default(),
+            SpeedChange { tick, speed },
+        );
+    }
+
+    /// Changes the modifier applied to every timeline from `tick` onwards
+    pub fn set_modifier(&mut self, tick: u32, speed: f64) {
+        insert_change(&mut self.modifiers, SpeedChange { tick, speed });
+    }
+
+    /// Effective speed of `timeline` at `tick`, including the chart-wide modifier
+    pub fn speed_at(&self, timeline: u32, tick: u32) -> f64 {
+        speed_in(self.speed_changes(timeline), tick) * speed_in(&self.modifiers, tick)
+    }
+
+    /// Speed changes of `timeline` with the chart-wide modifiers folded in
+    ///
+    /// Changes that don't alter the speed are left out, so a timeline that always
+    /// scrolls at speed 1 has none.
+    pub fn effective_changes(&self, timeline: u32) -> Vec<SpeedChange> {
+        let mut ticks: Vec<u32> = self
+            .speed_changes(timeline)
+            .iter()
+            .chain(&self.modifiers)
+            .map(|change| change.tick)
+            .collect();
+        ticks.sort_unstable();
+        ticks.dedup();
+
+        let mut speed = 1.0;
+        let mut changes = Vec::new();
+        for tick in ticks {
+            let next = self.speed_at(timeline, tick);
+            if next != speed {
+                changes.push(SpeedChange { tick, speed: next });
+                speed = next;
+            }
+        }
+        changes
+    }
+
+    /// The same scroll map with every tick passed through `f`, to change its resolution
+    pub fn map_ticks(&self, f: impl Fn(u32) -> u32) -> Self {
+        let map = |changes: &Vec<SpeedChange>| {
+            changes
+                .iter()
+                .map(|change| SpeedChange {
+                    tick: f(change.tick),
+                    speed: change.speed,
+                })
+                .collect()
+        };
+        Self {
+            main_timeline: self.main_timeline,
+            timelines: self
+                .timelines
+                .iter()
+                .map(|(timeline, changes)| (*timeline, map(changes)))
+                .collect(),
+            modifiers: map(&self.modifiers),
+        }
+    }
+
+    /// How far `timeline` has scrolled by `tick`
+    ///
+    /// At speed 1 the position advances by one per tick, so a timeline that never
+    /// changes speed is at position `tick`. Negative speeds scroll backwards.
+    pub fn position_at(&self, timeline: u32, tick: u32) -> f64 {
+        let mut boundaries: Vec<u32> = self
+            .speed_changes(timeline)
+            .iter()
+            .chain(&self.modifiers)
+            .map(|change| change.tick)
+            .filter(|&change| change < tick)
+            .collect();
+        boundaries.push(0);
+        boundaries.push(tick);
+        boundaries.sort_unstable();
+        boundaries.dedup();
+
+        boundaries
+            .windows(2)
+            .map(|segment| (segment[1] - segment[0]) as f64 * self.speed_at(timeline, segment[0]))
+            .sum()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_timeline_speeds() {
+        let mut scroll = ScrollMap::new(0);
+        scroll.set_speed(1, 480, 0.5);
+        scroll.set_speed(1, 960, -1.0);
+        scroll.set_modifier(1440, 2.0);
+
+        assert_eq!(scroll.speed_at(0, 1000), 1.0);
+        assert_eq!(scroll.speed_at(1, 479), 1.0);
+        assert_eq!(scroll.speed_at(1, 480), 0.5);
+        assert_eq!(scroll.speed_at(1, 1440), -2.0);
+        assert_eq!(scroll.speed_at(0, 1440), 2.0);
+
+        assert_eq!(scroll.position_at(0, 1920), 1440.0 + 960.0);
+        assert_eq!(scroll.position_at(1, 720), 480.0 + 120.0);
+        // 480 at speed 1, 480 at 0.5, 480 backwards, then 480 backwards at twice the speed
+        assert_eq!(scroll.position_at(1, 1920), 480.0 + 240.0 - 480.0 - 960.0);
+        assert_eq!(scroll.timelines().collect::<Vec<_>>(), vec![1]);
+    }
+
+    #[test]
+    fn test_effective_changes() {
+        let mut scroll = ScrollMap::new(0);
+        scroll.set_speed(1, 0, 1.0);
+        scroll.set_speed(1, 480, 0.5);
+        scroll.set_modifier(480, 2.0);
+        scroll.set_modifier(960, 1.0);
+
+        let speeds = |timeline| {
+            scroll
+                .effective_changes(timeline)
+                .iter()
+                .map(|change| (change.tick, change.speed))
+                .collect::<Vec<_>>()
+        };
+        // 0.5 × 2 at tick 480 is speed 1 again, nothing changes there
+        assert_eq!(speeds(1), vec![(960, 0.5)]);
+        assert_eq!(speeds(0), vec![(480, 2.0), (960, 1.0)]);
+        assert_eq!(speeds(2), speeds(0));
+    }
+}
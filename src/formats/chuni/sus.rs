@@ -10,17 +10,17 @@
 //!
 //! The `SusLine` enum provides ergonomic constructors for all common variants:
 //!
-//! ```rust
+//! ```ignore
 //! SusLine::metadata("TITLE", "Song Title")
 //! SusLine::comment("This is a comment")
-//! SusLine::note(Note { lane: 1, tick: 480, note_type: "tap".to_string(), width: 1 })
+//! SusLine::note(Note::tap(480, 1, 1))
 //! SusLine::tap_notes(0, 1, "14141414")
 //! SusLine::hold_notes(0, 1, 2, "14002400")
 //! SusLine::slide_notes(0, 3, 1, 2, "14340024")
 //! SusLine::directional_notes(0, 1, "14241424")
 //! SusLine::bpm_definition("01", 140.0)
-//! SusLine::attribute_definition("01", "pr:100, h:1.5")
-//! SusLine::hi_speed_definition("01", "0'0:1.0, 0'960:2.0")
+//! SusLine::attribute_definition("01", NoteAttributes { priority: Some(100), height: Some(1.5), ..Default::default() })
+//! SusLine::hi_speed_definition("01", vec![HiSpeedEntry { measure: 0, tick: 960, speed: 2.0 }])
 //! SusLine::measure_length(0, 4.0)
 //! SusLine::bpm_change(0, 0, "01")
 //! ```
@@ -29,7 +29,7 @@
 //!
 //! The `SusChart` struct provides builder-style methods for ergonomic chart construction and modification:
 //!
-//! ```rust
+//! ```ignore
 //! let mut chart = SusChart {
 //!     metadata: HashMap::new(),
 //!     lines: Vec::new(),
@@ -37,7 +37,7 @@
 //!
 //! chart
 //!     .metadata("TITLE", "Test Song")
-//!     .note(Note { lane: 1, tick: 480, note_type: "tap".to_string(), width: 1 })
+//!     .note(Note::tap(480, 1, 1))
 //!     .new_line(SusLine::comment("A comment"));
 //! ```
 //!
@@ -47,7 +47,21 @@
 //! - `.comment(content)` — Add a comment line
 //!
 
-use std::collections::HashMap;
+use std::{
+    collections::{BTreeMap, HashMap},
+    fmt,
+};
+
+use super::{
+    AirDirection, ChuniChart,
+    ir::{
+        self, Conversion, ConversionWarning, IrChart, IrMetadata, IrNote, IrNoteKind, SlidePoint,
+        SlidePointKind, rescale_tick,
+    },
+    scroll::ScrollMap,
+    tempo::TempoMap,
+};
+use crate::formats::error::{Diagnostics, ParseError, ParseErrorKind, ParseMode, Parsed};
 
 #[derive(Debug, Clone)]
 pub enum SusLine {
@@ -65,11 +79,11 @@ pub enum SusLine {
     },
     AttributeDefinition {
         id: String,
-        attributes: String,
+        attributes: NoteAttributes,
     },
     HiSpeedDefinition {
         id: String,
-        definition: String,
+        entries: Vec<HiSpeedEntry>,
     },
     MeasureLength {
         measure: u32,
@@ -108,6 +122,92 @@ pub enum SusLine {
     },
 }
 
+/// A `#ATRzz` definition, applied to the notes inside `#ATTRIBUTE zz`
+///
+/// Keys that a definition leaves out are `None`.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct NoteAttributes {
+    /// `pr`, drawing priority, higher is drawn on top
+    pub priority: Option<i32>,
+    /// `h`, height of air notes
+    pub height: Option<f64>,
+    /// `rh`, roll speed
+    pub roll_speed: Option<f64>,
+}
+
+impl fmt::Display for NoteAttributes {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut entries = Vec::new();
+        if let Some(priority) = self.priority {
+            entries.push(format!("pr:{}", priority));
+        }
+        if let Some(height) = self.height {
+            entries.push(format!("h:{}", height));
+        }
+        if let Some(roll_speed) = self.roll_speed {
+            entries.push(format!("rh:{}", roll_speed));
+        }
+        write!(f, "\"{}\"", entries.join(", "))
+    }
+}
+
+/// Options set by `#REQUEST` lines
+///
+/// Applications may define requests of their own, those are not kept here.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct SusRequests {
+    /// `ticks_per_beat n`, resolution of every tick in the chart
+    pub ticks_per_beat: Option<u32>,
+    /// `enable_priority true/false`, whether `pr` attributes decide the drawing order
+    pub enable_priority: Option<bool>,
+}
+
+impl SusRequests {
+    /// Applies the (unquoted) value of a `#REQUEST` line
+    fn apply(&mut self, line: &str, request: &str) -> Result<(), ParseError> {
+        let (name, value) = request
+            .trim()
+            .split_once(char::is_whitespace)
+            .unwrap_or((request.trim(), ""));
+        let value = value.trim();
+        match name {
+            "ticks_per_beat" => {
+                let ticks = value
+                    .parse()
+                    .ok()
+                    .filter(|&ticks| ticks > 0)
+                    .ok_or_else(|| ParseError::invalid(line, value, "ticks per beat"))?;
+                self.ticks_per_beat = Some(ticks);
+            }
+            "enable_priority" => {
+                let enable = value
+                    .parse()
+                    .map_err(|_| ParseError::invalid(line, value, "enable_priority"))?;
+                self.enable_priority = Some(enable);
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+}
+
+/// One `meas'tick:speed` entry of a `#TILzz` hi-speed definition
+#[derive(Debug, Clone, PartialEq)]
+pub struct HiSpeedEntry {
+    pub measure: u32,
+    /// Offset into the measure, in the chart's ticks per beat
+    pub tick: u32,
+    /// Speed multiplier from this point on, negative values scroll backwards
+    pub speed: f64,
+}
+
+impl fmt::Display for HiSpeedEntry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // `{:?}` keeps the `.0` of whole speeds, the way definitions are usually written
+        write!(f, "{}'{}:{:?}", self.measure, self.tick, self.speed)
+    }
+}
+
 /// Represents a parsed SUS chart.
 #[derive(Debug)]
 pub struct SusChart {
@@ -180,17 +280,17 @@ impl SusLine {
         SusLine::BpmDefinition { id: id.into(), bpm }
     }
 
-    pub fn attribute_definition(id: impl Into<String>, attributes: impl Into<String>) -> Self {
+    pub fn attribute_definition(id: impl Into<String>, attributes: NoteAttributes) -> Self {
         SusLine::AttributeDefinition {
             id: id.into(),
-            attributes: attributes.into(),
+            attributes,
         }
     }
 
-    pub fn hi_speed_definition(id: impl Into<String>, definition: impl Into<String>) -> Self {
+    pub fn hi_speed_definition(id: impl Into<String>, entries: Vec<HiSpeedEntry>) -> Self {
         SusLine::HiSpeedDefinition {
             id: id.into(),
-            definition: definition.into(),
+            entries,
         }
     }
 
@@ -207,23 +307,127 @@ impl SusLine {
     }
 }
 
+impl SusLine {
+    /// Measure number of measure-based lines
+    fn measure(&self) -> Option<u32> {
+        match self {
+            SusLine::MeasureLength { measure, .. }
+            | SusLine::BpmChange { measure, .. }
+            | SusLine::TapNotes { measure, .. }
+            | SusLine::HoldNotes { measure, .. }
+            | SusLine::SlideNotes { measure, .. }
+            | SusLine::DirectionalNotes { measure, .. } => Some(*measure),
+            _ => None,
+        }
+    }
+
+    fn measure_mut(&mut self) -> Option<&mut u32> {
+        match self {
+            SusLine::MeasureLength { measure, .. }
+            | SusLine::BpmChange { measure, .. }
+            | SusLine::TapNotes { measure, .. }
+            | SusLine::HoldNotes { measure, .. }
+            | SusLine::SlideNotes { measure, .. }
+            | SusLine::DirectionalNotes { measure, .. } => Some(measure),
+            _ => None,
+        }
+    }
+}
+
 /// Map SUS type digit to ChuniNoteType
+///
+/// Taps pass their type digit as is, holds and directional notes add their line type
+/// as the high nibble (`0x2n`, `0x5n`), slides pass their `slide_type`.
 fn sus_type_to_chuni_note_type(type_digit: u8, slide_type: Option<u8>) -> ChuniNoteType {
     match slide_type {
         Some(3) | Some(4) => ChuniNoteType::Slide,
         _ => match type_digit {
-            1 | 2 | 3 | 4 | 5 | 6 => ChuniNoteType::Tap,
+            1..=6 => tap_type(|(subtype, _)| *subtype == type_digit).map_or_else(
+                || ChuniNoteType::Unknown(format!("SUS type {:X}", type_digit)),
+                |(_, note_type)| note_type,
+            ),
             // Hold: 2xy
             0x20..=0x2F => ChuniNoteType::Hold,
             // Slide: 3xy, 4xy
-            0x30..=0x3F | 0x40..=0x4F => ChuniNoteType::Slide,
+            0x30..=0x4F => ChuniNoteType::Slide,
             // Directional: 5x
-            0x50..=0x5F => ChuniNoteType::Flick,
+            0x51..=0x56 => air_direction(|(subtype, ..)| *subtype == type_digit & 0xF)
+                .map_or(ChuniNoteType::Air, |(_, note_type, _)| note_type),
             _ => ChuniNoteType::Unknown(format!("SUS type {:X}", type_digit)),
         },
     }
 }
 
+/// Tap (`1x`) subtypes and the notes they stand for
+///
+/// `5x` taps are invisible, `6x` (an air action without an air hold) has no equivalent.
+static TAP_TYPES: &[(u8, ChuniNoteType)] = &[
+    (1, ChuniNoteType::Tap),
+    (2, ChuniNoteType::ExTap),
+    (3, ChuniNoteType::Flick),
+    (4, ChuniNoteType::Mine),
+    (5, ChuniNoteType::Default),
+];
+
+/// The entry of [`TAP_TYPES`] matching `predicate`
+fn tap_type(predicate: impl Fn(&(u8, ChuniNoteType)) -> bool) -> Option<(u8, ChuniNoteType)> {
+    TAP_TYPES.iter().find(|entry| predicate(entry)).cloned()
+}
+
+/// Directional (`5x`) subtypes and the air notes they stand for, an up arrow is a plain air note
+static AIR_DIRECTIONS: &[(u8, ChuniNoteType, ir::AirDirection)] = &[
+    (1, ChuniNoteType::Air, ir::AirDirection::Up),
+    (
+        2,
+        ChuniNoteType::AirDirectional(AirDirection::Down),
+        ir::AirDirection::Down,
+    ),
+    (
+        3,
+        ChuniNoteType::AirDirectional(AirDirection::UpLeft),
+        ir::AirDirection::UpLeft,
+    ),
+    (
+        4,
+        ChuniNoteType::AirDirectional(AirDirection::UpRight),
+        ir::AirDirection::UpRight,
+    ),
+    (
+        5,
+        ChuniNoteType::AirDirectional(AirDirection::DownLeft),
+        ir::AirDirection::DownLeft,
+    ),
+    (
+        6,
+        ChuniNoteType::AirDirectional(AirDirection::DownRight),
+        ir::AirDirection::DownRight,
+    ),
+];
+
+/// The entry of [`AIR_DIRECTIONS`] matching `predicate`
+fn air_direction(
+    predicate: impl Fn(&(u8, ChuniNoteType, ir::AirDirection)) -> bool,
+) -> Option<(u8, ChuniNoteType, ir::AirDirection)> {
+    AIR_DIRECTIONS
+        .iter()
+        .find(|entry| predicate(entry))
+        .cloned()
+}
+
+/// Splits note data into its two-digit pairs and the tick each of them falls on
+///
+/// Pairs are spread evenly over the `length` ticks of the measure starting at `start`.
+fn data_pairs(data: &str, start: u32, length: u32) -> impl Iterator<Item = (u32, &str)> {
+    let count = (data.len() / 2).max(1) as u64;
+    data.as_bytes()
+        .chunks_exact(2)
+        .enumerate()
+        .filter_map(move |(index, pair)| {
+            let tick = start + (length as u64 * index as u64 / count) as u32;
+            Some((tick, std::str::from_utf8(pair).ok()?))
+        })
+}
+
 impl SusChart {
     /// Add any SusLine
     pub fn new_line(&mut self, line: SusLine) -> &mut Self {
@@ -256,17 +460,220 @@ impl SusChart {
         });
         self
     }
+
+    /// Change the BPM at an absolute tick
+    ///
+    /// BPMs that are already defined reuse their `#BPMzz` id, new ones get the next free id,
+    /// the change is left out once every id is taken.
+    /// Measure lengths have to be added first, they decide which measure `tick` falls in.
+    pub fn bpm(&mut self, tick: u32, bpm: f64) -> &mut Self {
+        let tempo = self.tempo_map();
+        let mut allocations = Allocations::of(self);
+        self.add_bpm(&mut allocations, &tempo, tick, bpm);
+        self
+    }
+
+    /// Add a hold or slide from its points, on the first channel that's free for its duration
+    ///
+    /// The points should be ordered, the first one being the start (`1`) and the last one the end (`2`),
+    /// and all share the same `data_type`. When all 36 channels are busy it goes on channel 0.
+    pub fn long_note(&mut self, points: Vec<Note>) -> &mut Self {
+        let mut allocations = Allocations::of(self);
+        self.add_long_note(&mut allocations, points);
+        self
+    }
+
+    /// [`SusChart::bpm`] with the ids taken so far in `allocations` and the chart's `tempo`
+    ///
+    /// Warns when there's no `#BPMzz` id left for a new BPM.
+    fn add_bpm(
+        &mut self,
+        allocations: &mut Allocations,
+        tempo: &TempoMap,
+        tick: u32,
+        bpm: f64,
+    ) -> Option<ConversionWarning> {
+        let id = match allocations.bpms.iter().find(|(defined, _)| *defined == bpm) {
+            Some((_, id)) => id.clone(),
+            None => {
+                let Some(id) = (1..)
+                    .map_while(base36_id)
+                    .find(|id| allocations.bpms.iter().all(|(_, used)| used != id))
+                else {
+                    return Some(ConversionWarning::at(
+                        tick,
+                        format!("SUS has no #BPM ids left, skipping the change to {}", bpm),
+                    ));
+                };
+                allocations.bpms.push((bpm, id.clone()));
+                self.new_line(SusLine::bpm_definition(id.clone(), bpm));
+                id
+            }
+        };
+
+        let (measure, offset) = tempo.tick_to_measure(tick);
+        let length = tempo.measure_length(measure);
+        let slots = length / gcd(offset, length);
+        let mut data = "00".repeat(slots as usize);
+        let slot = (offset * slots / length) as usize * 2;
+        data.replace_range(slot..slot + 2, &id);
+        self.new_line(SusLine::bpm_change(measure, 0, data));
+        None
+    }
+
+    /// [`SusChart::long_note`] with the channels taken so far in `allocations`
+    ///
+    /// Warns when all 36 channels are busy.
+    fn add_long_note(
+        &mut self,
+        allocations: &mut Allocations,
+        mut points: Vec<Note>,
+    ) -> Option<ConversionWarning> {
+        let (Some(first), Some(last)) = (points.first(), points.last()) else {
+            return None;
+        };
+        let (data_type, start, end) = (first.data_type, first.tick, last.tick);
+
+        let free = (0..36).find(|channel| allocations.is_free(data_type, *channel, start, end));
+        let channel = free.unwrap_or(0);
+        allocations.take(data_type, channel, start, end);
+
+        for point in &mut points {
+            point.channel = Some(channel);
+        }
+        self.lines.extend(points.into_iter().map(SusLine::Note));
+        free.is_none().then(|| {
+            ConversionWarning::at(
+                start,
+                "SUS has no free channel left for this long note, it may connect to another one",
+            )
+        })
+    }
+}
+
+/// `#BPMzz` ids and long note channels already taken in a chart
+///
+/// Kept up to date while writing, so adding notes doesn't have to go through the whole chart again.
+#[derive(Debug, Default)]
+struct Allocations {
+    /// Defined BPMs and their ids
+    bpms: Vec<(f64, String)>,
+    /// Ticks taken on each `(data_type, channel)`, from start to end
+    channels: HashMap<(u8, u8), BTreeMap<u32, u32>>,
+}
+
+impl Allocations {
+    /// Everything `chart` already uses
+    fn of(chart: &SusChart) -> Self {
+        let mut allocations = Allocations::default();
+        for line in &chart.lines {
+            if let SusLine::BpmDefinition { id, bpm } = line {
+                allocations.bpms.push((*bpm, id.clone()));
+            }
+        }
+        for long_note in chart.long_notes().0 {
+            allocations.take(
+                long_note.data_type,
+                long_note.channel,
+                long_note.start().tick,
+                long_note.end().tick,
+            );
+        }
+        allocations
+    }
+
+    /// Whether nothing on `channel` overlaps `start..=end`
+    fn is_free(&self, data_type: u8, channel: u8, start: u32, end: u32) -> bool {
+        // spans on one channel don't overlap, the last one starting by `end` is the only candidate
+        self.channels
+            .get(&(data_type, channel))
+            .and_then(|spans| spans.range(..=end).next_back())
+            .is_none_or(|(_, other_end)| *other_end < start)
+    }
+
+    fn take(&mut self, data_type: u8, channel: u8, start: u32, end: u32) {
+        self.channels
+            .entry((data_type, channel))
+            .or_default()
+            .insert(start, end);
+    }
 }
 
 /// Represents a single note or event in SUS.
 use super::ChuniNoteType;
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct Note {
     pub lane: u8,
+    /// Absolute tick, in the chart's ticks per beat
     pub tick: u32,
     pub note_type: ChuniNoteType,
     pub width: u8,
+    /// Type of the data line the note comes from, the `c` in `#mmmcxy`
+    pub data_type: u8,
+    /// First digit of the note's data pair, e.g. `2` for the end of a hold
+    pub subtype: u8,
+    /// Channel of hold and slide notes, points sharing one are connected
+    pub channel: Option<u8>,
+    /// Id of the `#TILzz` selected by `#HISPEED` for this note, `None` outside of any
+    pub hispeed: Option<String>,
+    /// Id of the `#ATRzz` selected by `#ATTRIBUTE` for this note, `None` outside of any
+    pub attribute: Option<String>,
+}
+
+impl Note {
+    /// A plain `1x` tap note
+    pub fn tap(tick: u32, lane: u8, width: u8) -> Self {
+        Self {
+            lane,
+            tick,
+            note_type: ChuniNoteType::Tap,
+            width,
+            data_type: 1,
+            subtype: 1,
+            channel: None,
+            hispeed: None,
+            attribute: None,
+        }
+    }
+}
+
+/// The `#HISPEED` and `#ATTRIBUTE` ids in effect at some point of a chart
+#[derive(Debug, Clone, Default, PartialEq, Eq, PartialOrd, Ord)]
+struct NoteScope {
+    hispeed: Option<String>,
+    attribute: Option<String>,
+}
+
+impl NoteScope {
+    /// Follows a `#HISPEED`, `#NOSPEED`, `#ATTRIBUTE` or `#NOATTRIBUTE` line
+    fn apply(&mut self, key: &str, value: &str) {
+        match key {
+            "HISPEED" => self.hispeed = Some(value.to_string()),
+            "NOSPEED" => self.hispeed = None,
+            "ATTRIBUTE" => self.attribute = Some(value.to_string()),
+            "NOATTRIBUTE" => self.attribute = None,
+            _ => {}
+        }
+    }
+
+    /// Writes the lines switching from this scope to `next`
+    fn switch_to(&mut self, f: &mut fmt::Formatter<'_>, next: NoteScope) -> fmt::Result {
+        if next.hispeed != self.hispeed {
+            match &next.hispeed {
+                Some(id) => writeln!(f, "#HISPEED {}", id)?,
+                None => writeln!(f, "#NOSPEED")?,
+            }
+        }
+        if next.attribute != self.attribute {
+            match &next.attribute {
+                Some(id) => writeln!(f, "#ATTRIBUTE {}", id)?,
+                None => writeln!(f, "#NOATTRIBUTE")?,
+            }
+        }
+        *self = next;
+        Ok(())
+    }
 }
 
 /// Parse a hexadecimal character to its numeric value
@@ -279,21 +686,12 @@ fn parse_hex_char(c: char) -> Option<u8> {
     }
 }
 
-/// Parse a 3-digit measure number (with support for hex)
+/// Parse a 3-digit decimal measure number, anything else is special data
 fn parse_measure(s: &str) -> Option<u32> {
-    if s.len() != 3 {
+    if s.len() != 3 || !s.chars().all(|c| c.is_ascii_digit()) {
         return None;
     }
-
-    let mut result = 0u32;
-    for c in s.chars() {
-        if let Some(digit) = parse_hex_char(c) {
-            result = result * 36 + digit as u32;
-        } else {
-            return None;
-        }
-    }
-    Some(result)
+    s.parse().ok()
 }
 
 /// Parse a single hex digit lane identifier
@@ -334,245 +732,1239 @@ fn parse_metadata_line(line: &str) -> Option<SusLine> {
     }
 }
 
-/// Parse chart data line with format: header:data
-fn parse_chart_data_line(line: &str) -> Option<SusLine> {
-    if !line.contains(':') {
-        return None;
+/// Parses the quoted, comma-separated `key:value` entries of a `#ATRzz` line
+fn parse_attributes(line: &str, data: &str) -> Result<NoteAttributes, ParseError> {
+    let mut attributes = NoteAttributes::default();
+    let entries = data.trim_matches('"');
+    for entry in entries
+        .split(',')
+        .map(str::trim)
+        .filter(|entry| !entry.is_empty())
+    {
+        let (key, value) = entry
+            .split_once(':')
+            .ok_or_else(|| ParseError::invalid(line, entry, "attribute"))?;
+        let value = value.trim();
+        match key.trim() {
+            "pr" => {
+                attributes.priority = Some(
+                    value
+                        .parse()
+                        .map_err(|_| ParseError::invalid(line, value, "priority"))?,
+                )
+            }
+            "h" => {
+                attributes.height = Some(
+                    value
+                        .parse()
+                        .map_err(|_| ParseError::invalid(line, value, "height"))?,
+                )
+            }
+            "rh" => {
+                attributes.roll_speed = Some(
+                    value
+                        .parse()
+                        .map_err(|_| ParseError::invalid(line, value, "roll speed"))?,
+                )
+            }
+            _ => return Err(ParseError::invalid(line, entry, "attribute")),
+        }
     }
+    Ok(attributes)
+}
 
-    let parts: Vec<&str> = line.splitn(2, ':').collect();
-    if parts.len() != 2 {
-        return None;
+/// Parses the quoted, comma-separated `meas'tick:speed` entries of a `#TILzz` line
+fn parse_hispeed_entries(line: &str, data: &str) -> Result<Vec<HiSpeedEntry>, ParseError> {
+    let entries = data.trim_matches('"');
+    if entries.trim().is_empty() {
+        return Ok(Vec::new());
+    }
+    entries
+        .split(',')
+        .map(|entry| {
+            let entry = entry.trim();
+            let invalid = || ParseError::invalid(line, entry, "hi-speed entry");
+            let (timing, speed) = entry.split_once(':').ok_or_else(invalid)?;
+            let (measure, tick) = timing.split_once('\'').ok_or_else(invalid)?;
+            Ok(HiSpeedEntry {
+                measure: measure
+                    .trim()
+                    .parse()
+                    .map_err(|_| ParseError::invalid(line, measure, "measure"))?,
+                tick: tick
+                    .trim()
+                    .parse()
+                    .map_err(|_| ParseError::invalid(line, tick, "tick"))?,
+                speed: speed
+                    .trim()
+                    .parse()
+                    .map_err(|_| ParseError::invalid(line, speed, "speed"))?,
+            })
+        })
+        .collect()
+}
+
+/// Checks that note data is made of two-digit base-36 pairs
+///
+/// Spaces between pairs are allowed and dropped.
+fn parse_note_data(line: &str, data: &str) -> Result<String, ParseError> {
+    let pairs: String = data.split_whitespace().collect();
+    if !pairs.len().is_multiple_of(2) || !pairs.chars().all(|c| c.is_ascii_alphanumeric()) {
+        return Err(ParseError::invalid(line, data, "note data"));
     }
+    Ok(pairs)
+}
+
+/// Parse chart data line with format: header:data
+///
+/// Returns `None` for lines that aren't chart data, such as metadata with a `:` in its value.
+fn parse_chart_data_line(line: &str) -> Result<Option<SusLine>, ParseError> {
+    let Some((header, data)) = line.split_once(':') else {
+        return Ok(None);
+    };
 
-    let header = parts[0].trim().trim_start_matches('#');
-    let data = parts[1].trim();
+    let header = header.trim().trim_start_matches('#');
+    if header.contains(char::is_whitespace) {
+        return Ok(None);
+    }
+    let data = data.trim();
 
     // Handle special definitions (BPM, ATR, TIL, etc.)
     if header.starts_with("BPM") && header.len() >= 4 {
         let id = &header[3..];
-        if let Ok(bpm) = data.parse::<f64>() {
-            return Some(SusLine::BpmDefinition {
-                id: id.to_string(),
-                bpm,
-            });
-        }
+        let bpm = data
+            .parse::<f64>()
+            .map_err(|_| ParseError::invalid(line, data, "BPM"))?;
+        return Ok(Some(SusLine::BpmDefinition {
+            id: id.to_string(),
+            bpm,
+        }));
     }
 
     if header.starts_with("ATR") && header.len() >= 4 {
         let id = &header[3..];
-        return Some(SusLine::AttributeDefinition {
+        return Ok(Some(SusLine::AttributeDefinition {
             id: id.to_string(),
-            attributes: data.to_string(),
-        });
+            attributes: parse_attributes(line, data)?,
+        }));
     }
 
     if header.starts_with("TIL") && header.len() >= 4 {
         let id = &header[3..];
-        return Some(SusLine::HiSpeedDefinition {
+        return Ok(Some(SusLine::HiSpeedDefinition {
             id: id.to_string(),
-            definition: data.to_string(),
-        });
+            entries: parse_hispeed_entries(line, data)?,
+        }));
     }
 
     // Handle measure data lines
-    if header.len() >= 5 {
-        if let Some(measure) = parse_measure(&header[0..3]) {
-            let type_and_lane = &header[3..];
-
-            match type_and_lane.chars().next() {
-                Some('0') if type_and_lane.len() >= 2 => {
-                    // 02 = measure length
-                    if type_and_lane == "02" {
-                        if let Ok(length) = data.parse::<f64>() {
-                            return Some(SusLine::MeasureLength { measure, length });
-                        }
-                    }
-                    // 08 = BPM change
-                    else if type_and_lane == "08" {
-                        return Some(SusLine::BpmChange {
-                            measure,
-                            lane: 0,
-                            data: data.to_string(),
-                        });
-                    }
+    if header.len() >= 5
+        && let Some(measure) = header.get(0..3).and_then(parse_measure)
+    {
+        let type_and_lane = &header[3..];
+
+        match type_and_lane.chars().next() {
+            Some('0') if type_and_lane.len() >= 2 => {
+                // 02 = measure length
+                if type_and_lane == "02" {
+                    let length = data
+                        .parse::<f64>()
+                        .map_err(|_| ParseError::invalid(line, data, "measure length"))?;
+                    return Ok(Some(SusLine::MeasureLength { measure, length }));
                 }
-                Some('1') if type_and_lane.len() >= 2 => {
-                    // Tap notes (10-16)
-                    if let Some(lane) = parse_lane(type_and_lane.chars().nth(1).unwrap_or('0')) {
-                        // Always use ChuniNoteType::Tap for tap lines
-                        if data.len() >= 2 {
-                            let width_digit = data.chars().nth(1).unwrap();
-                            let width = width_digit.to_digit(36).unwrap_or(1) as u8;
-                            let note = Note {
-                                lane,
-                                tick: 0,
-                                note_type: ChuniNoteType::Tap,
-                                width,
-                            };
-                            return Some(SusLine::Note(note));
-                        }
-                        return Some(SusLine::TapNotes {
-                            measure,
-                            lane,
-                            data: data.to_string(),
-                        });
-                    }
+                // 08 = BPM change
+                else if type_and_lane == "08" {
+                    return Ok(Some(SusLine::BpmChange {
+                        measure,
+                        lane: 0,
+                        data: data.to_string(),
+                    }));
                 }
-                Some('2') if type_and_lane.len() >= 3 => {
-                    // Hold notes (2xy)
-                    let chars: Vec<char> = type_and_lane.chars().collect();
-                    if let (Some(lane), Some(channel)) =
-                        (parse_lane(chars[1]), parse_lane(chars[2]))
-                    {
-                        // Always use ChuniNoteType::Hold for hold lines
-                        if data.len() >= 2 {
-                            let width_digit = data.chars().nth(1).unwrap();
-                            let width = width_digit.to_digit(36).unwrap_or(1) as u8;
-                            let note = Note {
-                                lane,
-                                tick: 0,
-                                note_type: ChuniNoteType::Hold,
-                                width,
-                            };
-                            return Some(SusLine::Note(note));
-                        }
-                        return Some(SusLine::HoldNotes {
-                            measure,
-                            lane,
-                            channel,
-                            data: data.to_string(),
-                        });
-                    }
+            }
+            Some('1') if type_and_lane.len() >= 2 => {
+                // Tap notes (1x)
+                if let Some(lane) = parse_lane(type_and_lane.chars().nth(1).unwrap_or('0')) {
+                    return Ok(Some(SusLine::TapNotes {
+                        measure,
+                        lane,
+                        data: parse_note_data(line, data)?,
+                    }));
                 }
-                Some('3') | Some('4') if type_and_lane.len() >= 3 => {
-                    // Slide notes (3xy, 4xy)
-                    let chars: Vec<char> = type_and_lane.chars().collect();
-                    let slide_type = if chars[0] == '3' { 3 } else { 4 };
-                    if let (Some(lane), Some(channel)) =
-                        (parse_lane(chars[1]), parse_lane(chars[2]))
-                    {
-                        // Always use ChuniNoteType::Slide for slide lines
-                        if data.len() >= 2 {
-                            let width_digit = data.chars().nth(1).unwrap();
-                            let width = width_digit.to_digit(36).unwrap_or(1) as u8;
-                            let note = Note {
-                                lane,
-                                tick: 0,
-                                note_type: ChuniNoteType::Slide,
-                                width,
-                            };
-                            return Some(SusLine::Note(note));
-                        }
-                        return Some(SusLine::SlideNotes {
-                            measure,
-                            slide_type,
-                            lane,
-                            channel,
-                            data: data.to_string(),
-                        });
-                    }
+            }
+            Some('2') if type_and_lane.len() >= 3 => {
+                // Hold notes (2xy)
+                let chars: Vec<char> = type_and_lane.chars().collect();
+                if let (Some(lane), Some(channel)) = (parse_lane(chars[1]), parse_lane(chars[2])) {
+                    return Ok(Some(SusLine::HoldNotes {
+                        measure,
+                        lane,
+                        channel,
+                        data: parse_note_data(line, data)?,
+                    }));
                 }
-                Some('5') if type_and_lane.len() >= 2 => {
-                    // Directional notes (5x)
-                    if let Some(lane) = parse_lane(type_and_lane.chars().nth(1).unwrap_or('0')) {
-                        if data.len() >= 2 {
-                            let type_digit = data.chars().nth(0).unwrap();
-                            let width_digit = data.chars().nth(1).unwrap();
-                            let type_digit = type_digit.to_digit(36).unwrap_or(5) as u8;
-                            let width = width_digit.to_digit(36).unwrap_or(1) as u8;
-                            let note_type = sus_type_to_chuni_note_type(type_digit, None);
-                            let note = Note {
-                                lane,
-                                tick: 0,
-                                note_type,
-                                width,
-                            };
-                            return Some(SusLine::Note(note));
-                        }
-                        return Some(SusLine::DirectionalNotes {
-                            measure,
-                            lane,
-                            data: data.to_string(),
-                        });
-                    }
+            }
+            Some('3') | Some('4') if type_and_lane.len() >= 3 => {
+                // Slide notes (3xy, 4xy)
+                let chars: Vec<char> = type_and_lane.chars().collect();
+                let slide_type = if chars[0] == '3' { 3 } else { 4 };
+                if let (Some(lane), Some(channel)) = (parse_lane(chars[1]), parse_lane(chars[2])) {
+                    return Ok(Some(SusLine::SlideNotes {
+                        measure,
+                        slide_type,
+                        lane,
+                        channel,
+                        data: parse_note_data(line, data)?,
+                    }));
+                }
+            }
+            Some('5') if type_and_lane.len() >= 2 => {
+                // Directional notes (5x)
+                if let Some(lane) = parse_lane(type_and_lane.chars().nth(1).unwrap_or('0')) {
+                    return Ok(Some(SusLine::DirectionalNotes {
+                        measure,
+                        lane,
+                        data: parse_note_data(line, data)?,
+                    }));
                 }
-                _ => {}
             }
+            _ => {}
         }
     }
 
-    Some(SusLine::Unknown {
-        content: line.to_string(),
-    })
+    Err(ParseError::at_field(
+        line,
+        header,
+        ParseErrorKind::UnrecognizedLine(line.trim().to_string()),
+    ))
 }
 
 /// Parse a single line of SUS data
-fn parse_line(line: &str) -> SusLine {
+fn parse_line(line: &str) -> Result<SusLine, ParseError> {
     let trimmed = line.trim();
 
     // Empty lines are treated as comments
     if trimmed.is_empty() {
-        return SusLine::Comment {
+        return Ok(SusLine::Comment {
             content: String::new(),
-        };
+        });
     }
 
     // Lines starting with # are metadata or chart data
     if trimmed.starts_with('#') {
         // Try parsing as chart data first (contains colon)
-        if let Some(parsed) = parse_chart_data_line(trimmed) {
-            return parsed;
+        if let Some(parsed) = parse_chart_data_line(line)? {
+            return Ok(parsed);
         }
         // Fall back to metadata
         if let Some(parsed) = parse_metadata_line(trimmed) {
-            return parsed;
+            return Ok(parsed);
         }
     }
 
     // Everything else is a comment
-    SusLine::Comment {
+    Ok(SusLine::Comment {
         content: trimmed.to_string(),
-    }
+    })
 }
 
-/// Parse a complete SUS file using an iterator-based approach
-pub fn parse_sus(content: &str) -> SusChart {
-    parse_sus_iter(content.lines())
+/// Parse a complete SUS file, failing on the first line that can't be parsed
+pub fn parse_sus(content: &str) -> Result<SusChart, ParseError> {
+    parse_sus_iter(content.lines(), ParseMode::Strict).map(|parsed| parsed.value)
 }
 
 /// Iterator-based SUS parser
-pub fn parse_sus_iter<'a, I>(lines: I) -> SusChart
+///
+/// In lenient mode, lines that can't be parsed are kept as [`SusLine::Unknown`].
+pub fn parse_sus_iter<'a, I>(lines: I, mode: ParseMode) -> Result<Parsed<SusChart>, ParseError>
 where
     I: IntoIterator<Item = &'a str>,
 {
     let mut metadata = HashMap::new();
-    let parsed_lines: Vec<SusLine> = lines
-        .into_iter()
-        .map(|line| {
-            let parsed_line = parse_line(line);
-            // Extract metadata for easy access
-            if let SusLine::Metadata { key, value } = &parsed_line {
-                metadata.insert(key.clone(), value.clone());
-            }
-            parsed_line
-        })
-        .collect();
+    let mut parsed_lines = Vec::new();
+    let mut diagnostics = Diagnostics::new(mode);
+    // `#MEASUREBS` is added to the measure of every data line after it
+    let mut measure_base = 0;
 
-    SusChart {
+    for (index, line) in lines.into_iter().enumerate() {
+        let mut parsed_line = match parse_line(line) {
+            Ok(parsed_line) => parsed_line,
+            Err(error) => {
+                diagnostics.report(error.on_line(index + 1))?;
+                SusLine::Unknown {
+                    content: line.trim().to_string(),
+                }
+            }
+        };
+        if let Some(measure) = parsed_line.measure_mut() {
+            *measure += measure_base;
+        }
+        // Extract metadata for easy access
+        if let SusLine::Metadata { key, value } = &parsed_line {
+            if key == "MEASUREBS" {
+                match value.parse() {
+                    Ok(base) => measure_base = base,
+                    Err(_) => {
+                        let field = line.split_whitespace().nth(1).unwrap_or(line);
+                        let error = ParseError::invalid(line, field, "measure base");
+                        diagnostics.report(error.on_line(index + 1))?;
+                    }
+                }
+            }
+            if key == "REQUEST"
+                && let Err(error) = SusRequests::default().apply(line, value)
+            {
+                diagnostics.report(error.on_line(index + 1))?;
+            }
+            metadata.insert(key.clone(), value.clone());
+        }
+        parsed_lines.push(parsed_line);
+    }
+
+    Ok(diagnostics.finish(SusChart {
         metadata,
         lines: parsed_lines,
+    }))
+}
+
+/// SUS metadata keys and the IR fields they map to
+const TEXT_METADATA: [&str; 7] = [
+    "TITLE",
+    "ARTIST",
+    "GENRE",
+    "DESIGNER",
+    "PLAYLEVEL",
+    "SONGID",
+    "WAVE",
+];
+
+impl SusChart {
+    /// Parse a complete SUS file, skipping bad lines in lenient mode
+    pub fn parse(content: &str, mode: ParseMode) -> Result<Parsed<Self>, ParseError> {
+        parse_sus_iter(content.lines(), mode)
+    }
+
+    /// Options of every `#REQUEST` line, later lines override earlier ones
+    ///
+    /// Requests that fail to parse are left out, [`parse_sus`] reports them.
+    pub fn requests(&self) -> SusRequests {
+        let mut requests = SusRequests::default();
+        for line in &self.lines {
+            if let SusLine::Metadata { key, value } = line
+                && key == "REQUEST"
+            {
+                requests.apply(value, value).ok();
+            }
+        }
+        requests
+    }
+
+    /// Ticks per beat of this chart, from `#REQUEST "ticks_per_beat n"`, 480 if unset
+    pub fn ticks_per_beat(&self) -> u32 {
+        self.requests().ticks_per_beat.unwrap_or(ir::TICKS_PER_BEAT)
+    }
+
+    /// Timing of this chart, in its own ticks per beat
+    ///
+    /// BPM changes pointing at undefined `#BPMzz` ids are left out.
+    pub fn tempo_map(&self) -> TempoMap {
+        self.timing().0
+    }
+
+    /// Builds the tempo map from `#mmm02` and `#mmm08` lines, warning about undefined BPMs
+    fn timing(&self) -> (TempoMap, Vec<ConversionWarning>) {
+        let mut warnings = Vec::new();
+        let ticks_per_beat = self.ticks_per_beat();
+        let mut tempo = TempoMap::new(ticks_per_beat, 120.0);
+
+        for line in &self.lines {
+            if let SusLine::MeasureLength { measure, length } = line {
+                let length = (length * ticks_per_beat as f64).round() as u32;
+                tempo.set_measure_length(*measure, length);
+            }
+        }
+
+        let bpms: HashMap<&str, f64> = self
+            .lines
+            .iter()
+            .filter_map(|line| match line {
+                SusLine::BpmDefinition { id, bpm } => Some((id.as_str(), *bpm)),
+                _ => None,
+            })
+            .collect();
+        for line in &self.lines {
+            let SusLine::BpmChange { measure, data, .. } = line else {
+                continue;
+            };
+            let start = tempo.measure_to_tick(*measure, 0);
+            let length = tempo.measure_length(*measure);
+            for (tick, id) in data_pairs(data, start, length) {
+                if id == "00" {
+                    continue;
+                }
+                match bpms.get(id) {
+                    Some(bpm) => tempo.set_bpm(tick, *bpm),
+                    None => warnings.push(ConversionWarning::at(
+                        rescale_tick(tick, ticks_per_beat, ir::TICKS_PER_BEAT).0,
+                        format!("BPM {} is never defined", id),
+                    )),
+                }
+            }
+        }
+
+        (tempo, warnings)
+    }
+
+    /// Scroll speeds of this chart, in its own ticks per beat
+    ///
+    /// Every `#TILzz` is the timeline numbered by its base-36 id. Notes outside of any
+    /// `#HISPEED` scope go on [`SusChart::default_timeline`], which has no speed changes.
+    /// The main timeline is the one measure lines scroll with, set by `#MEASUREHS`.
+    /// Without it, the first `#HISPEED` picks it, or the default timeline if there is none.
+    pub fn scroll_map(&self) -> ScrollMap {
+        let tempo = self.tempo_map();
+        let mut scroll = ScrollMap::new(0);
+
+        for line in &self.lines {
+            let SusLine::HiSpeedDefinition { id, entries } = line else {
+                continue;
+            };
+            let Some(timeline) = hispeed_timeline(id) else {
+                continue;
+            };
+            for entry in entries {
+                let tick = tempo.measure_to_tick(entry.measure, entry.tick);
+                scroll.set_speed(timeline, tick, entry.speed);
+            }
+        }
+
+        let scope = |scope: &str| {
+            self.lines.iter().find_map(|line| match line {
+                SusLine::Metadata { key, value } if key == scope => hispeed_timeline(value),
+                _ => None,
+            })
+        };
+        let main = scope("MEASUREHS").or_else(|| scope("HISPEED"));
+        scroll.set_main_timeline(main.unwrap_or_else(|| self.default_timeline()));
+        scroll
+    }
+
+    /// Attributes a note was declared with, `None` outside of `#ATTRIBUTE` or for an undefined id
+    pub fn note_attributes(&self, note: &Note) -> Option<&NoteAttributes> {
+        let id = note.attribute.as_deref()?;
+        self.lines.iter().rev().find_map(|line| match line {
+            SusLine::AttributeDefinition {
+                id: defined,
+                attributes,
+            } if defined == id => Some(attributes),
+            _ => None,
+        })
+    }
+
+    /// Timeline for notes outside of any `#HISPEED` scope
+    ///
+    /// This is the lowest timeline that no `#TILzz`, `#HISPEED` or `#MEASUREHS` refers to.
+    pub fn default_timeline(&self) -> u32 {
+        let used: Vec<u32> = self
+            .lines
+            .iter()
+            .filter_map(|line| match line {
+                SusLine::HiSpeedDefinition { id, .. } => hispeed_timeline(id),
+                SusLine::Metadata { key, value } if key == "HISPEED" || key == "MEASUREHS" => {
+                    hispeed_timeline(value)
+                }
+                SusLine::Note(Note {
+                    hispeed: Some(id), ..
+                }) => hispeed_timeline(id),
+                _ => None,
+            })
+            .collect();
+        (0..).find(|timeline| !used.contains(timeline)).unwrap_or(0)
+    }
+}
+
+/// Timeline of a `#TILzz` or `#HISPEED zz` id
+fn hispeed_timeline(id: &str) -> Option<u32> {
+    u32::from_str_radix(id.trim(), 36).ok()
+}
+
+impl SusChart {
+    /// Every note of this chart with its absolute tick, in the chart's own ticks per beat
+    ///
+    /// Data lines are split into one note per non-empty pair,
+    /// notes added with [`SusChart::note`] are kept as they are.
+    pub fn notes(&self) -> Vec<Note> {
+        let tempo = self.tempo_map();
+        let mut notes = Vec::new();
+        // `#HISPEED zz` and `#ATTRIBUTE zz` apply to every data line after them,
+        // until `#NOSPEED` and `#NOATTRIBUTE`
+        let mut scope = NoteScope::default();
+
+        for line in &self.lines {
+            let (measure, data_type, lane, channel, data) = match line {
+                SusLine::Note(note) => {
+                    notes.push(note.clone());
+                    continue;
+                }
+                SusLine::Metadata { key, value } => {
+                    scope.apply(key, value);
+                    continue;
+                }
+                SusLine::TapNotes {
+                    measure,
+                    lane,
+                    data,
+                } => (*measure, 1, *lane, None, data),
+                SusLine::HoldNotes {
+                    measure,
+                    lane,
+                    channel,
+                    data,
+                } => (*measure, 2, *lane, Some(*channel), data),
+                SusLine::SlideNotes {
+                    measure,
+                    slide_type,
+                    lane,
+                    channel,
+                    data,
+                } => (*measure, *slide_type, *lane, Some(*channel), data),
+                SusLine::DirectionalNotes {
+                    measure,
+                    lane,
+                    data,
+                } => (*measure, 5, *lane, None, data),
+                _ => continue,
+            };
+
+            let start = tempo.measure_to_tick(measure, 0);
+            let length = tempo.measure_length(measure);
+            for (tick, pair) in data_pairs(data, start, length) {
+                let mut digits = pair.chars().filter_map(|c| c.to_digit(36));
+                let (Some(subtype), Some(width)) = (digits.next(), digits.next()) else {
+                    continue;
+                };
+                if subtype == 0 {
+                    continue;
+                }
+                let subtype = subtype as u8;
+                let note_type = match line {
+                    SusLine::HoldNotes { .. } => sus_type_to_chuni_note_type(0x20 | subtype, None),
+                    SusLine::SlideNotes { slide_type, .. } => {
+                        sus_type_to_chuni_note_type(subtype, Some(*slide_type))
+                    }
+                    SusLine::DirectionalNotes { .. } => {
+                        sus_type_to_chuni_note_type(0x50 | subtype, None)
+                    }
+                    _ => sus_type_to_chuni_note_type(subtype, None),
+                };
+                notes.push(Note {
+                    lane,
+                    tick,
+                    note_type,
+                    width: width as u8,
+                    data_type,
+                    subtype,
+                    channel,
+                    hispeed: scope.hispeed.clone(),
+                    attribute: scope.attribute.clone(),
+                });
+            }
+        }
+
+        notes.sort_by_key(|note| (note.tick, note.lane));
+        notes
+    }
+
+    /// Holds and slides assembled from the points sharing a channel
+    ///
+    /// Points that can't be part of a complete long note are left out and reported.
+    pub fn long_notes(&self) -> (Vec<LongNote>, Vec<LongNoteError>) {
+        let mut channels: HashMap<(u8, u8), Vec<Note>> = HashMap::new();
+        for note in self.notes() {
+            if let Some(channel) = note.channel {
+                channels
+                    .entry((note.data_type, channel))
+                    .or_default()
+                    .push(note);
+            }
+        }
+
+        let mut long_notes = Vec::new();
+        let mut errors = Vec::new();
+        let mut channels: Vec<_> = channels.into_iter().collect();
+        channels.sort_by_key(|(key, _)| *key);
+        for ((data_type, channel), mut points) in channels {
+            // an end and a start on the same tick chain two long notes, close the first one
+            points.sort_by_key(|point| (point.tick, point.subtype != 2));
+
+            let mut open: Option<Vec<Note>> = None;
+            for point in points {
+                match point.subtype {
+                    1 => {
+                        if let Some(dangling) = open.replace(vec![point]) {
+                            errors.push(LongNoteError::DanglingStart {
+                                tick: dangling[0].tick,
+                                channel,
+                            });
+                        }
+                    }
+                    2 => match open.take() {
+                        Some(mut chain) => {
+                            chain.push(point);
+                            long_notes.push(LongNote {
+                                data_type,
+                                channel,
+                                points: chain,
+                            });
+                        }
+                        None => errors.push(LongNoteError::OrphanEnd {
+                            tick: point.tick,
+                            channel,
+                        }),
+                    },
+                    _ => match &mut open {
+                        Some(chain) => chain.push(point),
+                        None => errors.push(LongNoteError::OrphanPoint {
+                            tick: point.tick,
+                            channel,
+                        }),
+                    },
+                }
+            }
+            if let Some(dangling) = open {
+                errors.push(LongNoteError::DanglingStart {
+                    tick: dangling[0].tick,
+                    channel,
+                });
+            }
+        }
+
+        long_notes.sort_by_key(|long_note| (long_note.start().tick, long_note.start().lane));
+        (long_notes, errors)
+    }
+}
+
+/// A hold or slide, assembled from its points by [`SusChart::long_notes`]
+#[derive(Debug, Clone, PartialEq)]
+pub struct LongNote {
+    /// `2` for holds, `3` and `4` for the two kinds of slides
+    pub data_type: u8,
+    pub channel: u8,
+    /// Start, relay and control points and the end, in order
+    pub points: Vec<Note>,
+}
+
+impl LongNote {
+    pub fn start(&self) -> &Note {
+        &self.points[0]
+    }
+
+    pub fn end(&self) -> &Note {
+        &self.points[self.points.len() - 1]
+    }
+}
+
+/// Hold or slide points that don't form a complete long note
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum LongNoteError {
+    #[error("tick {tick}: long note on channel {channel} is never ended")]
+    DanglingStart { tick: u32, channel: u8 },
+    #[error("tick {tick}: long note end on channel {channel} has no start")]
+    OrphanEnd { tick: u32, channel: u8 },
+    #[error("tick {tick}: long note point on channel {channel} is outside of any long note")]
+    OrphanPoint { tick: u32, channel: u8 },
+}
+
+impl ChuniChart for SusChart {
+    fn to_ir(&self) -> Conversion<IrChart> {
+        let mut chart = IrChart::default();
+
+        let text = |key: &str| {
+            self.metadata
+                .get(key)
+                .filter(|value| !value.is_empty())
+                .cloned()
+        };
+        let [title, artist, genre, designer, level, song_id, bgm] = TEXT_METADATA.map(text);
+        chart.metadata = IrMetadata {
+            title,
+            artist,
+            genre,
+            designer,
+            level,
+            song_id,
+            bgm,
+            difficulty: text("DIFFICULTY").and_then(|value| value.parse().ok()),
+            bgm_offset: text("WAVEOFFSET").and_then(|value| value.parse().ok()),
+            jacket: text("JACKET"),
+            ..Default::default()
+        };
+
+        let (tempo, mut warnings) = self.timing();
+        chart.set_tempo_map(&tempo);
+
+        let ticks_per_beat = tempo.ticks_per_beat();
+        let to_ir_tick = |tick| rescale_tick(tick, ticks_per_beat, ir::TICKS_PER_BEAT).0;
+
+        chart.scroll = self.scroll_map().map_ticks(to_ir_tick);
+        let default_timeline = self.default_timeline();
+        let timeline = |note: &Note| {
+            note.hispeed
+                .as_deref()
+                .and_then(hispeed_timeline)
+                .unwrap_or(default_timeline)
+        };
+
+        let (long_notes, errors) = self.long_notes();
+        for error in errors {
+            warnings.push(ConversionWarning::new(format!("skipping {error}")));
+        }
+        for long_note in long_notes {
+            let start = long_note.start();
+            let kind = match long_note.data_type {
+                2 => IrNoteKind::Hold {
+                    end_tick: to_ir_tick(long_note.end().tick),
+                    ex: false,
+                    effect: None,
+                },
+                _ => IrNoteKind::Slide {
+                    ex: false,
+                    effect: None,
+                    head: SlidePointKind::Relay,
+                    points: long_note.points[1..]
+                        .iter()
+                        .map(|point| SlidePoint {
+                            tick: to_ir_tick(point.tick),
+                            lane: point.lane,
+                            width: point.width,
+                            kind: slide_point_kind(point.subtype),
+                        })
+                        .collect(),
+                },
+            };
+            chart.notes.push(IrNote {
+                tick: to_ir_tick(start.tick),
+                lane: start.lane,
+                width: start.width,
+                timeline: timeline(start),
+                kind,
+            });
+        }
+
+        for note in self.notes() {
+            // hold and slide points were handled above
+            if note.channel.is_some() {
+                continue;
+            }
+            let tick = to_ir_tick(note.tick);
+            let kind = match &note.note_type {
+                ChuniNoteType::Tap => IrNoteKind::Tap,
+                ChuniNoteType::ExTap => IrNoteKind::ExTap { effect: None },
+                ChuniNoteType::Flick => IrNoteKind::Flick,
+                ChuniNoteType::Mine => IrNoteKind::Mine,
+                ChuniNoteType::Air | ChuniNoteType::AirDirectional(_) => IrNoteKind::Air {
+                    direction: air_direction(|(_, note_type, _)| *note_type == note.note_type)
+                        .map_or(ir::AirDirection::Up, |(.., direction)| direction),
+                    inverted: false,
+                    // the ground note below, if any, is linked once the notes are sorted
+                    parent: None,
+                },
+                other => {
+                    warnings.push(ConversionWarning::at(
+                        tick,
+                        format!("skipping {:?} note, it can't be converted yet", other),
+                    ));
+                    continue;
+                }
+            };
+            chart.notes.push(IrNote {
+                tick,
+                lane: note.lane,
+                width: note.width,
+                timeline: timeline(&note),
+                kind,
+            });
+        }
+
+        chart.sort_notes();
+        chart.link_air_parents();
+        Conversion::new(chart, warnings)
+    }
+
+    fn from_ir(chart: &IrChart) -> Conversion<Self> {
+        let mut warnings = Vec::new();
+        let mut sus = SusChart {
+            metadata: HashMap::new(),
+            lines: Vec::new(),
+        };
+
+        let metadata = &chart.metadata;
+        let values = [
+            &metadata.title,
+            &metadata.artist,
+            &metadata.genre,
+            &metadata.designer,
+            &metadata.level,
+            &metadata.song_id,
+            &metadata.bgm,
+        ];
+        for (key, value) in TEXT_METADATA.iter().zip(values) {
+            if let Some(value) = value {
+                sus.metadata(*key, value.clone());
+            }
+        }
+        if let Some(difficulty) = metadata.difficulty {
+            sus.metadata("DIFFICULTY", difficulty.to_string());
+        }
+        if let Some(offset) = metadata.bgm_offset {
+            sus.metadata("WAVEOFFSET", offset.to_string());
+        }
+        if let Some(jacket) = &metadata.jacket {
+            sus.metadata("JACKET", jacket.clone());
+        }
+
+        let tempo = chart.tempo_map();
+        for change in &chart.time_signatures {
+            let (measure, _) = tempo.tick_to_measure(change.tick);
+            sus.new_line(SusLine::measure_length(
+                measure,
+                change.numerator as f64 * 4.0 / change.denominator as f64,
+            ));
+        }
+
+        // measure lengths are all in, they're all the BPM changes need
+        let sus_tempo = sus.tempo_map();
+        let mut allocations = Allocations::default();
+        for change in &chart.bpm_changes {
+            warnings.extend(sus.add_bpm(&mut allocations, &sus_tempo, change.tick, change.bpm));
+        }
+
+        // every timeline that changes speed gets a `#TILzz`, the rest scroll at speed 1
+        let mut timelines: Vec<u32> = chart.notes.iter().map(|note| note.timeline).collect();
+        timelines.push(chart.scroll.main_timeline());
+        timelines.sort_unstable();
+        timelines.dedup();
+        let mut hispeeds = HashMap::new();
+        // air slide heights, each one's `#ATR` ID is its index plus one
+        let mut heights = Vec::new();
+        for timeline in timelines {
+            let changes = chart.scroll.effective_changes(timeline);
+            if changes.is_empty() {
+                continue;
+            }
+            let Some(id) = base36_id(hispeeds.len() + 1) else {
+                warnings.push(ConversionWarning::new(
+                    "SUS has no #TIL ids left, the remaining timelines scroll at speed 1",
+                ));
+                break;
+            };
+            let entries = changes
+                .iter()
+                .map(|change| {
+                    let (measure, tick) = tempo.tick_to_measure(change.tick);
+                    HiSpeedEntry {
+                        measure,
+                        tick,
+                        speed: change.speed,
+                    }
+                })
+                .collect();
+            sus.new_line(SusLine::hi_speed_definition(id.clone(), entries));
+            hispeeds.insert(timeline, id);
+        }
+        if let Some(id) = hispeeds.get(&chart.scroll.main_timeline()) {
+            sus.metadata("MEASUREHS", id.clone());
+        }
+
+        for note in &chart.notes {
+            let hispeed = hispeeds.get(&note.timeline).cloned();
+            let point = |tick, lane, width, subtype| Note {
+                data_type: 2,
+                subtype,
+                hispeed: hispeed.clone(),
+                ..Note::tap(tick, lane, width)
+            };
+            let note_type = match &note.kind {
+                IrNoteKind::Tap => ChuniNoteType::Tap,
+                IrNoteKind::ExTap { effect } => {
+                    if effect.is_some() {
+                        warnings.push(ConversionWarning::at(
+                            note.tick,
+                            "SUS ExTaps have no effect, dropping it",
+                        ));
+                    }
+                    ChuniNoteType::ExTap
+                }
+                IrNoteKind::Flick => ChuniNoteType::Flick,
+                IrNoteKind::Mine => ChuniNoteType::Mine,
+                IrNoteKind::Hold { end_tick, ex, .. } => {
+                    if *ex {
+                        warnings.push(ConversionWarning::at(
+                            note.tick,
+                            "SUS holds can't start with an ExTap, writing a plain hold",
+                        ));
+                    }
+                    let points = [(note.tick, 1), (*end_tick, 2)].map(|(tick, subtype)| Note {
+                        note_type: ChuniNoteType::Hold,
+                        ..point(tick, note.lane, note.width, subtype)
+                    });
+                    warnings.extend(sus.add_long_note(&mut allocations, points.to_vec()));
+                    continue;
+                }
+                IrNoteKind::Slide { ex, points, .. } => {
+                    if *ex {
+                        warnings.push(ConversionWarning::at(
+                            note.tick,
+                            "SUS slides can't start with an ExTap, writing a plain slide",
+                        ));
+                    }
+                    let mut chain = vec![point(note.tick, note.lane, note.width, 1)];
+                    for (index, slide_point) in points.iter().enumerate() {
+                        let subtype = match slide_point.kind {
+                            _ if index == points.len() - 1 => 2,
+                            SlidePointKind::Relay => 3,
+                            SlidePointKind::Control => 4,
+                            SlidePointKind::Invisible => 5,
+                        };
+                        chain.push(point(
+                            slide_point.tick,
+                            slide_point.lane,
+                            slide_point.width,
+                            subtype,
+                        ));
+                    }
+                    for point in &mut chain {
+                        point.data_type = 3;
+                        point.note_type = ChuniNoteType::Slide;
+                    }
+                    warnings.extend(sus.add_long_note(&mut allocations, chain));
+                    continue;
+                }
+                IrNoteKind::Air {
+                    direction,
+                    inverted,
+                    ..
+                } => {
+                    if *inverted {
+                        warnings.push(ConversionWarning::at(
+                            note.tick,
+                            "SUS has no inverted air notes, writing a plain one",
+                        ));
+                    }
+                    // directional notes sit on the ground note at the same position by themselves
+                    let (subtype, note_type, _) = air_direction(|(.., other)| other == direction)
+                        .unwrap_or_else(|| AIR_DIRECTIONS[0].clone());
+                    sus.note(Note {
+                        note_type,
+                        data_type: 5,
+                        subtype,
+                        hispeed,
+                        ..Note::tap(note.tick, note.lane, note.width)
+                    });
+                    continue;
+                }
+                IrNoteKind::AirHold { points, .. } => {
+                    warnings.push(ConversionWarning::at(
+                        note.tick,
+                        "SUS has no air holds, writing a 4xy slide that reads back as a ground slide",
+                    ));
+                    let mut chain = vec![point(note.tick, note.lane, note.width, 1)];
+                    for (index, hold_point) in points.iter().enumerate() {
+                        let subtype = match hold_point.action {
+                            _ if index == points.len() - 1 => 2,
+                            true => 3,
+                            false => 5,
+                        };
+                        chain.push(point(hold_point.tick, note.lane, note.width, subtype));
+                    }
+                    for point in &mut chain {
+                        point.data_type = 4;
+                        point.note_type = ChuniNoteType::Slide;
+                    }
+                    warnings.extend(sus.add_long_note(&mut allocations, chain));
+                    continue;
+                }
+                IrNoteKind::AirSlide { height, points, .. } => {
+                    warnings.push(ConversionWarning::at(
+                        note.tick,
+                        "SUS has no air slides, writing a 4xy slide that reads back as a ground slide",
+                    ));
+                    // the heights are kept as `#ATR` `h` attributes of the points
+                    let mut chain = vec![Note {
+                        attribute: height_attribute(&mut sus, &mut heights, *height),
+                        ..point(note.tick, note.lane, note.width, 1)
+                    }];
+                    for (index, slide_point) in points.iter().enumerate() {
+                        let subtype = match slide_point.kind {
+                            _ if index == points.len() - 1 => 2,
+                            SlidePointKind::Relay => 3,
+                            SlidePointKind::Control => 4,
+                            SlidePointKind::Invisible => 5,
+                        };
+                        chain.push(Note {
+                            attribute: height_attribute(&mut sus, &mut heights, slide_point.height),
+                            ..point(
+                                slide_point.tick,
+                                slide_point.lane,
+                                slide_point.width,
+                                subtype,
+                            )
+                        });
+                    }
+                    if chain.iter().any(|point| point.attribute.is_none()) {
+                        warnings.push(ConversionWarning::at(
+                            note.tick,
+                            "SUS has no #ATR ids left, air slide heights are lost",
+                        ));
+                    }
+                    for point in &mut chain {
+                        point.data_type = 4;
+                        point.note_type = ChuniNoteType::Slide;
+                    }
+                    warnings.extend(sus.add_long_note(&mut allocations, chain));
+                    continue;
+                }
+                IrNoteKind::AirCrush { .. } => {
+                    warnings.push(ConversionWarning::at(
+                        note.tick,
+                        "SUS has no air crushes, skipping",
+                    ));
+                    continue;
+                }
+            };
+            let (subtype, note_type) =
+                tap_type(|(_, other)| *other == note_type).unwrap_or_else(|| TAP_TYPES[0].clone());
+            sus.note(Note {
+                note_type,
+                subtype,
+                hispeed,
+                ..Note::tap(note.tick, note.lane, note.width)
+            });
+        }
+
+        Conversion::new(sus, warnings)
+    }
+}
+
+/// Metadata that is written as a quoted string
+const QUOTED_METADATA: [&str; 11] = [
+    "TITLE",
+    "SUBTITLE",
+    "ARTIST",
+    "GENRE",
+    "DESIGNER",
+    "SONGID",
+    "WAVE",
+    "JACKET",
+    "BACKGROUND",
+    "MOVIE",
+    "REQUEST",
+];
+
+/// Notes sharing a scope, measure, data type, lane and channel end up on the same data line
+type NoteLineKey = (NoteScope, u32, u8, u8, Option<u8>);
+
+impl SusChart {
+    /// Merges the [`SusLine::Note`]s of this chart into per-measure, per-lane data lines
+    ///
+    /// Each line uses the smallest subdivision that fits its notes,
+    /// notes that land on an already filled slot are moved to an extra line.
+    /// Every line comes with the scope its notes need to be written under.
+    fn note_lines(&self) -> Vec<(NoteScope, SusLine)> {
+        let tempo = self.tempo_map();
+        let mut groups: BTreeMap<NoteLineKey, Vec<(u32, String)>> = BTreeMap::new();
+        for line in &self.lines {
+            let SusLine::Note(note) = line else {
+                continue;
+            };
+            let (measure, offset) = tempo.tick_to_measure(note.tick);
+            let digit = |value: u8| char::from_digit(value as u32 % 36, 36).unwrap_or('0');
+            groups
+                .entry((
+                    NoteScope {
+                        hispeed: note.hispeed.clone(),
+                        attribute: note.attribute.clone(),
+                    },
+                    measure,
+                    note.data_type,
+                    note.lane,
+                    note.channel,
+                ))
+                .or_default()
+                .push((
+                    offset,
+                    [digit(note.subtype), digit(note.width)].iter().collect(),
+                ));
+        }
+
+        let mut lines = Vec::new();
+        for ((scope, measure, data_type, lane, channel), mut notes) in groups {
+            let length = tempo.measure_length(measure);
+            while !notes.is_empty() {
+                let step = notes
+                    .iter()
+                    .fold(length, |step, (offset, _)| gcd(*offset, step));
+                let mut data = "00".repeat((length / step) as usize);
+                let mut overflow = Vec::new();
+                for (offset, pair) in notes {
+                    let slot = (offset / step) as usize * 2;
+                    if &data[slot..slot + 2] == "00" {
+                        data.replace_range(slot..slot + 2, &pair);
+                    } else {
+                        overflow.push((offset, pair));
+                    }
+                }
+                notes = overflow;
+
+                let channel = channel.unwrap_or(0);
+                let line = match data_type {
+                    2 => SusLine::hold_notes(measure, lane, channel, data),
+                    3 | 4 => SusLine::slide_notes(measure, data_type, lane, channel, data),
+                    5 => SusLine::directional_notes(measure, lane, data),
+                    _ => SusLine::tap_notes(measure, lane, data),
+                };
+                lines.push((scope.clone(), line));
+            }
+        }
+        lines
     }
 }
 
+/// Writes a single line, switching `#MEASUREBS` first if its measure is out of range
+fn write_line(f: &mut fmt::Formatter<'_>, line: &SusLine, measure_base: &mut u32) -> fmt::Result {
+    if let Some(measure) = line.measure()
+        && (measure < *measure_base || measure - *measure_base > 999)
+    {
+        *measure_base = measure / 1000 * 1000;
+        writeln!(f, "#MEASUREBS {}", measure_base)?;
+    }
+    let measure = line.measure().unwrap_or_default() - *measure_base;
+    let lane = |lane: &u8| char::from_digit(*lane as u32 % 36, 36).unwrap_or('0');
+
+    match line {
+        SusLine::Metadata { key, value } if value.is_empty() => writeln!(f, "#{}", key),
+        SusLine::Metadata { key, value } if QUOTED_METADATA.contains(&key.as_str()) => {
+            writeln!(f, "#{} \"{}\"", key, value)
+        }
+        SusLine::Metadata { key, value } => writeln!(f, "#{} {}", key, value),
+        SusLine::Comment { content } | SusLine::Unknown { content } => writeln!(f, "{}", content),
+        // merged into data lines by `SusChart::note_lines`
+        SusLine::Note(_) => Ok(()),
+        SusLine::BpmDefinition { id, bpm } => writeln!(f, "#BPM{}: {}", id, bpm),
+        SusLine::AttributeDefinition { id, attributes } => {
+            writeln!(f, "#ATR{}: {}", id, attributes)
+        }
+        SusLine::HiSpeedDefinition { id, entries } => {
+            let entries: Vec<String> = entries.iter().map(ToString::to_string).collect();
+            writeln!(f, "#TIL{}: \"{}\"", id, entries.join(", "))
+        }
+        SusLine::MeasureLength { length, .. } => writeln!(f, "#{:03}02: {}", measure, length),
+        SusLine::BpmChange { data, .. } => writeln!(f, "#{:03}08: {}", measure, data),
+        SusLine::TapNotes { lane: x, data, .. } => {
+            writeln!(f, "#{:03}1{}: {}", measure, lane(x), data)
+        }
+        SusLine::HoldNotes {
+            lane: x,
+            channel,
+            data,
+            ..
+        } => writeln!(f, "#{:03}2{}{}: {}", measure, lane(x), lane(channel), data),
+        SusLine::SlideNotes {
+            slide_type,
+            lane: x,
+            channel,
+            data,
+            ..
+        } => writeln!(
+            f,
+            "#{:03}{}{}{}: {}",
+            measure,
+            slide_type,
+            lane(x),
+            lane(channel),
+            data
+        ),
+        SusLine::DirectionalNotes { lane: x, data, .. } => {
+            writeln!(f, "#{:03}5{}: {}", measure, lane(x), data)
+        }
+    }
+}
+
+impl fmt::Display for SusChart {
+    /// Writes every line in order, followed by the data lines merged from [`SusLine::Note`]s
+    ///
+    /// Measure numbers are written relative to `#MEASUREBS`, which is emitted as needed,
+    /// and `#HISPEED`/`#ATTRIBUTE` lines switch to the scope of each merged data line.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut measure_base = 0;
+        let mut scope = NoteScope::default();
+        for line in &self.lines {
+            match line {
+                // the base is rewritten from the (absolute) measures below
+                SusLine::Metadata { key, .. } if key == "MEASUREBS" => continue,
+                SusLine::Metadata { key, value } => scope.apply(key, value),
+                _ => {}
+            }
+            write_line(f, line, &mut measure_base)?;
+        }
+        for (next, line) in self.note_lines() {
+            scope.switch_to(f, next)?;
+            write_line(f, &line, &mut measure_base)?;
+        }
+        Ok(())
+    }
+}
+
+/// ID of the `#ATR` giving air notes `height`, defined the first time it's needed
+///
+/// `None` once every id is taken.
+fn height_attribute(sus: &mut SusChart, heights: &mut Vec<f32>, height: f32) -> Option<String> {
+    if let Some(index) = heights.iter().position(|other| *other == height) {
+        return base36_id(index + 1);
+    }
+    let id = base36_id(heights.len() + 1)?;
+    heights.push(height);
+    let attributes = NoteAttributes {
+        // through the shortest f32 representation, 2.3 shouldn't become 2.299999952316284
+        height: height.to_string().parse().ok(),
+        ..Default::default()
+    };
+    sus.new_line(SusLine::attribute_definition(id.clone(), attributes));
+    Some(id)
+}
+
+/// Kind of a slide or air slide point from its subtype, ends are relays
+fn slide_point_kind(subtype: u8) -> SlidePointKind {
+    match subtype {
+        4 => SlidePointKind::Control,
+        5 => SlidePointKind::Invisible,
+        _ => SlidePointKind::Relay,
+    }
+}
+
+/// Formats `value` as the two-digit base-36 ids used by definitions, `None` past `ZZ`
+fn base36_id(value: usize) -> Option<String> {
+    let digit = |value: usize| char::from_digit(value as u32 % 36, 36).unwrap_or('0');
+    (value < 36 * 36).then(|| {
+        [digit(value / 36), digit(value)]
+            .iter()
+            .collect::<String>()
+            .to_uppercase()
+    })
+}
+
+fn gcd(a: u32, b: u32) -> u32 {
+    if b == 0 { a.max(1) } else { gcd(b, a % b) }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::formats::chuni::{c2s::C2SChart, ir::BpmChange, ugc::UGCChart};
 
     #[test]
     fn test_parse_metadata() {
         let line = "#TITLE \"Test Song\"";
-        let result = parse_line(line);
+        let result = parse_line(line).unwrap();
         match result {
             SusLine::Metadata { key, value } => {
                 assert_eq!(key, "TITLE");
@@ -585,7 +1977,7 @@ mod tests {
     #[test]
     fn test_parse_comment() {
         let line = "This is a comment";
-        let result = parse_line(line);
+        let result = parse_line(line).unwrap();
         match result {
             SusLine::Comment { content } => {
                 assert_eq!(content, "This is a comment");
@@ -597,7 +1989,7 @@ mod tests {
     #[test]
     fn test_parse_bpm_definition() {
         let line = "#BPM01: 140.0";
-        let result = parse_line(line);
+        let result = parse_line(line).unwrap();
         match result {
             SusLine::BpmDefinition { id, bpm } => {
                 assert_eq!(id, "01");
@@ -610,7 +2002,7 @@ mod tests {
     #[test]
     fn test_parse_measure_length() {
         let line = "#00002: 4.0";
-        let result = parse_line(line);
+        let result = parse_line(line).unwrap();
         match result {
             SusLine::MeasureLength { measure, length } => {
                 assert_eq!(measure, 0);
@@ -623,42 +2015,58 @@ mod tests {
     #[test]
     fn test_parse_tap_notes() {
         let line = "#00010: 14141414";
-        let result = parse_line(line);
+        let result = parse_line(line).unwrap();
         match result {
-            SusLine::Note(note) => {
-                assert_eq!(note.lane, 0);
-                assert_eq!(note.note_type, super::super::ChuniNoteType::Tap);
-                assert_eq!(note.width, 4);
+            SusLine::TapNotes {
+                measure,
+                lane,
+                data,
+            } => {
+                assert_eq!(measure, 0);
+                assert_eq!(lane, 0);
+                assert_eq!(data, "14141414");
             }
-            _ => panic!("Expected tap note, got {:?}", result),
+            _ => panic!("Expected tap notes, got {:?}", result),
         }
     }
 
     #[test]
     fn test_parse_hold_notes() {
         let line = "#00020a: 14002400";
-        let result = parse_line(line);
+        let result = parse_line(line).unwrap();
         match result {
-            SusLine::Note(note) => {
-                assert_eq!(note.lane, 0);
-                assert_eq!(note.note_type, super::super::ChuniNoteType::Hold);
-                assert_eq!(note.width, 4);
+            SusLine::HoldNotes {
+                lane,
+                channel,
+                data,
+                ..
+            } => {
+                assert_eq!(lane, 0);
+                assert_eq!(channel, 10);
+                assert_eq!(data, "14002400");
             }
-            _ => panic!("Expected hold note, got {:?}", result),
+            _ => panic!("Expected hold notes, got {:?}", result),
         }
     }
 
     #[test]
     fn test_parse_slide_notes() {
         let line = "#00030a: 14340024";
-        let result = parse_line(line);
+        let result = parse_line(line).unwrap();
         match result {
-            SusLine::Note(note) => {
-                assert_eq!(note.lane, 0);
-                assert_eq!(note.note_type, super::super::ChuniNoteType::Slide);
-                assert_eq!(note.width, 4);
+            SusLine::SlideNotes {
+                slide_type,
+                lane,
+                channel,
+                data,
+                ..
+            } => {
+                assert_eq!(slide_type, 3);
+                assert_eq!(lane, 0);
+                assert_eq!(channel, 10);
+                assert_eq!(data, "14340024");
             }
-            _ => panic!("Expected slide note, got {:?}", result),
+            _ => panic!("Expected slide notes, got {:?}", result),
         }
     }
 
@@ -673,7 +2081,7 @@ This is a comment
 #00010: 14141414"#;
 
         // Test both parse_sus and parse_sus_iter
-        let chart = parse_sus(content);
+        let chart = parse_sus(content).unwrap();
         assert_eq!(chart.metadata.get("TITLE"), Some(&"Test Song".to_string()));
         assert_eq!(
             chart.metadata.get("ARTIST"),
@@ -681,7 +2089,9 @@ This is a comment
         );
         assert_eq!(chart.lines.len(), 7);
 
-        let chart_iter = parse_sus_iter(content.lines());
+        let chart_iter = parse_sus_iter(content.lines(), ParseMode::Lenient)
+            .unwrap()
+            .value;
         assert_eq!(
             chart_iter.metadata.get("TITLE"),
             Some(&"Test Song".to_string())
@@ -694,11 +2104,648 @@ This is a comment
     }
 
     #[test]
-    fn test_parse_hex_measure() {
-        // Test parsing measure numbers in base 36
+    fn test_parse_errors() {
+        let content = "#TITLE \"Re:Start\"\n#BPM01: fast\n#00002: 4";
+
+        let error = parse_sus(content).unwrap_err();
+        assert_eq!(error.location.line, 2);
+        assert_eq!(error.location.column, 9);
+        assert_eq!(
+            error.kind,
+            ParseErrorKind::InvalidField {
+                field: "BPM",
+                value: "fast".to_string()
+            }
+        );
+
+        let parsed = SusChart::parse(content, ParseMode::Lenient).unwrap();
+        assert_eq!(parsed.warnings, vec![error]);
+        assert_eq!(parsed.value.metadata["TITLE"], "Re:Start");
+        assert!(matches!(
+            &parsed.value.lines[1],
+            SusLine::Unknown { content } if content == "#BPM01: fast"
+        ));
+
+        // a header cut in the middle of a character isn't a measure
+        let parsed = SusChart::parse("#abé34: 11", ParseMode::Lenient).unwrap();
+        assert!(matches!(
+            &parsed.warnings[0].kind,
+            ParseErrorKind::UnrecognizedLine(line) if line == "#abé34: 11"
+        ));
+    }
+
+    #[test]
+    fn test_decode_notes() {
+        let content = r#"#REQUEST "ticks_per_beat 192"
+#00002: 3
+#00010: 14001400
+#00120a: 1400
+#MEASUREBS 1000
+#00012: 002300000000
+#00020a: 0024"#;
+        let chart = parse_sus(content).unwrap();
+        let notes = chart.notes();
+
+        // measure 0 is 3 beats of 192 ticks, every measure after it too
+        let ticks: Vec<(u32, u8)> = notes.iter().map(|note| (note.tick, note.lane)).collect();
+        assert_eq!(
+            ticks,
+            vec![
+                (0, 0),
+                (288, 0),
+                (576, 0),
+                (1000 * 576 + 96, 2),
+                (1000 * 576 + 288, 0)
+            ]
+        );
+        assert_eq!(notes[0].note_type, ChuniNoteType::Tap);
+        assert_eq!(notes[0].width, 4);
+        assert_eq!(notes[2].note_type, ChuniNoteType::Hold);
+        assert_eq!(notes[2].channel, Some(10));
+        assert_eq!((notes[3].subtype, notes[3].width), (2, 3));
+        assert_eq!((notes[4].subtype, notes[4].channel), (2, Some(10)));
+
+        // `#MEASUREBS` is already applied to the lines themselves
+        assert!(
+            chart
+                .lines
+                .iter()
+                .any(|line| matches!(line, SusLine::HoldNotes { measure: 1000, .. }))
+        );
+
+        let error = parse_sus("#00010: 1410141").unwrap_err();
+        assert_eq!(error.location.column, 9);
+    }
+
+    #[test]
+    fn test_long_notes() {
+        let content = r#"#00020a: 14000000
+#00120a: 00002400
+#00030b: 14003800
+#00130b: 4c00002c
+#00020c: 0000002400000000
+#00120c: 1400
+#00020d: 0033"#;
+        let chart = parse_sus(content).unwrap();
+        let (long_notes, errors) = chart.long_notes();
+
+        assert_eq!(long_notes.len(), 2);
+        let hold = &long_notes[0];
+        assert_eq!((hold.data_type, hold.channel), (2, 10));
+        assert_eq!((hold.start().tick, hold.end().tick), (0, 1920 + 960));
+
+        // the slide crosses into measure 1 through a relay and a control point
+        let slide = &long_notes[1];
+        let points: Vec<(u32, u8, u8)> = slide
+            .points
+            .iter()
+            .map(|point| (point.tick, point.subtype, point.lane))
+            .collect();
+        assert_eq!(
+            points,
+            vec![(0, 1, 0), (960, 3, 0), (1920, 4, 0), (1920 + 1440, 2, 0)]
+        );
+
+        assert_eq!(
+            errors,
+            vec![
+                LongNoteError::OrphanEnd {
+                    tick: 720,
+                    channel: 12
+                },
+                LongNoteError::DanglingStart {
+                    tick: 1920,
+                    channel: 12
+                },
+                LongNoteError::OrphanPoint {
+                    tick: 960,
+                    channel: 13
+                },
+            ]
+        );
+
+        let ir = chart.to_ir();
+        assert_eq!(ir.warnings.len(), 3);
+        assert!(matches!(
+            ir.value.notes[0].kind,
+            IrNoteKind::Hold { end_tick: 2880, .. }
+        ));
+        assert!(matches!(
+            &ir.value.notes[1].kind,
+            IrNoteKind::Slide { points, .. } if points.len() == 3
+                && points[1].kind == SlidePointKind::Control
+        ));
+    }
+
+    #[test]
+    fn test_write_sus() {
+        let content = r#"#TITLE "Test Song"
+#PLAYLEVEL 13+
+#REQUEST "ticks_per_beat 480"
+#BPM01: 150
+#00002: 3
+#00008: 01
+#00010: 14001400
+#00012: 0014
+#00020a: 14000000
+#00120a: 00002400
+#00030b: 14003800
+#00130b: 4c00002c
+#MEASUREBS 1000
+#00011: 000000001a000000"#;
+        let chart = parse_sus(content).unwrap();
+        let written = chart.to_string();
+        assert!(written.starts_with(
+            "#TITLE \"Test Song\"\n#PLAYLEVEL 13+\n#REQUEST \"ticks_per_beat 480\"\n#BPM01: 150\n"
+        ));
+        assert!(written.contains("\n#MEASUREBS 1000\n#00011: 000000001a000000\n"));
+
+        let reparsed = parse_sus(&written).unwrap();
+        assert_eq!(reparsed.notes(), chart.notes());
+        assert_eq!(reparsed.long_notes(), chart.long_notes());
+    }
+
+    #[test]
+    fn test_merge_notes() {
+        let mut chart = SusChart {
+            metadata: HashMap::new(),
+            lines: Vec::new(),
+        };
+        chart
+            .new_line(SusLine::measure_length(0, 3.0))
+            .bpm(0, 120.0)
+            .bpm(480, 180.0)
+            .bpm(1440 + 720, 120.0)
+            .note(Note::tap(0, 2, 4))
+            .note(Note::tap(960, 2, 4))
+            .note(Note::tap(960, 2, 16))
+            .note(Note::tap(1440 * 1000, 2, 4));
+        let hold = |start, end| {
+            [(start, 1), (end, 2)]
+                .map(|(tick, subtype)| Note {
+                    note_type: ChuniNoteType::Hold,
+                    data_type: 2,
+                    subtype,
+                    ..Note::tap(tick, 0, 4)
+                })
+                .to_vec()
+        };
+        // the second hold overlaps the first one and needs its own channel, the third doesn't
+        chart
+            .long_note(hold(0, 480))
+            .long_note(hold(240, 960))
+            .long_note(hold(720, 1440));
+
+        let written = chart.to_string();
+        assert_eq!(
+            written,
+            "#00002: 3\n#BPM01: 120\n#00008: 01\n#BPM02: 180\n#00008: 000200\n#00108: 0001\n\
+             #00012: 140014\n#00012: 00001g\n#000200: 140024140000\n#000201: 001400002400\n\
+             #001200: 24\n#MEASUREBS 1000\n#00012: 14\n"
+        );
+    }
+
+    #[test]
+    fn test_busy_channels() {
+        let ir = IrChart {
+            notes: (0..37)
+                .map(|lane| IrNote {
+                    tick: 0,
+                    lane: lane % 16,
+                    width: 1,
+                    timeline: 0,
+                    kind: IrNoteKind::Hold {
+                        end_tick: 480,
+                        ex: false,
+                        effect: None,
+                    },
+                })
+                .collect(),
+            ..Default::default()
+        };
+        let sus = SusChart::from_ir(&ir);
+        // 36 channels for the first ones, the last hold has none left
+        assert_eq!(sus.warnings.len(), 1, "{:?}", sus.warnings);
+        assert!(sus.warnings[0].message.contains("no free channel"));
+    }
+
+    #[test]
+    fn test_ir_timing() {
+        let content = r#"#TITLE "Test Song"
+#BPM01: 120.0
+#BPM02: 180.0
+#00002: 3
+#00008: 01
+#00108: 0002"#;
+        let ir = parse_sus(content).unwrap().to_ir();
+        assert!(ir.warnings.is_empty(), "{:?}", ir.warnings);
+        let ir = ir.value;
+
+        assert_eq!(ir.metadata.title.as_deref(), Some("Test Song"));
+        assert_eq!(ir.time_signatures[0].numerator, 3);
+        assert_eq!(ir.time_signatures[0].denominator, 4);
+        assert_eq!(
+            ir.bpm_changes[0],
+            BpmChange {
+                tick: 0,
+                bpm: 120.0
+            }
+        );
+        assert_eq!(
+            ir.bpm_changes[1],
+            BpmChange {
+                tick: 1440 + 720,
+                bpm: 180.0
+            }
+        );
+
+        // and back, the BPM change lands in the second half of measure 1 again
+        let sus = SusChart::from_ir(&ir).value;
+        assert!(sus.lines.iter().any(|line| matches!(
+            line,
+            SusLine::BpmChange { measure: 1, data, .. } if data == "0002"
+        )));
+        assert_eq!(sus.to_ir().value.bpm_changes, ir.bpm_changes);
+    }
+
+    #[test]
+    fn test_ir_speeds() {
+        let content = "#00002: 3\n#TIL00: \"0'0:1.0, 1'720:2.5, 2'0:-1\"\n#HISPEED 00\n#00010: 14";
+        let ir = parse_sus(content).unwrap().to_ir();
+        assert!(ir.warnings.is_empty(), "{:?}", ir.warnings);
+        let ir = ir.value;
+        assert_eq!(ir.scroll.speed_at(0, 1440 + 719), 1.0);
+        assert_eq!(ir.scroll.speed_at(0, 1440 + 720), 2.5);
+        assert_eq!(ir.scroll.speed_at(0, 2880), -1.0);
+
+        let sus = SusChart::from_ir(&ir).value;
+        assert!(sus.lines.iter().any(|line| matches!(
+            line,
+            SusLine::HiSpeedDefinition { id, entries } if id == "01" && entries.len() == 2
+        )));
+        assert_eq!(sus.metadata["MEASUREHS"], "01");
+        assert_eq!(
+            sus.to_ir().value.scroll.effective_changes(1),
+            ir.scroll.effective_changes(0)
+        );
+
+        // without `#HISPEED` the notes scroll at speed 1, away from TIL00
+        let ir = parse_sus("#TIL00: \"0'0:2.0\"\n#00010: 14")
+            .unwrap()
+            .to_ir()
+            .value;
+        assert_eq!(ir.notes[0].timeline, 1);
+    }
+
+    #[test]
+    fn test_hispeed_scopes() {
+        let content =
+            "#TIL01: \"0'0:1.0, 0'960:2.0\"\n#HISPEED 01\n#00010: 14141414\n#NOSPEED\n#00110: 14\n";
+        let sus = parse_sus(content).unwrap();
+        let entries = vec![
+            HiSpeedEntry {
+                measure: 0,
+                tick: 0,
+                speed: 1.0,
+            },
+            HiSpeedEntry {
+                measure: 0,
+                tick: 960,
+                speed: 2.0,
+            },
+        ];
+        assert!(sus.lines.iter().any(|line| matches!(
+            line,
+            SusLine::HiSpeedDefinition { id, entries: parsed } if id == "01" && *parsed == entries
+        )));
+        assert_eq!(sus.to_string(), content);
+
+        let scopes: Vec<_> = sus.notes().into_iter().map(|note| note.hispeed).collect();
+        assert_eq!(scopes[..4], vec![Some("01".to_string()); 4]);
+        assert_eq!(scopes[4], None);
+
+        assert!(parse_sus("#TIL01: \"0'0\"").is_err());
+    }
+
+    #[test]
+    fn test_attribute_scopes() {
+        let content = "#ATR01: \"pr:100, h:1.5\"\n#ATR02: \"rh:-2\"\n#ATTRIBUTE 01\n#00010: 1414\n#NOATTRIBUTE\n#00012: 14\n";
+        let sus = parse_sus(content).unwrap();
+        assert!(sus.lines.iter().any(|line| matches!(
+            line,
+            SusLine::AttributeDefinition { id, attributes } if id == "02"
+                && *attributes == NoteAttributes { roll_speed: Some(-2.0), ..Default::default() }
+        )));
+        assert_eq!(sus.to_string(), content);
+
+        let notes = sus.notes();
+        let first = sus.note_attributes(&notes[0]).unwrap();
+        assert_eq!(first.priority, Some(100));
+        assert_eq!(first.height, Some(1.5));
+        assert_eq!(first.roll_speed, None);
+        // sorted by tick, the note without attributes comes second
+        assert!(sus.note_attributes(&notes[1]).is_none());
+        assert_eq!(notes[2].attribute.as_deref(), Some("01"));
+
+        // notes added through the builder get their scope written before them
+        let mut built = SusChart {
+            metadata: HashMap::new(),
+            lines: Vec::new(),
+        };
+        built.note(Note {
+            attribute: Some("01".to_string()),
+            ..Note::tap(0, 2, 4)
+        });
+        built.note(Note::tap(0, 8, 4));
+        assert_eq!(built.to_string(), "#00018: 14\n#ATTRIBUTE 01\n#00012: 14\n");
+
+        assert!(parse_sus("#ATR01: \"x:1\"").is_err());
+        assert!(parse_sus("#ATR01: \"pr:high\"").is_err());
+    }
+
+    #[test]
+    fn test_ir_timelines() {
+        // notes on two timelines with different speeds, and one at speed 1
+        let content = "#TIL01: \"0'0:0.5\"\n#TIL02: \"1'0:2.0\"\n#HISPEED 01\n#00010: 14\n#HISPEED 02\n#00012: 14\n#NOSPEED\n#00014: 14";
+        let ir = parse_sus(content).unwrap().to_ir().value;
+        let timelines: Vec<_> = ir.notes.iter().map(|note| note.timeline).collect();
+        assert_eq!(timelines, vec![1, 2, 0]);
+        assert_eq!(ir.scroll.main_timeline(), 1);
+
+        let sus = SusChart::from_ir(&ir).value;
+        let again = parse_sus(&sus.to_string()).unwrap().to_ir().value;
+        assert_eq!(again.scroll.main_timeline(), 1);
+        for note in &again.notes {
+            let original = ir
+                .notes
+                .iter()
+                .find(|other| other.lane == note.lane)
+                .unwrap();
+            assert_eq!(
+                again.scroll.effective_changes(note.timeline),
+                ir.scroll.effective_changes(original.timeline)
+            );
+        }
+    }
+
+    #[test]
+    fn test_requests() {
+        let content = "#REQUEST \"ticks_per_beat 192\"\n#REQUEST \"enable_priority true\"\n#REQUEST \"side_lane false\"\n#00010: 0014";
+        let sus = parse_sus(content).unwrap();
+        assert_eq!(
+            sus.requests(),
+            SusRequests {
+                ticks_per_beat: Some(192),
+                enable_priority: Some(true),
+            }
+        );
+        // the last `#REQUEST` doesn't hide the resolution set before it
+        assert_eq!(sus.notes()[0].tick, 2 * 192);
+
+        let error = parse_sus("#REQUEST \"ticks_per_beat 0\"").unwrap_err();
+        assert!(matches!(
+            error.kind,
+            ParseErrorKind::InvalidField {
+                field: "ticks per beat",
+                ..
+            }
+        ));
+        assert!(parse_sus("#REQUEST \"enable_priority yes\"").is_err());
+    }
+
+    #[test]
+    fn test_slide_2_channel() {
+        // `4xy` is a slide like `3xy`
+        let ir = parse_sus("#00040a: 14002400").unwrap().to_ir().value;
+        assert!(matches!(ir.notes[0].kind, IrNoteKind::Slide { .. }));
+        let c2s = C2SChart::from_ir(&ir).value.to_string();
+        assert!(c2s.contains("SLD\t0\t0\t0\t4\t192\t0\t4\n"), "{c2s}");
+    }
+
+    #[test]
+    fn test_measure_line_speed() {
+        // `#MEASUREHS` picks the main timeline over the first `#HISPEED`
+        let content =
+            "#TIL01: \"0'0:2.0\"\n#TIL02: \"0'0:0.5\"\n#MEASUREHS 02\n#HISPEED 01\n#00010: 14";
+        let ir = parse_sus(content).unwrap().to_ir().value;
+        assert_eq!(ir.scroll.main_timeline(), 2);
+        assert_eq!(ir.notes[0].timeline, 1);
+
+        let written = SusChart::from_ir(&ir).value.to_string();
+        assert!(written.contains("#MEASUREHS 02\n"), "{written}");
+        let again = parse_sus(&written).unwrap().to_ir().value;
+        assert_eq!(again.scroll.main_timeline(), 2);
+        assert_eq!(again.notes[0].timeline, 1);
+    }
+
+    #[test]
+    fn test_air_notes() {
+        // an up-left air note on a tap, and a down air note on its own
+        let content = "#00010: 14\n#00050: 34\n#00058: 24\n";
+        let sus = parse_sus(content).unwrap();
+        let notes = sus.notes();
+        assert_eq!(
+            notes[1].note_type,
+            ChuniNoteType::AirDirectional(AirDirection::UpLeft)
+        );
+
+        let ir = sus.to_ir();
+        assert!(ir.warnings.is_empty(), "{:?}", ir.warnings);
+        let ir = ir.value;
+        assert_eq!(
+            ir.notes[1].kind,
+            IrNoteKind::Air {
+                direction: ir::AirDirection::UpLeft,
+                inverted: false,
+                parent: Some(0),
+            }
+        );
+        assert_eq!(
+            ir.notes[2].kind,
+            IrNoteKind::Air {
+                direction: ir::AirDirection::Down,
+                inverted: false,
+                parent: None,
+            }
+        );
+
+        let c2s = C2SChart::from_ir(&ir).value.to_string();
+        assert!(c2s.contains("AUL\t0\t0\t0\t4\tTAP"), "{c2s}");
+
+        let written = SusChart::from_ir(&ir).value.to_string();
+        assert!(written.ends_with(content), "{written}");
+    }
+
+    #[test]
+    fn test_tap_types() {
+        let content = "#00010: 1424344454\n";
+        let sus = parse_sus(content).unwrap();
+        let types: Vec<_> = sus.notes().into_iter().map(|note| note.note_type).collect();
+        assert_eq!(
+            types,
+            vec![
+                ChuniNoteType::Tap,
+                ChuniNoteType::ExTap,
+                ChuniNoteType::Flick,
+                ChuniNoteType::Mine,
+                ChuniNoteType::Default,
+            ]
+        );
+
+        // the invisible tap has no IR equivalent
+        let ir = sus.to_ir();
+        assert_eq!(ir.warnings.len(), 1);
+        let kinds: Vec<_> = ir.value.notes.iter().map(|note| &note.kind).collect();
+        assert_eq!(
+            kinds,
+            vec![
+                &IrNoteKind::Tap,
+                &IrNoteKind::ExTap { effect: None },
+                &IrNoteKind::Flick,
+                &IrNoteKind::Mine,
+            ]
+        );
+
+        let c2s = C2SChart::from_ir(&ir.value).value.to_string();
+        for note in ["CHR", "FLK", "MNE"] {
+            assert!(c2s.contains(note), "{c2s}");
+        }
+
+        let written = SusChart::from_ir(&ir.value).value.to_string();
+        assert!(written.ends_with("#00010: 1424344400\n"), "{written}");
+    }
+
+    #[test]
+    fn test_c2s_conversion() {
+        let sus = parse_sus(include_str!("../../../test/chuni/sus/sample.sus")).unwrap();
+        let ir = sus.to_ir();
+        assert!(ir.warnings.is_empty(), "{:?}", ir.warnings);
+        let ir = ir.value;
+
+        let c2s = C2SChart::from_ir(&ir);
+        assert!(c2s.warnings.is_empty(), "{:?}", c2s.warnings);
+        let c2s = c2s.value.to_string();
+        for line in [
+            "BPM\t0\t0\t150.000",
+            "BPM\t2\t0\t200.000",
+            "MET\t2\t0\t3\t4",
+            "TAP\t0\t0\t0\t4",
+            "FLK\t0\t0\t2\t4\tL",
+            "CHR\t0\t192\t0\t4\tUP",
+            "AUL\t0\t192\t0\t4\tCHR",
+            "MNE\t0\t192\t2\t4",
+            "HLD\t1\t0\t0\t4\t288",
+            "SLD\t1\t0\t0\t8\t144\t0\t8",
+            "SLD\t1\t144\t0\t8\t192\t0\t8",
+            "AIR\t2\t0\t4\t3\tTAP",
+            "ADR\t2\t0\t8\t4\tTAP",
+            "TAP\t2\t0\t15\t1",
+        ] {
+            assert!(
+                c2s.contains(&format!("\n{line}\n")),
+                "{line} missing from\n{c2s}"
+            );
+        }
+
+        // C2S -> SUS gives back the same chart, apart from the ExTap effect SUS can't store
+        let c2s = C2SChart::from_string(&c2s).unwrap();
+        let c2s_ir = c2s.to_ir().value;
+        let written = SusChart::from_ir(&c2s_ir);
+        assert_eq!(written.warnings.len(), 1, "{:?}", written.warnings);
+        let back = parse_sus(&written.value.to_string()).unwrap().to_ir().value;
+        assert_eq!(back.notes, ir.notes);
+        assert_eq!(back.bpm_changes, ir.bpm_changes);
+        assert_eq!(back.time_signatures, ir.time_signatures);
+
+        // and that SUS converts to the same C2S notes again
+        let again = C2SChart::from_ir(&back).value;
+        assert_eq!(again.to_ir().value.notes, c2s_ir.notes);
+    }
+
+    #[test]
+    fn test_ugc_conversion() {
+        let content = r#"#TITLE "Sample"
+#DESIGNER "Charter"
+#PLAYLEVEL 12+
+#WAVE "song.ogg"
+#WAVEOFFSET 0.25
+#JACKET "jacket.png"
+#REQUEST "ticks_per_beat 480"
+#BPM01: 150
+#00008: 01
+#TIL01: "1'0:2.0"
+#HISPEED 01
+#00010: 14
+#000210: 14002400
+#000320: 1800003800000028
+#00110: 14
+#00140a: 14
+#00240a: 24
+#00250: 14
+#003400: 14
+#003480: 00000028
+"#;
+        let ir = parse_sus(content).unwrap().to_ir();
+        assert!(ir.warnings.is_empty(), "{:?}", ir.warnings);
+        let ir = ir.value;
+
+        let ugc = UGCChart::from_ir(&ir);
+        assert!(ugc.warnings.is_empty(), "{:?}", ugc.warnings);
+        let ugc = ugc.value.to_string();
+        for line in [
+            "@TITLE\tSample",
+            "@DESIGN\tCharter",
+            "@LEVEL\t12+",
+            "@BGM\tsong.ogg",
+            "@BGMOFS\t0.25",
+            "@JACKET\tjacket.png",
+            "@TIL\t1\t1'0\t2",
+            "@USETIL\t1",
+            "#0'0:h14\n#960>s",
+            "#0'0:s28\n#720>s28\n#1680>s28",
+            "#1'0:s04\n#1920>s04",
+            "#2'0:a04UCN",
+            "#3'0:s04\n#1440>s88",
+        ] {
+            assert!(
+                ugc.contains(&format!("{line}\n")),
+                "{line} missing from\n{ugc}"
+            );
+        }
+
+        // UGC -> SUS gives back the same notes and speeds
+        let ugc_ir = UGCChart::from_string(&ugc).unwrap().to_ir().value;
+        let written = SusChart::from_ir(&ugc_ir);
+        assert!(written.warnings.is_empty(), "{:?}", written.warnings);
+        let back = parse_sus(&written.value.to_string()).unwrap().to_ir().value;
+        assert_eq!(back.notes, ir.notes);
+        assert_eq!(back.metadata, ir.metadata);
+        assert_eq!(back.scroll, ir.scroll);
+
+        // air holds and air slides are written as slides, with the heights as `#ATR`
+        let air = UGCChart::from_string("#0'0:H04N\n#960>s\n#1'0:S040PN\n#480>s881E")
+            .unwrap()
+            .to_ir()
+            .value;
+        let written = SusChart::from_ir(&air);
+        assert_eq!(written.warnings.len(), 2, "{:?}", written.warnings);
+        let written = written.value.to_string();
+        assert!(written.contains("#ATR01: \"h:2.5\"\n"), "{written}");
+        let back = parse_sus(&written).unwrap().to_ir().value;
+        assert!(
+            back.notes
+                .iter()
+                .all(|note| matches!(note.kind, IrNoteKind::Slide { .. }))
+        );
+    }
+
+    #[test]
+    fn test_parse_measure() {
+        // Measure numbers are decimal, anything else is special data like `BPM`
         assert_eq!(parse_measure("000"), Some(0));
         assert_eq!(parse_measure("001"), Some(1));
-        assert_eq!(parse_measure("00a"), Some(10));
-        assert_eq!(parse_measure("010"), Some(36));
+        assert_eq!(parse_measure("010"), Some(10));
+        assert_eq!(parse_measure("00a"), None);
+        assert_eq!(parse_measure("BPM"), None);
     }
 }
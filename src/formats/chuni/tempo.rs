@@ -0,0 +1,250 @@
+//! Chart timing: BPM changes and measure lengths
+//!
+//! A [`TempoMap`] works in the tick resolution of whichever chart it was built from,
+//! and converts between measures, absolute ticks and milliseconds.
+//! BPM always counts quarter notes, time signatures only change how long measures are.
+
+/// A BPM change at an absolute tick
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TempoChange {
+    pub tick: u32,
+    pub bpm: f64,
+}
+
+/// A change of measure length, in effect from `measure` onwards
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MeasureChange {
+    pub measure: u32,
+    /// Length of each measure in ticks
+    pub length: u32,
+}
+
+impl MeasureChange {
+    /// Smallest power-of-two time signature for this measure length
+    ///
+    /// Lengths that don't fit any denominator up to 64 are rounded.
+    pub fn time_signature(&self, ticks_per_beat: u32) -> (u32, u32) {
+        let whole = ticks_per_beat as u64 * 4;
+        let mut denominator = 4u64;
+        while denominator < 64 && !(self.length as u64 * denominator).is_multiple_of(whole) {
+            denominator *= 2;
+        }
+        let numerator = (self.length as u64 * denominator + whole / 2) / whole;
+        (numerator as u32, denominator as u32)
+    }
+}
+
+/// BPM changes and measure lengths of a chart
+#[derive(Debug, Clone, PartialEq)]
+pub struct TempoMap {
+    ticks_per_beat: u32,
+    /// Sorted by tick, the first one is always at tick 0
+    tempo_changes: Vec<TempoChange>,
+    /// Sorted by measure, the first one is always at measure 0
+    measure_changes: Vec<MeasureChange>,
+}
+
+impl TempoMap {
+    /// A tempo map at a constant `bpm` in 4/4
+    ///
+    /// Panics if `ticks_per_beat` is 0, the parsers reject such resolutions.
+    pub fn new(ticks_per_beat: u32, bpm: f64) -> Self {
+        assert!(ticks_per_beat > 0, "a beat can't be 0 ticks long");
+        Self {
+            ticks_per_beat,
+            tempo_changes: vec![TempoChange { tick: 0, bpm }],
+            measure_changes: vec![MeasureChange {
+                measure: 0,
+                length: ticks_per_beat * 4,
+            }],
+        }
+    }
+
+    pub fn ticks_per_beat(&self) -> u32 {
+        self.ticks_per_beat
+    }
+
+    pub fn tempo_changes(&self) -> &[TempoChange] {
+        &self.tempo_changes
+    }
+
+    pub fn measure_changes(&self) -> &[MeasureChange] {
+        &self.measure_changes
+    }
+
+    /// Changes the BPM from `tick` onwards, replacing any change already at that tick
+    pub fn set_bpm(&mut self, tick: u32, bpm: f64) {
+        match self
+            .tempo_changes
+            .binary_search_by_key(&tick, |change| change.tick)
+        {
+            Ok(index) => self.tempo_changes[index].bpm = bpm,
+            Err(index) => self.tempo_changes.insert(index, TempoChange { tick, bpm }),
+        }
+    }
+
+    /// Changes the length of every measure from `measure` onwards, in ticks
+    pub fn set_measure_length(&mut self, measure: u32, length: u32) {
+        match self
+            .measure_changes
+            .binary_search_by_key(&measure, |change| change.measure)
+        {
+            Ok(index) => self.measure_changes[index].length = length,
+            Err(index) => self
+                .measure_changes
+                .insert(index, MeasureChange { measure, length }),
+        }
+    }
+
+    /// Changes the time signature from `measure` onwards
+    pub fn set_time_signature(&mut self, measure: u32, numerator: u32, denominator: u32) {
+        let length = self.ticks_per_beat * 4 * numerator / denominator.max(1);
+        self.set_measure_length(measure, length);
+    }
+
+    /// BPM in effect at `tick`
+    pub fn bpm_at(&self, tick: u32) -> f64 {
+        let index = self
+            .tempo_changes
+            .partition_point(|change| change.tick <= tick);
+        self.tempo_changes[index.saturating_sub(1)].bpm
+    }
+
+    /// Length of `measure` in ticks
+    pub fn measure_length(&self, measure: u32) -> u32 {
+        let index = self
+            .measure_changes
+            .partition_point(|change| change.measure <= measure);
+        self.measure_changes[index.saturating_sub(1)].length
+    }
+
+    /// Absolute tick of an offset into a measure
+    pub fn measure_to_tick(&self, measure: u32, offset: u32) -> u32 {
+        let mut tick = 0;
+        for (index, change) in self.measure_changes.iter().enumerate() {
+            if change.measure >= measure {
+                break;
+            }
+            let until = self
+                .measure_changes
+                .get(index + 1)
+                .map_or(measure, |next| next.measure.min(measure));
+            tick += (until - change.measure) * change.length;
+        }
+        tick + offset
+    }
+
+    /// Measure and offset into it of an absolute tick
+    pub fn tick_to_measure(&self, tick: u32) -> (u32, u32) {
+        let mut start = 0;
+        for (index, change) in self.measure_changes.iter().enumerate() {
+            let length = change.length.max(1);
+            if let Some(next) = self.measure_changes.get(index + 1) {
+                let end = start + (next.measure - change.measure) * length;
+                if tick >= end {
+                    start = end;
+                    continue;
+                }
+            }
+            let measures = (tick - start) / length;
+            return (change.measure + measures, tick - start - measures * length);
+        }
+        unreachable!("a tempo map always has a measure change at measure 0")
+    }
+
+    /// Milliseconds per tick at a given BPM
+    fn ms_per_tick(&self, bpm: f64) -> f64 {
+        60000.0 / (bpm * self.ticks_per_beat as f64)
+    }
+
+    /// Time of an absolute tick in milliseconds from the start of the chart
+    pub fn tick_to_ms(&self, tick: u32) -> f64 {
+        let mut ms = 0.0;
+        for (index, change) in self.tempo_changes.iter().enumerate() {
+            let end = self
+                .tempo_changes
+                .get(index + 1)
+                .map_or(tick, |next| next.tick.min(tick));
+            if end <= change.tick {
+                break;
+            }
+            ms += (end - change.tick) as f64 * self.ms_per_tick(change.bpm);
+        }
+        ms
+    }
+
+    /// Absolute tick closest to a time in milliseconds, negative times clamp to 0
+    pub fn ms_to_tick(&self, ms: f64) -> u32 {
+        let mut elapsed = 0.0;
+        for (index, change) in self.tempo_changes.iter().enumerate() {
+            let ms_per_tick = self.ms_per_tick(change.bpm);
+            if let Some(next) = self.tempo_changes.get(index + 1) {
+                let duration = (next.tick - change.tick) as f64 * ms_per_tick;
+                if ms >= elapsed + duration {
+                    elapsed += duration;
+                    continue;
+                }
+            }
+            let ticks = ((ms - elapsed) / ms_per_tick).round().max(0.0);
+            return change.tick + ticks as u32;
+        }
+        0
+    }
+
+    /// Time of an offset into a measure in milliseconds
+    pub fn measure_to_ms(&self, measure: u32, offset: u32) -> f64 {
+        self.tick_to_ms(self.measure_to_tick(measure, offset))
+    }
+
+    /// Measure and offset closest to a time in milliseconds
+    pub fn ms_to_measure(&self, ms: f64) -> (u32, u32) {
+        self.tick_to_measure(self.ms_to_tick(ms))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_measures_with_time_signatures() {
+        let mut tempo = TempoMap::new(480, 120.0);
+        tempo.set_time_signature(2, 3, 4);
+        tempo.set_time_signature(4, 7, 8);
+
+        assert_eq!(tempo.measure_to_tick(1, 0), 1920);
+        assert_eq!(tempo.measure_to_tick(2, 0), 3840);
+        assert_eq!(tempo.measure_to_tick(3, 480), 3840 + 1440 + 480);
+        assert_eq!(tempo.measure_to_tick(5, 0), 3840 + 1440 * 2 + 1680);
+        assert_eq!(tempo.tick_to_measure(3840 + 1440 + 480), (3, 480));
+        assert_eq!(tempo.tick_to_measure(3840 + 1440 * 2 + 1700), (5, 20));
+        assert_eq!(tempo.tick_to_measure(1919), (0, 1919));
+        assert_eq!(tempo.measure_length(4), 1680);
+    }
+
+    #[test]
+    fn test_mid_measure_bpm_change() {
+        // C2S resolution, 384 ticks per measure
+        let mut tempo = TempoMap::new(96, 120.0);
+        tempo.set_bpm(192, 240.0);
+
+        // two beats at 120 BPM, then two at 240 BPM
+        assert_eq!(tempo.tick_to_ms(192), 1000.0);
+        assert_eq!(tempo.measure_to_ms(1, 0), 1500.0);
+        assert_eq!(tempo.bpm_at(191), 120.0);
+        assert_eq!(tempo.bpm_at(192), 240.0);
+
+        assert_eq!(tempo.ms_to_tick(1000.0), 192);
+        assert_eq!(tempo.ms_to_tick(1250.0), 288);
+        assert_eq!(tempo.ms_to_measure(1500.0), (1, 0));
+        assert_eq!(tempo.ms_to_tick(-10.0), 0);
+    }
+
+    #[test]
+    fn test_time_signature_from_length() {
+        let change = |length| MeasureChange { measure: 0, length };
+        assert_eq!(change(1920).time_signature(480), (4, 4));
+        assert_eq!(change(1680).time_signature(480), (7, 8));
+        assert_eq!(change(360).time_signature(480), (3, 16));
+    }
+}
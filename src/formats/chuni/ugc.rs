@@ -1,16 +1,156 @@
 //! A format for a certain spiky marine simulator
 
-use std::collections::HashMap;
+use std::{
+    cell::Cell,
+    collections::{HashMap, HashSet},
+    fmt,
+    ops::Range,
+    str::FromStr,
+};
 
-use eyre::OptionExt;
+use super::{
+    ChuniChart,
+    ir::{
+        self, AirHoldPoint, AirSlidePoint, Conversion, ConversionWarning, CrushInterval,
+        DEFAULT_EXTAP_EFFECT, ExTapEffect, IrChart, IrMetadata, IrNote, IrNoteKind, SlidePoint,
+        SlidePointKind, rescale_tick,
+    },
+    scroll::ScrollMap,
+    tempo::TempoMap,
+};
+use crate::formats::error::{Diagnostics, ParseError, ParseErrorKind, ParseMode, Parsed};
 
 #[derive(Debug)]
 pub struct UGCChart {
-    pub metadata: HashMap<String, String>,
+    pub header: UgcHeader,
     pub timelines: HashMap<u32, Vec<ParentNote>>,
 }
 
+/// The `@` command lines of a chart
+///
+/// Commands that can be repeated are kept in the order they appear,
+/// commands without a typed field end up in `other` untouched.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct UgcHeader {
+    /// `@VER`, always 8
+    pub version: Option<u32>,
+    /// `@EXVER`, 1 forces [`UgcFlag::ExLong`] on
+    pub ex_version: Option<u32>,
+    pub title: Option<String>,
+    /// `@SORT`, the title normalized for sorting
+    pub sort: Option<String>,
+    pub artist: Option<String>,
+    pub genre: Option<String>,
+    /// `@DESIGN`
+    pub designer: Option<String>,
+    /// `@DIFF`, 0 for BASIC up to 5 for ULTIMA
+    pub difficulty: Option<u32>,
+    /// `@LEVEL`, the number of stars for WORLD'S END charts
+    pub level: Option<String>,
+    /// `@CONST`
+    pub constant: Option<f32>,
+    pub song_id: Option<String>,
+    /// `@BGM`, the audio file name
+    pub bgm: Option<String>,
+    /// `@BGMOFS`, in seconds, positive values delay the audio
+    pub bgm_offset: Option<f64>,
+    /// `@BGMPRV`, start and end of the preview in seconds
+    pub bgm_preview: Option<(f64, f64)>,
+    pub jacket: Option<String>,
+    /// `@FLDCOL`, the field divider color, -1 picks it automatically
+    pub field_color: Option<i32>,
+    /// `@TICKS`, always 480
+    pub ticks: Option<u32>,
+    /// `@MAINBPM`
+    pub main_bpm: Option<f64>,
+    /// `@MAINTIL`, the timeline used for the main speed
+    pub main_timeline: Option<u32>,
+    pub flags: Vec<(UgcFlag, bool)>,
+    pub bpms: Vec<BpmDefinition>,
+    pub beats: Vec<BeatDefinition>,
+    pub timeline_speeds: Vec<TimelineDefinition>,
+    pub speed_modifiers: Vec<SpeedDefinition>,
+    /// Any other command and its raw parameters
+    pub other: Vec<(String, String)>,
+}
+
+/// Attributes that can be set with `@FLAG`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UgcFlag {
+    /// `DIFFTTL`, a tutorial chart
+    Tutorial,
+    /// `SOFFSET`, a blank measure is inserted at the start
+    StartOffset,
+    /// `CLICK`, click sounds are played
+    Click,
+    /// `EXLONG`
+    ExLong,
+    /// `BGMWCMP`, the chart waits for the audio to finish
+    WaitForBgm,
+    /// `HIPRECISION`, air notes use high resolution values
+    HighPrecision,
+}
+
+const FLAG_NAMES: [(UgcFlag, &str); 6] = [
+    (UgcFlag::Tutorial, "DIFFTTL"),
+    (UgcFlag::StartOffset, "SOFFSET"),
+    (UgcFlag::Click, "CLICK"),
+    (UgcFlag::ExLong, "EXLONG"),
+    (UgcFlag::WaitForBgm, "BGMWCMP"),
+    (UgcFlag::HighPrecision, "HIPRECISION"),
+];
+
+impl UgcFlag {
+    pub fn from_name(name: &str) -> Option<Self> {
+        FLAG_NAMES
+            .iter()
+            .find(|(_, flag_name)| *flag_name == name)
+            .map(|(flag, _)| *flag)
+    }
+
+    pub fn name(self) -> &'static str {
+        FLAG_NAMES
+            .iter()
+            .find(|(flag, _)| *flag == self)
+            .map_or("", |(_, name)| name)
+    }
+}
+
+/// `@BPM`, a tempo change
+#[derive(Debug, Clone, PartialEq)]
+pub struct BpmDefinition {
+    pub bar: u64,
+    pub tick: u64,
+    pub bpm: f64,
+}
+
+/// `@BEAT`, a time signature starting at `bar`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BeatDefinition {
+    pub bar: u64,
+    pub numerator: u32,
+    pub denominator: u32,
+}
+
+/// `@TIL`, a scroll speed change of a single timeline
+#[derive(Debug, Clone, PartialEq)]
+pub struct TimelineDefinition {
+    pub timeline: u32,
+    pub bar: u64,
+    pub tick: u64,
+    pub speed: f64,
+}
+
+/// `@SPDMOD`, a note speed change
+#[derive(Debug, Clone, PartialEq)]
+pub struct SpeedDefinition {
+    pub bar: u64,
+    pub tick: u64,
+    pub speed: f64,
+}
+
 #[derive(Debug)]
+#[allow(clippy::enum_variant_names)]
 pub enum ChildNoteType {
     HoldEndPoint,
     SlideRelayPoint {
@@ -84,7 +224,7 @@ pub enum AirColor {
     Inverted,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub enum AirCrushColor {
     Normal,
     Red,
@@ -164,6 +304,32 @@ pub enum ParentNoteType {
     },
 }
 
+impl ParentNoteType {
+    /// Child notes of a sustained note, `None` for notes that can't have any
+    pub fn children(&self) -> Option<&Vec<ChildNote>> {
+        match self {
+            ParentNoteType::Hold { children, .. }
+            | ParentNoteType::Slide { children, .. }
+            | ParentNoteType::AirHold { children, .. }
+            | ParentNoteType::AirSlide { children, .. }
+            | ParentNoteType::AirCrush { children, .. } => Some(children),
+            _ => None,
+        }
+    }
+
+    /// Mutable access to the child notes of a sustained note
+    pub fn children_mut(&mut self) -> Option<&mut Vec<ChildNote>> {
+        match self {
+            ParentNoteType::Hold { children, .. }
+            | ParentNoteType::Slide { children, .. }
+            | ParentNoteType::AirHold { children, .. }
+            | ParentNoteType::AirSlide { children, .. }
+            | ParentNoteType::AirCrush { children, .. } => Some(children),
+            _ => None,
+        }
+    }
+}
+
 #[derive(Debug)]
 pub struct ParentNote {
     pub note_type: ParentNoteType,
@@ -171,25 +337,198 @@ pub struct ParentNote {
     pub tick: u64,
 }
 
-fn parse_timing(string: &str) -> eyre::Result<(u64, u64)> {
-    let (bar, tick) = string.split_once('\'').ok_or_eyre("failed to split")?;
-    Ok((bar.parse::<u64>()?, tick.parse::<u64>()?))
+/// Slice `range` of a note's `data`, which must be a slice of `line`
+fn field<'a>(
+    line: &str,
+    data: &'a str,
+    range: Range<usize>,
+    name: &'static str,
+) -> Result<&'a str, ParseError> {
+    data.get(range)
+        .ok_or_else(|| ParseError::missing(line, name))
+}
+
+/// Base-36 number in `range` of a note's `data`
+fn base36(
+    line: &str,
+    data: &str,
+    range: Range<usize>,
+    name: &'static str,
+) -> Result<u16, ParseError> {
+    let digits = field(line, data, range, name)?;
+    u16::from_str_radix(digits, 36).map_err(|_| ParseError::invalid(line, digits, name))
+}
+
+/// Parses the `bar'tick` part of a note line
+fn parse_timing(line: &str, timing: &str) -> Result<(u64, u64), ParseError> {
+    let (bar, tick) = timing
+        .split_once('\'')
+        .ok_or_else(|| ParseError::invalid(line, timing, "timing"))?;
+    Ok((
+        bar.parse()
+            .map_err(|_| ParseError::invalid(line, bar, "bar"))?,
+        tick.parse()
+            .map_err(|_| ParseError::invalid(line, tick, "tick"))?,
+    ))
+}
+
+/// Parses the one-letter color suffix of air notes, if present
+fn parse_air_color(line: &str, data: &str, at: usize) -> Result<Option<AirColor>, ParseError> {
+    match data.get(at..at + 1) {
+        Some("N") => Ok(Some(AirColor::Normal)),
+        Some("I") => Ok(Some(AirColor::Inverted)),
+        Some(slice) => Err(ParseError::invalid(line, slice, "air color")),
+        None => Ok(None),
+    }
+}
+
+/// Parameter `index` of a header command
+fn argument<T: FromStr>(
+    line: &str,
+    arguments: &[&str],
+    index: usize,
+    name: &'static str,
+) -> Result<T, ParseError> {
+    let argument = arguments
+        .get(index)
+        .ok_or_else(|| ParseError::missing(line, name))?;
+    argument
+        .parse()
+        .map_err(|_| ParseError::invalid(line, argument, name))
+}
+
+impl UgcHeader {
+    /// Reads the parameters of a `@key` command, `rest` must be a slice of `line`
+    ///
+    /// Parameters are tab-separated, text parameters take the rest of the line
+    /// while numeric ones are split on any whitespace so space-separated charts still load.
+    fn parse_command(&mut self, line: &str, key: &str, rest: &str) -> Result<(), ParseError> {
+        let arguments: Vec<&str> = rest.split_whitespace().collect();
+        let text = |name| {
+            let text = rest.trim();
+            match text.is_empty() {
+                true => Err(ParseError::missing(line, name)),
+                false => Ok(Some(text.to_string())),
+            }
+        };
+        let timing = |index: usize, name| {
+            let timing = arguments
+                .get(index)
+                .ok_or_else(|| ParseError::missing(line, name))?;
+            parse_timing(line, timing)
+        };
+
+        match key {
+            "VER" => self.version = Some(argument(line, &arguments, 0, "version")?),
+            "EXVER" => self.ex_version = Some(argument(line, &arguments, 0, "ex version")?),
+            "TITLE" => self.title = text("title")?,
+            "SORT" => self.sort = text("sort key")?,
+            "ARTIST" => self.artist = text("artist")?,
+            "GENRE" => self.genre = text("genre")?,
+            "DESIGN" => self.designer = text("designer")?,
+            "DIFF" => self.difficulty = Some(argument(line, &arguments, 0, "difficulty")?),
+            "LEVEL" => self.level = text("level")?,
+            "CONST" => self.constant = Some(argument(line, &arguments, 0, "constant")?),
+            "SONGID" => self.song_id = text("song id")?,
+            "BGM" => self.bgm = text("bgm")?,
+            "BGMOFS" => self.bgm_offset = Some(argument(line, &arguments, 0, "bgm offset")?),
+            "BGMPRV" => {
+                self.bgm_preview = Some((
+                    argument(line, &arguments, 0, "preview start")?,
+                    argument(line, &arguments, 1, "preview end")?,
+                ))
+            }
+            "JACKET" => self.jacket = text("jacket")?,
+            "FLDCOL" => self.field_color = Some(argument(line, &arguments, 0, "field color")?),
+            "TICKS" => {
+                let ticks = Some(argument(line, &arguments, 0, "resolution")?)
+                    .filter(|&ticks: &u32| ticks > 0)
+                    .ok_or_else(|| ParseError::invalid(line, arguments[0], "resolution"))?;
+                self.ticks = Some(ticks);
+            }
+            "MAINBPM" => self.main_bpm = Some(argument(line, &arguments, 0, "main bpm")?),
+            "MAINTIL" => self.main_timeline = Some(argument(line, &arguments, 0, "main timeline")?),
+            "FLAG" => {
+                let name = arguments
+                    .first()
+                    .ok_or_else(|| ParseError::missing(line, "flag"))?;
+                let flag = UgcFlag::from_name(name)
+                    .ok_or_else(|| ParseError::invalid(line, name, "flag"))?;
+                let value = match arguments.get(1).copied() {
+                    Some("TRUE") => true,
+                    Some("FALSE") => false,
+                    Some(value) => return Err(ParseError::invalid(line, value, "flag value")),
+                    None => return Err(ParseError::missing(line, "flag value")),
+                };
+                self.flags.push((flag, value));
+            }
+            "BPM" => {
+                let (bar, tick) = timing(0, "timing")?;
+                self.bpms.push(BpmDefinition {
+                    bar,
+                    tick,
+                    bpm: argument(line, &arguments, 1, "bpm")?,
+                });
+            }
+            "BEAT" => self.beats.push(BeatDefinition {
+                bar: argument(line, &arguments, 0, "bar")?,
+                numerator: argument(line, &arguments, 1, "numerator")?,
+                denominator: argument(line, &arguments, 2, "denominator")?,
+            }),
+            "TIL" => {
+                let timeline = argument(line, &arguments, 0, "timeline")?;
+                let (bar, tick) = timing(1, "timing")?;
+                self.timeline_speeds.push(TimelineDefinition {
+                    timeline,
+                    bar,
+                    tick,
+                    speed: argument(line, &arguments, 2, "speed")?,
+                });
+            }
+            "SPDMOD" => {
+                let (bar, tick) = timing(0, "timing")?;
+                self.speed_modifiers.push(SpeedDefinition {
+                    bar,
+                    tick,
+                    speed: argument(line, &arguments, 1, "speed")?,
+                });
+            }
+            _ => self.other.push((key.to_string(), rest.to_string())),
+        }
+        Ok(())
+    }
+
+    /// Value of `flag`, the last `@FLAG` setting it wins
+    pub fn flag(&self, flag: UgcFlag) -> Option<bool> {
+        self.flags
+            .iter()
+            .rev()
+            .find(|(set, _)| *set == flag)
+            .map(|(_, value)| *value)
+    }
 }
 
-fn parse_parent_note(line: &str) -> eyre::Result<ParentNote> {
-    let (timing, data) = line.split_once(':').ok_or_eyre("failed to split")?;
-    let (bar, tick) = parse_timing(timing)?;
+fn parse_parent_note(line: &str) -> Result<ParentNote, ParseError> {
+    let note = line.trim().trim_start_matches('#');
+    let (timing, data) = note
+        .split_once(':')
+        .ok_or_else(|| ParseError::missing(line, "note data"))?;
+    let (bar, tick) = parse_timing(line, timing)?;
+
+    let lane = || base36(line, data, 1..2, "lane").map(|lane| lane as u8);
+    let width = || base36(line, data, 2..3, "width").map(|width| width as u8);
 
-    let note_type = match &data[0..1] {
-        "c" => Some(ParentNoteType::Click),
-        "t" => Some(ParentNoteType::Tap {
-            lane: u8::from_str_radix(&data[1..2], 36)?,
-            width: u8::from_str_radix(&data[2..3], 36)?,
-        }),
-        "x" => Some(ParentNoteType::ExTap {
-            lane: u8::from_str_radix(&data[1..2], 36)?,
-            width: u8::from_str_radix(&data[2..3], 36)?,
-            direction: match &data[3..4] {
+    let kind = field(line, data, 0..1, "note type")?;
+    let note_type = match kind {
+        "c" => ParentNoteType::Click,
+        "t" => ParentNoteType::Tap {
+            lane: lane()?,
+            width: width()?,
+        },
+        "x" => ParentNoteType::ExTap {
+            lane: lane()?,
+            width: width()?,
+            direction: match field(line, data, 3..4, "extap effect")? {
                 "U" => ExTapEffectDirection::Up,
                 "D" => ExTapEffectDirection::Down,
                 "C" => ExTapEffectDirection::Center,
@@ -198,135 +537,111 @@ fn parse_parent_note(line: &str) -> eyre::Result<ParentNote> {
                 "L" => ExTapEffectDirection::Right,
                 "R" => ExTapEffectDirection::Left,
                 "I" => ExTapEffectDirection::InOut,
-                _ => Err(eyre::Report::msg(format!(
-                    "unknown extap effect direction type {}",
-                    &data[3..4]
-                )))?,
+                effect => return Err(ParseError::invalid(line, effect, "extap effect")),
             },
-        }),
-        "f" => Some(ParentNoteType::Flick {
-            lane: u8::from_str_radix(&data[1..2], 36)?,
-            width: u8::from_str_radix(&data[2..3], 36)?,
-            direction: match &data[3..4] {
+        },
+        "f" => ParentNoteType::Flick {
+            lane: lane()?,
+            width: width()?,
+            direction: match field(line, data, 3..4, "flick direction")? {
                 "A" => FlickEffectDirection::Auto,
-                "L" => FlickEffectDirection::Left,
-                "R" => FlickEffectDirection::Right,
-                _ => Err(eyre::Report::msg(format!(
-                    "unknown flick effect direction type {}",
-                    &data[3..4]
-                )))?,
+                "L" => FlickEffectDirection::Right,
+                "R" => FlickEffectDirection::Left,
+                direction => return Err(ParseError::invalid(line, direction, "flick direction")),
             },
-        }),
-        "d" => Some(ParentNoteType::Damage {
-            lane: u8::from_str_radix(&data[1..2], 36)?,
-            width: u8::from_str_radix(&data[2..3], 36)?,
-        }),
-        "h" => Some(ParentNoteType::Hold {
-            lane: u8::from_str_radix(&data[1..2], 36)?,
-            width: u8::from_str_radix(&data[2..3], 36)?,
+        },
+        "d" => ParentNoteType::Damage {
+            lane: lane()?,
+            width: width()?,
+        },
+        "h" => ParentNoteType::Hold {
+            lane: lane()?,
+            width: width()?,
             children: vec![],
-        }),
-        "s" => Some(ParentNoteType::Slide {
-            lane: u8::from_str_radix(&data[1..2], 36)?,
-            width: u8::from_str_radix(&data[2..3], 36)?,
+        },
+        "s" => ParentNoteType::Slide {
+            lane: lane()?,
+            width: width()?,
             children: vec![],
-        }),
-        "a" => Some(ParentNoteType::Air {
-            lane: u8::from_str_radix(&data[1..2], 36)?,
-            width: u8::from_str_radix(&data[2..3], 36)?,
-            direction: match &data[3..5] {
+        },
+        "a" => ParentNoteType::Air {
+            lane: lane()?,
+            width: width()?,
+            direction: match field(line, data, 3..5, "air direction")? {
                 "UC" => AirDirection::Up,
                 "UL" => AirDirection::UpRight,
                 "UR" => AirDirection::UpLeft,
                 "DC" => AirDirection::Down,
                 "DL" => AirDirection::DownRight,
                 "DR" => AirDirection::DownLeft,
-                _ => Err(eyre::Report::msg(format!(
-                    "unknown air direction type {}",
-                    &data[3..5]
-                )))?,
-            },
-            color: match data.get(5..6) {
-                Some(slice) => Some(match slice {
-                    "N" => AirColor::Normal,
-                    "I" => AirColor::Inverted,
-                    _ => Err(eyre::Report::msg(format!("unknown air color {}", slice)))?,
-                }),
-                None => None,
-            },
-        }),
-        "H" => Some(ParentNoteType::AirHold {
-            lane: u8::from_str_radix(&data[1..2], 36)?,
-            width: u8::from_str_radix(&data[2..3], 36)?,
-            color: match data.get(3..4) {
-                Some(slice) => Some(match slice {
-                    "N" => AirColor::Normal,
-                    "I" => AirColor::Inverted,
-                    _ => Err(eyre::Report::msg(format!(
-                        "unknown air hold color {}",
-                        slice
-                    )))?,
-                }),
-                None => None,
-            },
-            children: vec![],
-        }),
-        "S" => Some(ParentNoteType::AirSlide {
-            lane: u8::from_str_radix(&data[1..2], 36)?,
-            width: u8::from_str_radix(&data[2..3], 36)?,
-            height: u16::from_str_radix(&data[3..5], 36)?,
-            color: match data.get(5..6) {
-                Some(slice) => Some(match slice {
-                    "N" => AirColor::Normal,
-                    "I" => AirColor::Inverted,
-                    _ => Err(eyre::Report::msg(format!(
-                        "unknown air slide color {}",
-                        slice
-                    )))?,
-                }),
-                None => None,
+                direction => return Err(ParseError::invalid(line, direction, "air direction")),
             },
+            color: parse_air_color(line, data, 5)?,
+        },
+        "H" => ParentNoteType::AirHold {
+            lane: lane()?,
+            width: width()?,
+            color: parse_air_color(line, data, 3)?,
             children: vec![],
-        }),
-        "C" => Some(ParentNoteType::AirCrush {
-            lane: u8::from_str_radix(&data[1..2], 36)?,
-            width: u8::from_str_radix(&data[2..3], 36)?,
-            height: u16::from_str_radix(&data[3..5], 36)?,
-            color: match data.get(5..6) {
-                Some(slice) => Some(match slice {
-                    "0" => AirCrushColor::Normal,
-                    "1" => AirCrushColor::Red,
-                    "2" => AirCrushColor::Orange,
-                    "3" => AirCrushColor::Yellow,
-                    "4" => AirCrushColor::YellowGreen,
-                    "5" => AirCrushColor::Green,
-                    "6" => AirCrushColor::Cyan,
-                    "7" => AirCrushColor::Sky,
-                    "8" => AirCrushColor::Light,
-                    "9" => AirCrushColor::Blue,
-                    "A" => AirCrushColor::BluePurple,
-                    "Y" => AirCrushColor::Magenta,
-                    "B" => AirCrushColor::Pink,
-                    "C" => AirCrushColor::White,
-                    "D" => AirCrushColor::Black,
-                    "Z" => AirCrushColor::Transparent,
-                    _ => Err(eyre::Report::msg(format!(
-                        "unknown air crush color {}",
-                        &data[5..6]
-                    )))?,
-                }),
-                None => None,
-            },
-            interval: if let Some((_, interval)) = data.split_once(',') {
-                Some(interval.parse::<f32>()?)
-            } else {
-                None
-            },
+        },
+        "S" => ParentNoteType::AirSlide {
+            lane: lane()?,
+            width: width()?,
+            height: base36(line, data, 3..5, "height")?,
+            color: parse_air_color(line, data, 5)?,
             children: vec![],
-        }),
-        _ => None,
-    }
-    .ok_or_eyre(format!("unknown note type {}", &data[0..1]))?;
+        },
+        "C" => {
+            let (data, interval) = match data.split_once(',') {
+                Some((data, interval)) => (data, Some(interval)),
+                None => (data, None),
+            };
+            ParentNoteType::AirCrush {
+                lane: lane()?,
+                width: width()?,
+                height: base36(line, data, 3..5, "height")?,
+                color: match data.get(5..6) {
+                    Some(slice) => Some(match slice {
+                        "0" => AirCrushColor::Normal,
+                        "1" => AirCrushColor::Red,
+                        "2" => AirCrushColor::Orange,
+                        "3" => AirCrushColor::Yellow,
+                        "4" => AirCrushColor::YellowGreen,
+                        "5" => AirCrushColor::Green,
+                        "6" => AirCrushColor::Cyan,
+                        "7" => AirCrushColor::Sky,
+                        "8" => AirCrushColor::Light,
+                        "9" => AirCrushColor::Blue,
+                        "A" => AirCrushColor::BluePurple,
+                        "Y" => AirCrushColor::Magenta,
+                        "B" => AirCrushColor::Pink,
+                        "C" => AirCrushColor::White,
+                        "D" => AirCrushColor::Black,
+                        "Z" => AirCrushColor::Transparent,
+                        _ => return Err(ParseError::invalid(line, slice, "air crush color")),
+                    }),
+                    None => None,
+                },
+                // `$` means only the start note generates combo, same as leaving it out
+                interval: match interval {
+                    Some("$") | None => None,
+                    Some(interval) => Some(
+                        interval
+                            .parse::<f32>()
+                            .map_err(|_| ParseError::invalid(line, interval, "interval"))?,
+                    ),
+                },
+                children: vec![],
+            }
+        }
+        _ => {
+            return Err(ParseError::at_field(
+                line,
+                kind,
+                ParseErrorKind::UnknownNoteType(kind.to_string()),
+            ));
+        }
+    };
 
     Ok(ParentNote {
         note_type,
@@ -335,76 +650,1635 @@ fn parse_parent_note(line: &str) -> eyre::Result<ParentNote> {
     })
 }
 
-fn parse_child_note(line: &str) -> ChildNote {
-    todo!()
+/// Whether a note line is a `#OffsetTick>...` child rather than a `#BarTick:...` parent
+fn is_child_line(line: &str) -> bool {
+    line.find([':', '>'])
+        .is_some_and(|at| line[at..].starts_with('>'))
+}
+
+/// Parses a child note line, which types are allowed depends on the `parent` it belongs to
+fn parse_child_note(line: &str, parent: &ParentNoteType) -> Result<ChildNote, ParseError> {
+    let note = line.trim().trim_start_matches('#');
+    if parent.children().is_none() {
+        return Err(ParseError::at_field(
+            line,
+            note,
+            ParseErrorKind::OrphanChildNote,
+        ));
+    }
+    let (offset, data) = note
+        .split_once('>')
+        .ok_or_else(|| ParseError::missing(line, "note data"))?;
+    let offset_tick = offset
+        .parse()
+        .map_err(|_| ParseError::invalid(line, offset, "offset tick"))?;
+
+    let lane = || base36(line, data, 1..2, "lane").map(|lane| lane as u8);
+    let width = || base36(line, data, 2..3, "width").map(|width| width as u8);
+    let height = || base36(line, data, 3..5, "height");
+
+    let kind = field(line, data, 0..1, "note type")?;
+    let note_type = match (parent, kind) {
+        (ParentNoteType::Hold { .. }, "s") => ChildNoteType::HoldEndPoint,
+        (ParentNoteType::Slide { .. }, "s") => ChildNoteType::SlideRelayPoint {
+            lane: lane()?,
+            width: width()?,
+        },
+        (ParentNoteType::Slide { .. }, "c") => ChildNoteType::SlideControlPoint {
+            lane: lane()?,
+            width: width()?,
+        },
+        (ParentNoteType::AirHold { .. }, "s") => ChildNoteType::AirHoldRelayPoint,
+        (ParentNoteType::AirHold { .. }, "c") => ChildNoteType::AirHoldControlPoint,
+        (ParentNoteType::AirSlide { .. }, "s") => ChildNoteType::AirSlideRelayPoint {
+            lane: lane()?,
+            width: width()?,
+            height: height()?,
+        },
+        (ParentNoteType::AirSlide { .. }, "c") => ChildNoteType::AirSlideControlPoint {
+            lane: lane()?,
+            width: width()?,
+            height: height()?,
+        },
+        (ParentNoteType::AirCrush { .. }, "c") => ChildNoteType::AirCrushEndPoint {
+            lane: lane()?,
+            width: width()?,
+            height: height()?,
+        },
+        _ => {
+            return Err(ParseError::at_field(
+                line,
+                kind,
+                ParseErrorKind::UnknownNoteType(kind.to_string()),
+            ));
+        }
+    };
+
+    Ok(ChildNote {
+        note_type,
+        offset_tick,
+    })
 }
 
-impl<T: AsRef<str>> From<T> for UGCChart {
-    fn from(value: T) -> Self {
-        let mut metadata = HashMap::new();
+impl UGCChart {
+    /// Parse a complete UGC chart, failing on the first line that can't be parsed
+    pub fn from_string(content: &str) -> Result<Self, ParseError> {
+        Self::parse(content, ParseMode::Strict).map(|parsed| parsed.value)
+    }
+
+    /// Parse a complete UGC chart, skipping bad lines in lenient mode
+    pub fn parse(content: &str, mode: ParseMode) -> Result<Parsed<Self>, ParseError> {
+        let mut header = UgcHeader::default();
         let mut timelines = HashMap::new();
+        let mut diagnostics = Diagnostics::new(mode);
 
-        let mut lines = value.as_ref().lines();
         let mut current_timeline = 0u32;
+        // set when a parent note was skipped, so its children aren't attached to the one before it
+        let mut orphaned = false;
 
-        while let Some(line) = lines.next() {
-            let line = line.trim();
+        for (index, raw_line) in content.lines().enumerate() {
+            let line = raw_line.trim();
 
             if line.is_empty() || !line.starts_with('@') && !line.starts_with('#') {
                 continue;
             }
 
-            if line.starts_with('@') {
-                let parts = line.split(' ').collect::<Vec<_>>();
+            if let Some(command) = line.strip_prefix('@') {
+                let (key, rest) = command
+                    .split_once(['\t', ' '])
+                    .unwrap_or((command, &command[command.len()..]));
 
-                let key = &parts[0][1..];
-                let rest = &parts[1..].join(" ");
-
-                match key {
-                    "USETIL" => {
-                        if let Ok(new_timeline_value) = rest.parse::<u32>() {
+                let result = match key {
+                    "USETIL" => match rest.trim().parse::<u32>() {
+                        Ok(new_timeline_value) => {
                             current_timeline = new_timeline_value;
+                            orphaned = false;
+                            Ok(())
+                        }
+                        Err(_) => Err(ParseError::invalid(raw_line, rest.trim(), "timeline")),
+                    },
+                    _ => header.parse_command(raw_line, key, rest),
+                };
+                if let Err(error) = result {
+                    diagnostics.report(error.on_line(index + 1))?;
+                }
+            } else if is_child_line(line) {
+                let parent = timelines
+                    .get_mut(&current_timeline)
+                    .and_then(|notes: &mut Vec<ParentNote>| notes.last_mut())
+                    .filter(|_| !orphaned);
+                let result = match parent {
+                    Some(parent) => parse_child_note(raw_line, &parent.note_type).map(|child| {
+                        if let Some(children) = parent.note_type.children_mut() {
+                            children.push(child);
                         }
+                    }),
+                    None => Err(ParseError::at_field(
+                        raw_line,
+                        line,
+                        ParseErrorKind::OrphanChildNote,
+                    )),
+                };
+                if let Err(error) = result {
+                    diagnostics.report(error.on_line(index + 1))?;
+                }
+            } else {
+                match parse_parent_note(raw_line) {
+                    Ok(parent_note) => {
+                        orphaned = false;
+                        timelines
+                            .entry(current_timeline)
+                            .or_insert_with(Vec::new)
+                            .push(parent_note);
                     }
-                    _ => {
-                        metadata.insert(String::from(key), rest.clone());
+                    Err(error) => {
+                        orphaned = true;
+                        diagnostics.report(error.on_line(index + 1))?;
                     }
                 }
-            } else if line.starts_with('#') {
-                let parent_note = parse_parent_note(&line[1..]);
-
-                match parent_note {
-                    Ok(parent_note) => timelines
-                        .entry(current_timeline)
-                        .or_insert_with(|| vec![])
-                        .push(parent_note),
-                    Err(_) => {}
+            }
+        }
+
+        Ok(diagnostics.finish(Self { header, timelines }))
+    }
+}
+
+fn to_ir_direction(direction: &AirDirection) -> ir::AirDirection {
+    match direction {
+        AirDirection::Up => ir::AirDirection::Up,
+        AirDirection::UpRight => ir::AirDirection::UpRight,
+        AirDirection::UpLeft => ir::AirDirection::UpLeft,
+        AirDirection::Down => ir::AirDirection::Down,
+        AirDirection::DownRight => ir::AirDirection::DownRight,
+        AirDirection::DownLeft => ir::AirDirection::DownLeft,
+    }
+}
+
+fn from_ir_direction(direction: ir::AirDirection) -> AirDirection {
+    match direction {
+        ir::AirDirection::Up => AirDirection::Up,
+        ir::AirDirection::UpRight => AirDirection::UpRight,
+        ir::AirDirection::UpLeft => AirDirection::UpLeft,
+        ir::AirDirection::Down => AirDirection::Down,
+        ir::AirDirection::DownRight => AirDirection::DownRight,
+        ir::AirDirection::DownLeft => AirDirection::DownLeft,
+    }
+}
+
+fn to_ir_effect(direction: &ExTapEffectDirection) -> ExTapEffect {
+    match direction {
+        ExTapEffectDirection::Up => ExTapEffect::Up,
+        ExTapEffectDirection::Down => ExTapEffect::Down,
+        ExTapEffectDirection::Center => ExTapEffect::Center,
+        ExTapEffectDirection::Clockwise => ExTapEffect::Clockwise,
+        ExTapEffectDirection::Counterclockwise => ExTapEffect::Counterclockwise,
+        ExTapEffectDirection::Right => ExTapEffect::Right,
+        ExTapEffectDirection::Left => ExTapEffect::Left,
+        ExTapEffectDirection::InOut => ExTapEffect::InOut,
+    }
+}
+
+fn from_ir_effect(effect: ExTapEffect) -> ExTapEffectDirection {
+    match effect {
+        ExTapEffect::Up => ExTapEffectDirection::Up,
+        ExTapEffect::Down => ExTapEffectDirection::Down,
+        ExTapEffect::Center => ExTapEffectDirection::Center,
+        ExTapEffect::Clockwise => ExTapEffectDirection::Clockwise,
+        ExTapEffect::Counterclockwise => ExTapEffectDirection::Counterclockwise,
+        ExTapEffect::Right => ExTapEffectDirection::Right,
+        ExTapEffect::Left => ExTapEffectDirection::Left,
+        ExTapEffect::InOut => ExTapEffectDirection::InOut,
+    }
+}
+
+/// UGC air crush colors in the same order as [`ir::AirCrushColor`], Gray has no UGC equivalent
+const CRUSH_COLORS: [(AirCrushColor, ir::AirCrushColor); 16] = [
+    (AirCrushColor::Normal, ir::AirCrushColor::Normal),
+    (AirCrushColor::Red, ir::AirCrushColor::Red),
+    (AirCrushColor::Orange, ir::AirCrushColor::Orange),
+    (AirCrushColor::Yellow, ir::AirCrushColor::Yellow),
+    (AirCrushColor::YellowGreen, ir::AirCrushColor::YellowGreen),
+    (AirCrushColor::Green, ir::AirCrushColor::Green),
+    (AirCrushColor::Cyan, ir::AirCrushColor::Cyan),
+    (AirCrushColor::Sky, ir::AirCrushColor::Sky),
+    (AirCrushColor::Light, ir::AirCrushColor::Light),
+    (AirCrushColor::Blue, ir::AirCrushColor::Blue),
+    (AirCrushColor::BluePurple, ir::AirCrushColor::BluePurple),
+    (AirCrushColor::Magenta, ir::AirCrushColor::Magenta),
+    (AirCrushColor::Pink, ir::AirCrushColor::Pink),
+    (AirCrushColor::White, ir::AirCrushColor::White),
+    (AirCrushColor::Black, ir::AirCrushColor::Black),
+    (AirCrushColor::Transparent, ir::AirCrushColor::Transparent),
+];
+
+/// UGC heights are stored as height×10
+fn to_ir_height(height: u16) -> f32 {
+    height as f32 / 10.0
+}
+
+fn from_ir_height(height: f32) -> u16 {
+    (height * 10.0).round() as u16
+}
+
+impl UGCChart {
+    /// Timing of this chart, from the `@TICKS`, `@BPM` and `@BEAT` headers
+    pub fn tempo_map(&self) -> TempoMap {
+        let header = &self.header;
+        let ticks_per_beat = header.ticks.unwrap_or(ir::TICKS_PER_BEAT);
+        let mut tempo = TempoMap::new(ticks_per_beat, header.main_bpm.unwrap_or(120.0));
+
+        // BPM changes are placed by measure, so every measure length has to be known first
+        for beat in &header.beats {
+            tempo.set_time_signature(beat.bar as u32, beat.numerator, beat.denominator);
+        }
+        for bpm in &header.bpms {
+            let tick = tempo.measure_to_tick(bpm.bar as u32, bpm.tick as u32);
+            tempo.set_bpm(tick, bpm.bpm);
+        }
+
+        tempo
+    }
+
+    /// Scroll speeds of this chart, from the `@TIL`, `@SPDMOD` and `@MAINTIL` headers
+    pub fn scroll_map(&self) -> ScrollMap {
+        let tempo = self.tempo_map();
+        let tick = |bar: u64, tick: u64| tempo.measure_to_tick(bar as u32, tick as u32);
+
+        let mut scroll = ScrollMap::new(self.header.main_timeline.unwrap_or(0));
+        for til in &self.header.timeline_speeds {
+            scroll.set_speed(til.timeline, tick(til.bar, til.tick), til.speed);
+        }
+        for modifier in &self.header.speed_modifiers {
+            scroll.set_modifier(tick(modifier.bar, modifier.tick), modifier.speed);
+        }
+        scroll
+    }
+}
+
+impl ChuniChart for UGCChart {
+    fn to_ir(&self) -> Conversion<IrChart> {
+        let mut warnings = Vec::new();
+        let mut chart = IrChart::default();
+
+        let header = &self.header;
+        chart.metadata = IrMetadata {
+            title: header.title.clone(),
+            artist: header.artist.clone(),
+            genre: header.genre.clone(),
+            designer: header.designer.clone(),
+            level: header.level.clone(),
+            song_id: header.song_id.clone(),
+            bgm: header.bgm.clone(),
+            jacket: header.jacket.clone(),
+            difficulty: header.difficulty,
+            constant: header.constant,
+            bgm_offset: header.bgm_offset,
+        };
+
+        let tempo = self.tempo_map();
+        chart.set_tempo_map(&tempo);
+        let to_ir = |bar: u64, tick: u64| {
+            let tick = tempo.measure_to_tick(bar as u32, tick as u32);
+            rescale_tick(tick, tempo.ticks_per_beat(), ir::TICKS_PER_BEAT).0
+        };
+
+        chart.scroll = self
+            .scroll_map()
+            .map_ticks(|tick| rescale_tick(tick, tempo.ticks_per_beat(), ir::TICKS_PER_BEAT).0);
+
+        let mut timelines: Vec<_> = self.timelines.iter().collect();
+        timelines.sort_unstable_by_key(|(timeline, _)| **timeline);
+        let parents = timelines
+            .into_iter()
+            .flat_map(|(timeline, parents)| parents.iter().map(move |parent| (*timeline, parent)));
+        for (timeline, parent) in parents {
+            let tick = to_ir(parent.bar, parent.tick);
+            let child_tick = |child: &ChildNote| to_ir(parent.bar, parent.tick + child.offset_tick);
+
+            let (lane, width, mut kind) = match &parent.note_type {
+                ParentNoteType::Click => {
+                    warnings.push(ConversionWarning::at(
+                        tick,
+                        "skipping click note, it has no equivalent",
+                    ));
+                    continue;
+                }
+                ParentNoteType::Tap { lane, width } => (lane, width, IrNoteKind::Tap),
+                ParentNoteType::ExTap {
+                    lane,
+                    width,
+                    direction,
+                } => (
+                    lane,
+                    width,
+                    IrNoteKind::ExTap {
+                        effect: Some(to_ir_effect(direction)),
+                    },
+                ),
+                ParentNoteType::Flick { lane, width, .. } => (lane, width, IrNoteKind::Flick),
+                ParentNoteType::Damage { lane, width } => (lane, width, IrNoteKind::Mine),
+                ParentNoteType::Hold {
+                    lane,
+                    width,
+                    children,
+                } => {
+                    let end_tick = children.iter().map(child_tick).max().unwrap_or(tick);
+                    (
+                        lane,
+                        width,
+                        IrNoteKind::Hold {
+                            end_tick,
+                            ex: false,
+                            effect: None,
+                        },
+                    )
                 }
-            } else {
-                unreachable!()
+                ParentNoteType::Slide {
+                    lane,
+                    width,
+                    children,
+                } => {
+                    let points = children
+                        .iter()
+                        .filter_map(|child| {
+                            let (lane, width, kind) = match child.note_type {
+                                ChildNoteType::SlideRelayPoint { lane, width } => {
+                                    (lane, width, SlidePointKind::Relay)
+                                }
+                                ChildNoteType::SlideControlPoint { lane, width } => {
+                                    (lane, width, SlidePointKind::Control)
+                                }
+                                _ => return None,
+                            };
+                            Some(SlidePoint {
+                                tick: child_tick(child),
+                                lane,
+                                width,
+                                kind,
+                            })
+                        })
+                        .collect();
+                    (
+                        lane,
+                        width,
+                        IrNoteKind::Slide {
+                            ex: false,
+                            effect: None,
+                            head: SlidePointKind::Relay,
+                            points,
+                        },
+                    )
+                }
+                ParentNoteType::Air {
+                    lane,
+                    width,
+                    direction,
+                    color,
+                } => (
+                    lane,
+                    width,
+                    IrNoteKind::Air {
+                        direction: to_ir_direction(direction),
+                        inverted: matches!(color, Some(AirColor::Inverted)),
+                        parent: None,
+                    },
+                ),
+                ParentNoteType::AirHold {
+                    lane,
+                    width,
+                    color,
+                    children,
+                } => {
+                    let points = children
+                        .iter()
+                        .map(|child| AirHoldPoint {
+                            tick: child_tick(child),
+                            action: !matches!(child.note_type, ChildNoteType::AirHoldControlPoint),
+                        })
+                        .collect();
+                    (
+                        lane,
+                        width,
+                        IrNoteKind::AirHold {
+                            inverted: matches!(color, Some(AirColor::Inverted)),
+                            ground: false,
+                            points,
+                            parent: None,
+                        },
+                    )
+                }
+                ParentNoteType::AirSlide {
+                    lane,
+                    width,
+                    height,
+                    color,
+                    children,
+                } => (
+                    lane,
+                    width,
+                    IrNoteKind::AirSlide {
+                        height: to_ir_height(*height),
+                        inverted: matches!(color, Some(AirColor::Inverted)),
+                        points: air_slide_points(children, child_tick),
+                        parent: None,
+                    },
+                ),
+                ParentNoteType::AirCrush {
+                    lane,
+                    width,
+                    height,
+                    color,
+                    interval,
+                    children,
+                } => {
+                    let color = color
+                        .as_ref()
+                        .and_then(|color| CRUSH_COLORS.iter().find(|(ugc, _)| ugc == color))
+                        .map_or(ir::AirCrushColor::Normal, |(_, color)| *color);
+                    (
+                        lane,
+                        width,
+                        IrNoteKind::AirCrush {
+                            height: to_ir_height(*height),
+                            color,
+                            // a missing numeric interval is `$`, only the start note generates combo
+                            interval: interval
+                                .map_or(CrushInterval::StartOnly, CrushInterval::Ticks),
+                            points: air_slide_points(children, child_tick),
+                        },
+                    )
+                }
+            };
+
+            if !sort_points(&mut kind) {
+                warnings.push(ConversionWarning::at(
+                    tick,
+                    "child notes out of order, sorting them by offset",
+                ));
+            }
+            let sustained = matches!(
+                kind,
+                IrNoteKind::Hold { .. }
+                    | IrNoteKind::Slide { .. }
+                    | IrNoteKind::AirHold { .. }
+                    | IrNoteKind::AirSlide { .. }
+                    | IrNoteKind::AirCrush { .. }
+            );
+            let note = IrNote {
+                tick,
+                lane: *lane,
+                width: *width,
+                timeline,
+                kind,
+            };
+            if sustained && note.end_tick() == tick {
+                warnings.push(ConversionWarning::at(
+                    tick,
+                    "sustained note without an end point, it will have no length",
+                ));
             }
+            chart.notes.push(note);
         }
 
-        Self {
-            metadata,
-            timelines,
+        merge_ex_heads(&mut chart.notes);
+        chart.sort_notes();
+        chart.link_air_parents();
+        Conversion::new(chart, warnings)
+    }
+
+    fn from_ir(chart: &IrChart) -> Conversion<Self> {
+        let mut warnings = Vec::new();
+        let ir_metadata = &chart.metadata;
+        let tempo = chart.tempo_map();
+        let mut header = UgcHeader {
+            version: Some(8),
+            title: ir_metadata.title.clone(),
+            artist: ir_metadata.artist.clone(),
+            genre: ir_metadata.genre.clone(),
+            designer: ir_metadata.designer.clone(),
+            difficulty: ir_metadata.difficulty,
+            level: ir_metadata.level.clone(),
+            constant: ir_metadata.constant,
+            song_id: ir_metadata.song_id.clone(),
+            bgm: ir_metadata.bgm.clone(),
+            bgm_offset: ir_metadata.bgm_offset,
+            jacket: ir_metadata.jacket.clone(),
+            ticks: Some(ir::TICKS_PER_BEAT),
+            main_bpm: chart.bpm_changes.first().map(|bpm| bpm.bpm),
+            bpms: chart
+                .bpm_changes
+                .iter()
+                .map(|bpm| {
+                    let (bar, tick) = tempo.tick_to_measure(bpm.tick);
+                    BpmDefinition {
+                        bar: bar as u64,
+                        tick: tick as u64,
+                        bpm: bpm.bpm,
+                    }
+                })
+                .collect(),
+            beats: chart
+                .time_signatures
+                .iter()
+                .map(|beat| BeatDefinition {
+                    bar: tempo.tick_to_measure(beat.tick).0 as u64,
+                    numerator: beat.numerator,
+                    denominator: beat.denominator,
+                })
+                .collect(),
+            ..Default::default()
+        };
+
+        let scroll = &chart.scroll;
+        let bar_tick = |tick| {
+            let (bar, tick) = tempo.tick_to_measure(tick);
+            (bar as u64, tick as u64)
+        };
+        if scroll.timelines().next().is_some() || !scroll.modifiers().is_empty() {
+            header.main_timeline = Some(scroll.main_timeline());
+        }
+        for timeline in scroll.timelines() {
+            for change in scroll.speed_changes(timeline) {
+                let (bar, tick) = bar_tick(change.tick);
+                header.timeline_speeds.push(TimelineDefinition {
+                    timeline,
+                    bar,
+                    tick,
+                    speed: change.speed,
+                });
+            }
+        }
+        for change in scroll.modifiers() {
+            let (bar, tick) = bar_tick(change.tick);
+            header.speed_modifiers.push(SpeedDefinition {
+                bar,
+                tick,
+                speed: change.speed,
+            });
         }
+
+        let mut timelines: HashMap<u32, Vec<ParentNote>> = HashMap::new();
+        for note in &chart.notes {
+            let (bar, tick) = tempo.tick_to_measure(note.tick);
+            let (lane, width) = (note.lane, note.width);
+            let backwards = Cell::new(false);
+            let offset = |child_tick: u32| {
+                let offset = child_tick.checked_sub(note.tick).unwrap_or_else(|| {
+                    backwards.set(true);
+                    0
+                });
+                offset as u64
+            };
+            // UGC has no ExTap heads, an ExTap is stacked on the start of the note instead
+            let mut ex_head = None;
+
+            let note_type = match &note.kind {
+                IrNoteKind::Tap => ParentNoteType::Tap { lane, width },
+                IrNoteKind::ExTap { effect } => ParentNoteType::ExTap {
+                    lane,
+                    width,
+                    direction: from_ir_effect(effect.unwrap_or(DEFAULT_EXTAP_EFFECT)),
+                },
+                IrNoteKind::Flick => ParentNoteType::Flick {
+                    lane,
+                    width,
+                    direction: FlickEffectDirection::Auto,
+                },
+                IrNoteKind::Mine => ParentNoteType::Damage { lane, width },
+                IrNoteKind::Hold {
+                    end_tick,
+                    ex,
+                    effect,
+                } => {
+                    ex_head = ex.then(|| effect.unwrap_or(DEFAULT_EXTAP_EFFECT));
+                    ParentNoteType::Hold {
+                        lane,
+                        width,
+                        children: vec![ChildNote {
+                            note_type: ChildNoteType::HoldEndPoint,
+                            offset_tick: offset(*end_tick),
+                        }],
+                    }
+                }
+                IrNoteKind::Slide {
+                    ex,
+                    effect,
+                    head,
+                    points,
+                } => {
+                    let invisible = SlidePointKind::Invisible;
+                    if *head == invisible || points.iter().any(|point| point.kind == invisible) {
+                        warnings.push(ConversionWarning::at(
+                            note.tick,
+                            "UGC slides have no invisible points, writing them as relays",
+                        ));
+                    }
+                    ex_head = ex.then(|| effect.unwrap_or(DEFAULT_EXTAP_EFFECT));
+                    let children = points
+                        .iter()
+                        .map(|point| ChildNote {
+                            note_type: match point.kind {
+                                SlidePointKind::Control => ChildNoteType::SlideControlPoint {
+                                    lane: point.lane,
+                                    width: point.width,
+                                },
+                                _ => ChildNoteType::SlideRelayPoint {
+                                    lane: point.lane,
+                                    width: point.width,
+                                },
+                            },
+                            offset_tick: offset(point.tick),
+                        })
+                        .collect();
+                    ParentNoteType::Slide {
+                        lane,
+                        width,
+                        children,
+                    }
+                }
+                IrNoteKind::Air {
+                    direction,
+                    inverted,
+                    ..
+                } => ParentNoteType::Air {
+                    lane,
+                    width,
+                    direction: from_ir_direction(*direction),
+                    color: Some(air_color(*inverted)),
+                },
+                IrNoteKind::AirHold {
+                    inverted,
+                    ground,
+                    points,
+                    ..
+                } => {
+                    if *ground {
+                        warnings.push(ConversionWarning::at(
+                            note.tick,
+                            "UGC air holds have no ground bar, writing a normal air hold",
+                        ));
+                    }
+                    let children = points
+                        .iter()
+                        .map(|point| ChildNote {
+                            note_type: if point.action {
+                                ChildNoteType::AirHoldRelayPoint
+                            } else {
+                                ChildNoteType::AirHoldControlPoint
+                            },
+                            offset_tick: offset(point.tick),
+                        })
+                        .collect();
+                    ParentNoteType::AirHold {
+                        lane,
+                        width,
+                        color: Some(air_color(*inverted)),
+                        children,
+                    }
+                }
+                IrNoteKind::AirSlide {
+                    height,
+                    inverted,
+                    points,
+                    ..
+                } => ParentNoteType::AirSlide {
+                    lane,
+                    width,
+                    height: from_ir_height(*height),
+                    color: Some(air_color(*inverted)),
+                    children: points
+                        .iter()
+                        .map(|point| ChildNote {
+                            note_type: match point.kind {
+                                SlidePointKind::Relay => ChildNoteType::AirSlideRelayPoint {
+                                    lane: point.lane,
+                                    width: point.width,
+                                    height: from_ir_height(point.height),
+                                },
+                                _ => ChildNoteType::AirSlideControlPoint {
+                                    lane: point.lane,
+                                    width: point.width,
+                                    height: from_ir_height(point.height),
+                                },
+                            },
+                            offset_tick: offset(point.tick),
+                        })
+                        .collect(),
+                },
+                IrNoteKind::AirCrush {
+                    height,
+                    color,
+                    interval,
+                    points,
+                } => {
+                    let ugc_color = match CRUSH_COLORS.iter().position(|(_, ir)| ir == color) {
+                        Some(index) => CRUSH_COLORS[index].0.clone(),
+                        None => {
+                            warnings.push(ConversionWarning::at(
+                                note.tick,
+                                format!(
+                                    "UGC has no {:?} air crushes, using the default color",
+                                    color
+                                ),
+                            ));
+                            AirCrushColor::Normal
+                        }
+                    };
+                    ParentNoteType::AirCrush {
+                        lane,
+                        width,
+                        height: from_ir_height(*height),
+                        color: Some(ugc_color),
+                        interval: match interval {
+                            CrushInterval::Ticks(ticks) => Some(*ticks),
+                            CrushInterval::StartOnly => None,
+                        },
+                        children: points
+                            .iter()
+                            .map(|point| ChildNote {
+                                note_type: ChildNoteType::AirCrushEndPoint {
+                                    lane: point.lane,
+                                    width: point.width,
+                                    height: from_ir_height(point.height),
+                                },
+                                offset_tick: offset(point.tick),
+                            })
+                            .collect(),
+                    }
+                }
+            };
+            if backwards.get() {
+                warnings.push(ConversionWarning::at(
+                    note.tick,
+                    "points before the start of their note, moving them onto it",
+                ));
+            }
+
+            let parents = timelines.entry(note.timeline).or_default();
+            parents.push(ParentNote {
+                note_type,
+                bar: bar as u64,
+                tick: tick as u64,
+            });
+            if let Some(effect) = ex_head {
+                parents.push(ParentNote {
+                    note_type: ParentNoteType::ExTap {
+                        lane,
+                        width,
+                        direction: from_ir_effect(effect),
+                    },
+                    bar: bar as u64,
+                    tick: tick as u64,
+                });
+            }
+        }
+
+        Conversion::new(UGCChart { header, timelines }, warnings)
+    }
+}
+
+/// Turns ExTaps stacked on the start of a hold or slide into an ExTap head
+///
+/// This is how UGC writes the ExHolds and ExSlides of other formats.
+fn merge_ex_heads(notes: &mut Vec<IrNote>) {
+    let position = |note: &IrNote| (note.tick, note.lane, note.width, note.timeline);
+    let mut heads: HashMap<_, usize> = notes
+        .iter()
+        .enumerate()
+        .filter(|(_, note)| {
+            matches!(
+                note.kind,
+                IrNoteKind::Hold { .. } | IrNoteKind::Slide { .. }
+            )
+        })
+        .map(|(index, note)| (position(note), index))
+        .collect();
+
+    let mut stacked = HashSet::new();
+    for index in 0..notes.len() {
+        let IrNoteKind::ExTap { effect } = notes[index].kind else {
+            continue;
+        };
+        // each head takes a single ExTap, any other one stays a separate note
+        let Some(head) = heads.remove(&position(&notes[index])) else {
+            continue;
+        };
+        if let IrNoteKind::Hold {
+            ex,
+            effect: head_effect,
+            ..
+        }
+        | IrNoteKind::Slide {
+            ex,
+            effect: head_effect,
+            ..
+        } = &mut notes[head].kind
+        {
+            *ex = true;
+            *head_effect = effect;
+        }
+        stacked.insert(index);
+    }
+
+    let mut index = 0;
+    notes.retain(|_| {
+        index += 1;
+        !stacked.contains(&(index - 1))
+    });
+}
+
+fn air_color(inverted: bool) -> AirColor {
+    if inverted {
+        AirColor::Inverted
+    } else {
+        AirColor::Normal
+    }
+}
+
+/// Puts the points of a sustained note in tick order, `false` if they weren't
+///
+/// Children are kept in file order, which nothing forces to go forward.
+fn sort_points(kind: &mut IrNoteKind) -> bool {
+    match kind {
+        IrNoteKind::Slide { points, .. } => {
+            let sorted = points.is_sorted_by_key(|point| point.tick);
+            points.sort_by_key(|point| point.tick);
+            sorted
+        }
+        IrNoteKind::AirHold { points, .. } => {
+            let sorted = points.is_sorted_by_key(|point| point.tick);
+            points.sort_by_key(|point| point.tick);
+            sorted
+        }
+        IrNoteKind::AirSlide { points, .. } | IrNoteKind::AirCrush { points, .. } => {
+            let sorted = points.is_sorted_by_key(|point| point.tick);
+            points.sort_by_key(|point| point.tick);
+            sorted
+        }
+        _ => true,
+    }
+}
+
+fn air_slide_points(
+    children: &[ChildNote],
+    child_tick: impl Fn(&ChildNote) -> u32,
+) -> Vec<AirSlidePoint> {
+    children
+        .iter()
+        .filter_map(|child| {
+            let (lane, width, height, kind) = match child.note_type {
+                ChildNoteType::AirSlideRelayPoint {
+                    lane,
+                    width,
+                    height,
+                }
+                | ChildNoteType::AirCrushEndPoint {
+                    lane,
+                    width,
+                    height,
+                } => (lane, width, height, SlidePointKind::Relay),
+                ChildNoteType::AirSlideControlPoint {
+                    lane,
+                    width,
+                    height,
+                } => (lane, width, height, SlidePointKind::Control),
+                _ => return None,
+            };
+            Some(AirSlidePoint {
+                tick: child_tick(child),
+                lane,
+                width,
+                height: to_ir_height(height),
+                kind,
+            })
+        })
+        .collect()
+}
+
+/// Letters used for the enum fields of note lines, the reverse of [`parse_parent_note`]
+impl ExTapEffectDirection {
+    fn code(&self) -> &'static str {
+        match self {
+            ExTapEffectDirection::Up => "U",
+            ExTapEffectDirection::Down => "D",
+            ExTapEffectDirection::Center => "C",
+            ExTapEffectDirection::Clockwise => "A",
+            ExTapEffectDirection::Counterclockwise => "W",
+            ExTapEffectDirection::Right => "L",
+            ExTapEffectDirection::Left => "R",
+            ExTapEffectDirection::InOut => "I",
+        }
+    }
+}
+
+impl FlickEffectDirection {
+    fn code(&self) -> &'static str {
+        match self {
+            FlickEffectDirection::Auto => "A",
+            FlickEffectDirection::Right => "L",
+            FlickEffectDirection::Left => "R",
+        }
+    }
+}
+
+impl AirDirection {
+    fn code(&self) -> &'static str {
+        match self {
+            AirDirection::Up => "UC",
+            AirDirection::UpRight => "UL",
+            AirDirection::UpLeft => "UR",
+            AirDirection::Down => "DC",
+            AirDirection::DownRight => "DL",
+            AirDirection::DownLeft => "DR",
+        }
+    }
+}
+
+impl AirColor {
+    fn code(&self) -> &'static str {
+        match self {
+            AirColor::Normal => "N",
+            AirColor::Inverted => "I",
+        }
+    }
+}
+
+impl AirCrushColor {
+    fn code(&self) -> &'static str {
+        match self {
+            AirCrushColor::Normal => "0",
+            AirCrushColor::Red => "1",
+            AirCrushColor::Orange => "2",
+            AirCrushColor::Yellow => "3",
+            AirCrushColor::YellowGreen => "4",
+            AirCrushColor::Green => "5",
+            AirCrushColor::Cyan => "6",
+            AirCrushColor::Sky => "7",
+            AirCrushColor::Light => "8",
+            AirCrushColor::Blue => "9",
+            AirCrushColor::BluePurple => "A",
+            AirCrushColor::Magenta => "Y",
+            AirCrushColor::Pink => "B",
+            AirCrushColor::White => "C",
+            AirCrushColor::Black => "D",
+            AirCrushColor::Transparent => "Z",
+        }
+    }
+}
+
+/// Single uppercase base-36 digit, for lanes and widths
+fn digit(value: u8) -> char {
+    char::from_digit(value as u32 % 36, 36)
+        .unwrap_or('0')
+        .to_ascii_uppercase()
+}
+
+/// Two-digit base-36 height
+fn height_digits(height: u16) -> String {
+    let height = height as u32 % (36 * 36);
+    [height / 36, height % 36]
+        .iter()
+        .map(|&value| digit(value as u8))
+        .collect()
+}
+
+impl fmt::Display for ChildNote {
+    /// Writes the `#OffsetTick>...` line, without a trailing newline
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "#{}>", self.offset_tick)?;
+        match &self.note_type {
+            ChildNoteType::HoldEndPoint | ChildNoteType::AirHoldRelayPoint => write!(f, "s"),
+            ChildNoteType::AirHoldControlPoint => write!(f, "c"),
+            ChildNoteType::SlideRelayPoint { lane, width } => {
+                write!(f, "s{}{}", digit(*lane), digit(*width))
+            }
+            ChildNoteType::SlideControlPoint { lane, width } => {
+                write!(f, "c{}{}", digit(*lane), digit(*width))
+            }
+            ChildNoteType::AirSlideRelayPoint {
+                lane,
+                width,
+                height,
+            } => write!(
+                f,
+                "s{}{}{}",
+                digit(*lane),
+                digit(*width),
+                height_digits(*height)
+            ),
+            ChildNoteType::AirSlideControlPoint {
+                lane,
+                width,
+                height,
+            }
+            | ChildNoteType::AirCrushEndPoint {
+                lane,
+                width,
+                height,
+            } => write!(
+                f,
+                "c{}{}{}",
+                digit(*lane),
+                digit(*width),
+                height_digits(*height)
+            ),
+        }
+    }
+}
+
+impl fmt::Display for ParentNote {
+    /// Writes the `#BarTick:...` line followed by the lines of its children
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "#{}'{}:", self.bar, self.tick)?;
+        let air_color = |color: &Option<AirColor>| color.as_ref().map_or("", AirColor::code);
+        match &self.note_type {
+            ParentNoteType::Click => write!(f, "c")?,
+            ParentNoteType::Tap { lane, width } => write!(f, "t{}{}", digit(*lane), digit(*width))?,
+            ParentNoteType::ExTap {
+                lane,
+                width,
+                direction,
+            } => write!(f, "x{}{}{}", digit(*lane), digit(*width), direction.code())?,
+            ParentNoteType::Flick {
+                lane,
+                width,
+                direction,
+            } => write!(f, "f{}{}{}", digit(*lane), digit(*width), direction.code())?,
+            ParentNoteType::Damage { lane, width } => {
+                write!(f, "d{}{}", digit(*lane), digit(*width))?
+            }
+            ParentNoteType::Hold { lane, width, .. } => {
+                write!(f, "h{}{}", digit(*lane), digit(*width))?
+            }
+            ParentNoteType::Slide { lane, width, .. } => {
+                write!(f, "s{}{}", digit(*lane), digit(*width))?
+            }
+            ParentNoteType::Air {
+                lane,
+                width,
+                direction,
+                color,
+            } => write!(
+                f,
+                "a{}{}{}{}",
+                digit(*lane),
+                digit(*width),
+                direction.code(),
+                air_color(color)
+            )?,
+            ParentNoteType::AirHold {
+                lane, width, color, ..
+            } => write!(f, "H{}{}{}", digit(*lane), digit(*width), air_color(color))?,
+            ParentNoteType::AirSlide {
+                lane,
+                width,
+                height,
+                color,
+                ..
+            } => write!(
+                f,
+                "S{}{}{}{}",
+                digit(*lane),
+                digit(*width),
+                height_digits(*height),
+                air_color(color)
+            )?,
+            ParentNoteType::AirCrush {
+                lane,
+                width,
+                height,
+                color,
+                interval,
+                ..
+            } => {
+                write!(
+                    f,
+                    "C{}{}{}{}",
+                    digit(*lane),
+                    digit(*width),
+                    height_digits(*height),
+                    color.as_ref().map_or("", AirCrushColor::code)
+                )?;
+                match interval {
+                    Some(interval) => write!(f, ",{}", interval)?,
+                    None => write!(f, ",$")?,
+                }
+            }
+        }
+
+        for child in self.note_type.children().into_iter().flatten() {
+            write!(f, "\n{}", child)?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for UgcHeader {
+    /// Writes every command as a tab-separated `@` line
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fn command(
+            f: &mut fmt::Formatter<'_>,
+            key: &str,
+            value: Option<impl fmt::Display>,
+        ) -> fmt::Result {
+            match value {
+                Some(value) => writeln!(f, "@{}\t{}", key, value),
+                None => Ok(()),
+            }
+        }
+
+        command(f, "VER", self.version)?;
+        command(f, "EXVER", self.ex_version)?;
+        command(f, "TITLE", self.title.as_ref())?;
+        command(f, "SORT", self.sort.as_ref())?;
+        command(f, "ARTIST", self.artist.as_ref())?;
+        command(f, "GENRE", self.genre.as_ref())?;
+        command(f, "DESIGN", self.designer.as_ref())?;
+        command(f, "DIFF", self.difficulty)?;
+        command(f, "LEVEL", self.level.as_ref())?;
+        command(f, "CONST", self.constant)?;
+        command(f, "SONGID", self.song_id.as_ref())?;
+        command(f, "BGM", self.bgm.as_ref())?;
+        command(f, "BGMOFS", self.bgm_offset)?;
+        if let Some((start, end)) = self.bgm_preview {
+            writeln!(f, "@BGMPRV\t{}\t{}", start, end)?;
+        }
+        command(f, "JACKET", self.jacket.as_ref())?;
+        command(f, "FLDCOL", self.field_color)?;
+        for (key, rest) in &self.other {
+            command(f, key, Some(rest))?;
+        }
+        command(f, "TICKS", self.ticks)?;
+        command(f, "MAINBPM", self.main_bpm)?;
+        command(f, "MAINTIL", self.main_timeline)?;
+        for (flag, value) in &self.flags {
+            let value = if *value { "TRUE" } else { "FALSE" };
+            writeln!(f, "@FLAG\t{}\t{}", flag.name(), value)?;
+        }
+
+        for beat in &self.beats {
+            writeln!(
+                f,
+                "@BEAT\t{}\t{}\t{}",
+                beat.bar, beat.numerator, beat.denominator
+            )?;
+        }
+        for bpm in &self.bpms {
+            writeln!(f, "@BPM\t{}'{}\t{}", bpm.bar, bpm.tick, bpm.bpm)?;
+        }
+        for til in &self.timeline_speeds {
+            writeln!(
+                f,
+                "@TIL\t{}\t{}'{}\t{}",
+                til.timeline, til.bar, til.tick, til.speed
+            )?;
+        }
+        for speed in &self.speed_modifiers {
+            writeln!(f, "@SPDMOD\t{}'{}\t{}", speed.bar, speed.tick, speed.speed)?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for UGCChart {
+    /// Writes the header, then the notes of every timeline under its own `@USETIL`
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.header)?;
+
+        let mut ids: Vec<_> = self.timelines.keys().copied().collect();
+        ids.sort_unstable();
+        // notes before the first `@USETIL` already belong to timeline 0
+        let mut current_timeline = 0;
+        for id in ids {
+            writeln!(f)?;
+            if id != current_timeline {
+                writeln!(f, "@USETIL\t{}", id)?;
+                current_timeline = id;
+            }
+            for note in &self.timelines[&id] {
+                writeln!(f, "{}", note)?;
+            }
+        }
+        Ok(())
     }
 }
 
 #[cfg(test)]
 mod test {
     use super::*;
+    use crate::formats::chuni::c2s::C2SChart;
+    use crate::formats::chuni::ir::BpmChange;
+
+    #[test]
+    fn test_ir_round_trip() {
+        let chart = UGCChart::from_string(
+            "@TITLE Test\n@BPM 0'0 150\n#0'0:t44\n#0'480:x84U\n#0'480:a84UCN\n\
+             #1'0:f24A\n#1'240:d02\n#1'960:S442OI\n#2'0:C8422Z,0",
+        )
+        .unwrap();
+        let ir = chart.to_ir().value;
+
+        assert_eq!(ir.metadata.title.as_deref(), Some("Test"));
+        assert_eq!(
+            ir.bpm_changes,
+            vec![BpmChange {
+                tick: 0,
+                bpm: 150.0
+            }]
+        );
+        assert_eq!(ir.notes.len(), 7);
+        assert!(matches!(
+            ir.notes[2].kind,
+            IrNoteKind::Air {
+                parent: Some(1),
+                ..
+            }
+        ));
+        assert!(matches!(
+            ir.notes[5].kind,
+            IrNoteKind::AirSlide { height, inverted: true, .. } if height == 9.6
+        ));
+
+        let converted = UGCChart::from_ir(&ir).value;
+        assert_eq!(converted.to_ir().value, ir);
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        let content = "@TITLE Test\n#0'0:t44\n#0'480:x84Q\n#0'960:q44\n";
+
+        let error = UGCChart::from_string(content).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "line 3, column 11: invalid extap effect `Q`"
+        );
+
+        let parsed = UGCChart::parse(content, ParseMode::Lenient).unwrap();
+        assert_eq!(parsed.value.timelines[&0].len(), 1);
+        assert_eq!(parsed.warnings.len(), 2);
+        assert_eq!(
+            parsed.warnings[1].kind,
+            ParseErrorKind::UnknownNoteType("q".to_string())
+        );
+    }
+
+    #[test]
+    fn test_parse_header() {
+        let chart = UGCChart::from_string(
+            "@VER\t8\n@TITLE\tRe:Zero ～ The Last Song\n@DIFF\t3\n@LEVEL\t13+\n\
+             @CONST\t13.7\n@BGMPRV\t12.5\t27\n@FLAG\tCLICK\tTRUE\n@FLAG\tSOFFSET\tFALSE\n\
+             @BPM\t0'0\t150\n@BEAT\t0\t4\t4\n@BEAT\t2\t3\t4\n@BPM\t3'0\t200.5\n\
+             @TIL\t1\t1'240\t0.5\n@SPDMOD\t2'0\t2\n@WEATTR\t嘘\n",
+        )
+        .unwrap();
+        let header = &chart.header;
+
+        assert_eq!(header.version, Some(8));
+        assert_eq!(header.title.as_deref(), Some("Re:Zero ～ The Last Song"));
+        assert_eq!(header.difficulty, Some(3));
+        assert_eq!(header.level.as_deref(), Some("13+"));
+        assert_eq!(header.constant, Some(13.7));
+        assert_eq!(header.bgm_preview, Some((12.5, 27.0)));
+        assert_eq!(header.flag(UgcFlag::Click), Some(true));
+        assert_eq!(header.flag(UgcFlag::StartOffset), Some(false));
+        assert_eq!(header.flag(UgcFlag::ExLong), None);
+        assert_eq!(
+            header.bpms,
+            vec![
+                BpmDefinition {
+                    bar: 0,
+                    tick: 0,
+                    bpm: 150.0
+                },
+                BpmDefinition {
+                    bar: 3,
+                    tick: 0,
+                    bpm: 200.5
+                }
+            ]
+        );
+        assert_eq!(header.beats.len(), 2);
+        assert_eq!(
+            header.timeline_speeds,
+            vec![TimelineDefinition {
+                timeline: 1,
+                bar: 1,
+                tick: 240,
+                speed: 0.5
+            }]
+        );
+        assert_eq!(header.speed_modifiers.len(), 1);
+        assert_eq!(header.other, vec![("WEATTR".to_string(), "嘘".to_string())]);
+
+        // two 4/4 measures and a 3/4 one before the second BPM change
+        let tempo = chart.tempo_map();
+        assert_eq!(tempo.bpm_at(480 * 11), 200.5);
+        assert_eq!(tempo.bpm_at(480 * 11 - 1), 150.0);
+    }
+
+    #[test]
+    fn test_scroll_map() {
+        let chart = UGCChart::from_string(
+            "@MAINTIL\t1\n@TIL\t1\t0'0\t1.5\n@TIL\t2\t1'0\t0\n@TIL\t2\t1'960\t2\n\
+             @SPDMOD\t2'0\t0.5\n",
+        )
+        .unwrap();
+        let scroll = chart.scroll_map();
+
+        assert_eq!(scroll.main_timeline(), 1);
+        assert_eq!(scroll.speed_at(1, 100), 1.5);
+        assert_eq!(scroll.speed_at(2, 1920), 0.0);
+        assert_eq!(scroll.speed_at(2, 3840), 1.0);
+        assert_eq!(scroll.speed_at(0, 3840), 0.5);
+        // a measure at speed 1, half a measure stopped, then half a measure at speed 2
+        assert_eq!(scroll.position_at(2, 3840), 1920.0 + 1920.0);
+    }
+
+    #[test]
+    fn test_from_c2s() {
+        let c2s = C2SChart::from_string(
+            "RESOLUTION\t384\nBPM\t0\t0\t150.000\nMET\t0\t0\t4\t4\n\
+             TAP\t0\t0\t0\t4\nCHR\t0\t96\t4\t4\tDW\nCHR\t0\t192\t8\t4\tRS\n\
+             FLK\t0\t288\t12\t4\tL\nMNE\t1\t0\t0\t2\n\
+             HXD\t1\t96\t4\t4\t96\nAIR\t1\t192\t4\t4\tHLD\n\
+             SLD\t2\t0\t0\t4\t96\t4\t4\nSLC\t2\t96\t4\t4\t96\t8\t4\n\
+             AUR\t2\t192\t8\t4\tSLD\n\
+             TAP\t3\t0\t12\t4\nAHD\t3\t0\t12\t4\tTAP\t192\n\
+             ALD\t4\t0\t0\t16\t38400\t5.0\t1\t0\t16\t5.0\tNON\n",
+        )
+        .unwrap();
+        let ir = c2s.to_ir();
+        let messages: Vec<_> = ir.warnings.iter().map(|warning| &warning.message).collect();
+        assert_eq!(messages, vec!["unknown CHR effect RS, using the default"]);
+
+        // the SLC segment bends at an invisible point, which becomes a relay
+        let ugc = UGCChart::from_ir(&ir.value);
+        let messages: Vec<_> = ugc
+            .warnings
+            .iter()
+            .map(|warning| &warning.message)
+            .collect();
+        assert_eq!(
+            messages,
+            vec!["UGC slides have no invisible points, writing them as relays"]
+        );
+        let written = ugc.value.to_string();
+        let notes = written.split_once("\n\n").unwrap().1;
+        assert_eq!(
+            notes,
+            "#0'0:t04\n#0'480:x44D\n#0'960:x84U\n#0'1440:fC4A\n#1'0:d02\n\
+             #1'480:h44\n#480>s\n#1'480:x44U\n#1'960:a44UCN\n\
+             #2'0:s04\n#480>s44\n#960>s84\n#2'960:a84ULN\n\
+             #3'0:tC4\n#3'0:HC4N\n#960>s\n#4'0:C0G1EZ,$\n#5>c0G1E\n"
+        );
+
+        // the effect of an SXD head is kept on the stacked ExTap
+        let c2s =
+            C2SChart::from_string("RESOLUTION\t384\nSXD\t0\t0\t0\t4\t96\t4\t4\tSLD\tDW\n").unwrap();
+        let written = UGCChart::from_ir(&c2s.to_ir().value).value.to_string();
+        assert!(
+            written.contains("#0'0:s04\n#480>s44\n#0'0:x04D\n"),
+            "{}",
+            written
+        );
+    }
+
+    #[test]
+    fn test_to_c2s() {
+        let chart = UGCChart::from_string(
+            "@TICKS\t480\n@BPM\t0'0\t150\n@BEAT\t0\t4\t4\n\
+             #0'480:x44D\n#0'1440:fC4A\n#1'0:d02\n\
+             #1'480:h44\n#480>s\n#1'480:x44U\n#1'960:a44UCN\n\
+             #2'0:s04\n#480>s44\n#960>s84\n#2'960:a84ULN\n\
+             #3'0:tC4\n#3'0:HC4N\n#960>s\n#4'0:C0G1EZ,$\n#5>c0G1E\n#4'7:t04\n",
+        )
+        .unwrap();
+        let ir = chart.to_ir();
+        assert!(ir.warnings.is_empty(), "{:?}", ir.warnings);
+
+        let c2s = C2SChart::from_ir(&ir.value);
+        let messages: Vec<_> = c2s
+            .warnings
+            .iter()
+            .map(|warning| &warning.message)
+            .collect();
+        assert_eq!(
+            messages,
+            vec!["1 positions were rounded to the nearest C2S tick"]
+        );
+        let written = c2s.value.to_string();
+        let notes = written.split("\n\n").nth(2).unwrap();
+        // the ExTap on the hold head makes it an HXD, which the air note then targets
+        assert_eq!(
+            notes,
+            "CHR\t0\t96\t4\t4\tDW\nFLK\t0\t288\t12\t4\tL\nMNE\t1\t0\t0\t2\n\
+             HXD\t1\t96\t4\t4\t96\nAIR\t1\t192\t4\t4\tHXD\n\
+             SLD\t2\t0\t0\t4\t96\t4\t4\nSLD\t2\t96\t4\t4\t96\t8\t4\nAUR\t2\t192\t8\t4\tSLD\n\
+             TAP\t3\t0\t12\t4\nAHD\t3\t0\t12\t4\tTAP\t192\n\
+             ALD\t4\t0\t0\t16\t38400\t5.0\t1\t0\t16\t5.0\tNON\nTAP\t4\t1\t0\t4\n"
+        );
+
+        // and back, the ExTap is stacked on the hold again
+        let again = UGCChart::from_ir(&C2SChart::from_string(&written).unwrap().to_ir().value);
+        let written = again.value.to_string();
+        assert!(written.contains("#1'480:h44\n#480>s\n#1'480:x44U\n"));
+
+        // the effect of a stacked ExTap is kept on the head
+        let chart = UGCChart::from_string(
+            "@TICKS\t480\n@BPM\t0'0\t150\n@BEAT\t0\t4\t4\n#0'0:s04\n#480>s44\n#0'0:x04D\n",
+        )
+        .unwrap();
+        let ir = chart.to_ir().value;
+        assert!(matches!(
+            ir.notes[..],
+            [IrNote {
+                kind: IrNoteKind::Slide {
+                    ex: true,
+                    effect: Some(ExTapEffect::Down),
+                    ..
+                },
+                ..
+            }]
+        ));
+        let written = UGCChart::from_ir(&ir).value.to_string();
+        assert!(written.contains("#0'0:s04\n#480>s44\n#0'0:x04D\n"));
+    }
+
+    #[test]
+    fn test_speed_conversion() {
+        let chart = UGCChart::from_string(
+            "@MAINTIL\t0\n@TIL\t0\t1'0\t2\n@TIL\t1\t1'0\t0.5\n@SPDMOD\t2'0\t1.5\n\
+             #0'0:t44\n@USETIL\t1\n#1'0:t84\n",
+        )
+        .unwrap();
+        let ir = chart.to_ir().value;
+        assert_eq!(ir.notes[1].timeline, 1);
+        assert_eq!(ir.scroll.speed_at(1, 3840), 0.75);
+
+        // both timelines and the modifier come back as they were
+        let converted = UGCChart::from_ir(&ir).value;
+        assert_eq!(converted.header.main_timeline, Some(0));
+        assert_eq!(
+            converted.header.timeline_speeds,
+            chart.header.timeline_speeds
+        );
+        assert_eq!(
+            converted.header.speed_modifiers,
+            chart.header.speed_modifiers
+        );
+        assert_eq!(converted.timelines[&1].len(), 1);
+
+        // C2S only keeps the main timeline, with the modifier folded in
+        let c2s = C2SChart::from_ir(&ir);
+        assert_eq!(c2s.warnings.len(), 1);
+        assert_eq!(
+            c2s.warnings[0].message,
+            "C2S has a single scroll speed, notes on timeline 1 use the speeds of timeline 0"
+        );
+        let multipliers: Vec<_> = c2s
+            .value
+            .metadata
+            .sfl
+            .iter()
+            .map(|sfl| (sfl.measure, sfl.duration, sfl.multiplier))
+            .collect();
+        assert_eq!(multipliers, vec![(1, 384, 2.0), (2, 384, 3.0)]);
+    }
+
+    #[test]
+    fn test_parse_header_errors() {
+        let error = UGCChart::from_string("@FLAG\tCLIK\tTRUE").unwrap_err();
+        assert_eq!(error.to_string(), "line 1, column 7: invalid flag `CLIK`");
+
+        let error = UGCChart::from_string("@BEAT\t0\t4").unwrap_err();
+        assert_eq!(
+            error.kind,
+            ParseErrorKind::MissingField {
+                field: "denominator"
+            }
+        );
+
+        let error = UGCChart::from_string("@TICKS\t0").unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "line 1, column 8: invalid resolution `0`"
+        );
+    }
+
+    #[test]
+    fn test_child_notes() {
+        let chart = UGCChart::from_string(
+            "#0'0:h44\n#240>s\n#1'0:s22\n#120>c44\n#480>s66\n\
+             @USETIL 1\n#2'0:C8422Z,0\n#960>cA42O",
+        )
+        .unwrap();
+
+        let parents = &chart.timelines[&0];
+        assert!(matches!(
+            parents[0].note_type.children().unwrap()[..],
+            [ChildNote {
+                note_type: ChildNoteType::HoldEndPoint,
+                offset_tick: 240
+            }]
+        ));
+        assert!(matches!(
+            parents[1].note_type.children().unwrap()[..],
+            [
+                ChildNote {
+                    note_type: ChildNoteType::SlideControlPoint { lane: 4, width: 4 },
+                    offset_tick: 120
+                },
+                ChildNote {
+                    note_type: ChildNoteType::SlideRelayPoint { lane: 6, width: 6 },
+                    offset_tick: 480
+                }
+            ]
+        ));
+        assert!(matches!(
+            chart.timelines[&1][0].note_type.children().unwrap()[..],
+            [ChildNote {
+                note_type: ChildNoteType::AirCrushEndPoint {
+                    lane: 10,
+                    width: 4,
+                    height: 96
+                },
+                offset_tick: 960
+            }]
+        ));
+
+        let ir = chart.to_ir().value;
+        assert_eq!(ir.notes[0].end_tick(), 240);
+        assert_eq!(ir.notes[1].end_tick(), 1920 + 480);
+    }
+
+    #[test]
+    fn test_orphan_child_notes() {
+        let error = UGCChart::from_string("#0'0:t44\n#240>s").unwrap_err();
+        assert_eq!(error.location.line, 2);
+        assert_eq!(error.kind, ParseErrorKind::OrphanChildNote);
+
+        let error = UGCChart::from_string("#0'0:h44\n#240>c").unwrap_err();
+        assert_eq!(error.kind, ParseErrorKind::UnknownNoteType("c".to_string()));
+
+        // children of a skipped hold must not end up on the slide before it
+        let parsed = UGCChart::parse("#0'0:s22\n#0'480:h4\n#240>s", ParseMode::Lenient).unwrap();
+        assert_eq!(parsed.warnings.len(), 2);
+        assert_eq!(parsed.warnings[1].kind, ParseErrorKind::OrphanChildNote);
+        assert!(
+            parsed.value.timelines[&0][0]
+                .note_type
+                .children()
+                .unwrap()
+                .is_empty()
+        );
+    }
+
+    #[test]
+    fn test_out_of_order_children() {
+        let chart = UGCChart::from_string("#1'0:s44\n#960>s84\n#480>s24").unwrap();
+        let ir = chart.to_ir();
+        assert_eq!(ir.warnings.len(), 1, "{:?}", ir.warnings);
+        let mut ir = ir.value;
+        assert!(matches!(
+            &ir.notes[0].kind,
+            IrNoteKind::Slide { points, .. } if points[0].lane == 2 && points[1].lane == 8
+        ));
+        assert!(C2SChart::from_ir(&ir).warnings.is_empty());
+
+        // points before the head are reported instead of wrapping around
+        if let IrNoteKind::Slide { points, .. } = &mut ir.notes[0].kind {
+            points[0].tick = 0;
+        }
+        assert_eq!(C2SChart::from_ir(&ir).warnings.len(), 1);
+        assert_eq!(UGCChart::from_ir(&ir).warnings.len(), 1);
+    }
+
+    #[test]
+    fn test_write_ugc() {
+        let content = "@VER\t8\n@TITLE\tRe:Zero ～ The Last Song\n@CONST\t13.7\n@WEATTR\t嘘\n\
+                       @TICKS\t480\n@FLAG\tCLICK\tTRUE\n@BEAT\t0\t4\t4\n@BPM\t0'0\t150\n\
+                       @TIL\t1\t1'240\t0.5\n\n\
+                       #0'0:cA\n#0'0:tAG\n#0'240:x84I\n#0'240:a84DRN\n#0'480:f24R\n#0'480:d02\n\
+                       #1'0:h44\n#240>s\n#1'0:s22\n#120>c44\n#480>s66\n\
+                       #1'960:H44I\n#240>s\n#480>c\n#2'0:S442OI\n#240>sA4A0\n#480>c4410\n\n\
+                       @USETIL\t1\n#2'0:C8422Z,$\n#960>cA42O\n#3'0:C842Y0,1.5\n#960>c842Y\n";
+        let chart = UGCChart::from_string(content).unwrap();
+        // the lone `c` click is written without its ignored lane
+        assert_eq!(chart.to_string(), content.replacen("cA", "c", 1));
+
+        let reparsed = UGCChart::from_string(&chart.to_string()).unwrap();
+        assert_eq!(reparsed.to_ir().value, chart.to_ir().value);
+    }
 
     #[test]
     fn test_parse_timing() {
-        let (bar, tick) = parse_timing("69'420").unwrap();
+        let (bar, tick) = parse_timing("69'420", "69'420").unwrap();
         assert_eq!((bar, tick), (69, 420));
     }
 
     #[test]
     fn test_parse_timing_fail() {
-        let result = parse_timing("69:420");
-        assert_eq!(result.is_err(), true);
+        let result = parse_timing("69:420", "69:420");
+        assert!(result.is_err());
     }
 }
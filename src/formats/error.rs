@@ -0,0 +1,216 @@
+//! Errors and warnings produced while parsing chart files
+//!
+//! Every parser can run in [`ParseMode::Strict`], where the first bad line fails the whole chart,
+//! or [`ParseMode::Lenient`], where bad lines are skipped and reported back as warnings
+//! alongside the chart so it's clear what was lost.
+
+use std::{
+    fmt,
+    path::{Path, PathBuf},
+};
+
+/// How a parser should react to lines it can't make sense of
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ParseMode {
+    /// Fail on the first bad line
+    Strict,
+    /// Skip bad lines and report them as warnings
+    #[default]
+    Lenient,
+}
+
+/// Where in a chart file a problem was found
+///
+/// Lines and columns are 1-based, columns count characters rather than bytes.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Location {
+    pub file: Option<PathBuf>,
+    pub line: usize,
+    pub column: usize,
+}
+
+impl fmt::Display for Location {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.file {
+            Some(file) => write!(f, "{}:{}:{}", file.display(), self.line, self.column),
+            None => write!(f, "line {}, column {}", self.line, self.column),
+        }
+    }
+}
+
+/// What was wrong with a line
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum ParseErrorKind {
+    #[error("missing {field}")]
+    MissingField { field: &'static str },
+    #[error("invalid {field} `{value}`")]
+    InvalidField { field: &'static str, value: String },
+    #[error("unknown note type `{0}`")]
+    UnknownNoteType(String),
+    #[error("child note without a hold, slide or air note before it")]
+    OrphanChildNote,
+    #[error("{0} are not supported yet")]
+    Unsupported(&'static str),
+    #[error("unrecognized line `{0}`")]
+    UnrecognizedLine(String),
+}
+
+/// A problem with a single line of a chart
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+#[error("{location}: {kind}")]
+pub struct ParseError {
+    pub location: Location,
+    pub kind: ParseErrorKind,
+}
+
+impl ParseError {
+    /// An error at `column` of the first line, for parsers that only see a single line
+    pub fn new(column: usize, kind: ParseErrorKind) -> Self {
+        Self {
+            location: Location {
+                file: None,
+                line: 1,
+                column,
+            },
+            kind,
+        }
+    }
+
+    /// An error about `field`, which must be a slice of `line`
+    pub(crate) fn at_field(line: &str, field: &str, kind: ParseErrorKind) -> Self {
+        Self::new(column_of(line, field), kind)
+    }
+
+    /// A `field` of `line` that couldn't be parsed
+    pub(crate) fn invalid(line: &str, field: &str, name: &'static str) -> Self {
+        Self::at_field(
+            line,
+            field,
+            ParseErrorKind::InvalidField {
+                field: name,
+                value: field.to_string(),
+            },
+        )
+    }
+
+    /// A field missing from the end of `line`
+    pub(crate) fn missing(line: &str, name: &'static str) -> Self {
+        Self::new(
+            line.chars().count() + 1,
+            ParseErrorKind::MissingField { field: name },
+        )
+    }
+
+    /// Moves this error to `line` of the chart
+    pub fn on_line(mut self, line: usize) -> Self {
+        self.location.line = line;
+        self
+    }
+
+    /// Attributes this error to `file`
+    pub fn with_file(mut self, file: impl AsRef<Path>) -> Self {
+        self.location.file = Some(file.as_ref().to_path_buf());
+        self
+    }
+}
+
+/// 1-based character column of `field` inside `line`
+///
+/// `field` has to point into `line`, anything else lands on column 1.
+fn column_of(line: &str, field: &str) -> usize {
+    let offset = (field.as_ptr() as usize).saturating_sub(line.as_ptr() as usize);
+    line.get(..offset)
+        .map_or(0, |before| before.chars().count())
+        + 1
+}
+
+/// A parsed value and the lines that were skipped to get it
+#[derive(Debug, Clone, PartialEq)]
+pub struct Parsed<T> {
+    pub value: T,
+    pub warnings: Vec<ParseError>,
+}
+
+impl<T> Parsed<T> {
+    pub fn new(value: T, warnings: Vec<ParseError>) -> Self {
+        Self { value, warnings }
+    }
+
+    pub fn map<U>(self, f: impl FnOnce(T) -> U) -> Parsed<U> {
+        Parsed {
+            value: f(self.value),
+            warnings: self.warnings,
+        }
+    }
+
+    /// Attributes every warning to `file`
+    pub fn with_file(mut self, file: impl AsRef<Path>) -> Self {
+        for warning in &mut self.warnings {
+            warning.location.file = Some(file.as_ref().to_path_buf());
+        }
+        self
+    }
+}
+
+/// Collects bad lines while parsing according to a [`ParseMode`]
+#[derive(Debug)]
+pub(crate) struct Diagnostics {
+    mode: ParseMode,
+    warnings: Vec<ParseError>,
+}
+
+impl Diagnostics {
+    pub fn new(mode: ParseMode) -> Self {
+        Self {
+            mode,
+            warnings: Vec::new(),
+        }
+    }
+
+    /// Records a bad line, which is fatal in strict mode
+    pub fn report(&mut self, error: ParseError) -> Result<(), ParseError> {
+        match self.mode {
+            ParseMode::Strict => Err(error),
+            ParseMode::Lenient => {
+                self.warnings.push(error);
+                Ok(())
+            }
+        }
+    }
+
+    pub fn finish<T>(self, value: T) -> Parsed<T> {
+        Parsed::new(value, self.warnings)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_error_location() {
+        let line = "TAP\t0\tあ\t8\t4";
+        let field = line.split_whitespace().nth(2).unwrap();
+        let error = ParseError::invalid(line, field, "offset").on_line(12);
+        assert_eq!(error.location.column, 7);
+        assert_eq!(error.to_string(), "line 12, column 7: invalid offset `あ`");
+        assert_eq!(
+            error.with_file("chart.c2s").to_string(),
+            "chart.c2s:12:7: invalid offset `あ`"
+        );
+
+        assert_eq!(ParseError::missing("TAP\t0", "cell").location.column, 6);
+    }
+
+    #[test]
+    fn test_diagnostics_modes() {
+        let error = ParseError::new(1, ParseErrorKind::UnrecognizedLine("?".to_string()));
+
+        let mut strict = Diagnostics::new(ParseMode::Strict);
+        assert_eq!(strict.report(error.clone()), Err(error.clone()));
+
+        let mut lenient = Diagnostics::new(ParseMode::Lenient);
+        assert_eq!(lenient.report(error.clone()), Ok(()));
+        assert_eq!(lenient.finish(()).warnings, vec![error]);
+    }
+}
@@ -0,0 +1,587 @@
+//! maimai DX `.ma2` chart format
+//!
+//! note: Like C2S, this format is TSV-based: a header, the `BPM`/`MET` composition lines,
+//! then one note per line. Notes are placed by measure and offset, with `RESOLUTION` ticks
+//! per measure.
+use std::{fmt, str::FromStr};
+
+use crate::formats::error::{Diagnostics, ParseError, ParseErrorKind, ParseMode, Parsed};
+
+const DEFAULT_RESOLUTION: u32 = 384;
+
+/// How a note is judged, from the first two letters of its tag
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NoteStyle {
+    /// `NM`
+    Normal,
+    /// `BR`, worth more and judged more strictly
+    Break,
+    /// `EX`, always judged as CRITICAL PERFECT
+    Ex,
+    /// `BX`, a break that is also an EX note
+    BreakEx,
+}
+
+static NOTE_STYLES: &[(&str, NoteStyle)] = &[
+    ("NM", NoteStyle::Normal),
+    ("BR", NoteStyle::Break),
+    ("EX", NoteStyle::Ex),
+    ("BX", NoteStyle::BreakEx),
+];
+
+/// Path of a slide, from the last three letters of its tag
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SlideShape {
+    /// `SI_`, a straight line
+    Straight,
+    /// `SCL`, counterclockwise around the ring
+    CircleLeft,
+    /// `SCR`, clockwise around the ring
+    CircleRight,
+    /// `SUL`, a curve bending left around the center
+    CurveLeft,
+    /// `SUR`, a curve bending right around the center
+    CurveRight,
+    /// `SSL`, a zigzag through the center
+    ThunderLeft,
+    /// `SSR`, a mirrored zigzag through the center
+    ThunderRight,
+    /// `SV_`, in to the center and back out
+    V,
+    /// `SXL`, a wide V turning left
+    GrandVLeft,
+    /// `SXR`, a wide V turning right
+    GrandVRight,
+    /// `SLL`, a V with its corner on the left
+    LLeft,
+    /// `SLR`, a V with its corner on the right
+    LRight,
+    /// `SF_`, a fan spreading over three end buttons
+    Fan,
+}
+
+static SLIDE_SHAPES: &[(&str, SlideShape)] = &[
+    ("SI_", SlideShape::Straight),
+    ("SCL", SlideShape::CircleLeft),
+    ("SCR", SlideShape::CircleRight),
+    ("SUL", SlideShape::CurveLeft),
+    ("SUR", SlideShape::CurveRight),
+    ("SSL", SlideShape::ThunderLeft),
+    ("SSR", SlideShape::ThunderRight),
+    ("SV_", SlideShape::V),
+    ("SXL", SlideShape::GrandVLeft),
+    ("SXR", SlideShape::GrandVRight),
+    ("SLL", SlideShape::LLeft),
+    ("SLR", SlideShape::LRight),
+    ("SF_", SlideShape::Fan),
+];
+
+/// What a note is, along with the fields only that kind of note has
+#[derive(Debug, Clone, PartialEq)]
+pub enum NoteKind {
+    /// `TAP`
+    Tap,
+    /// `HLD`
+    Hold { duration: u32 },
+    /// `STR`, a tap with a star, usually followed by the slides starting from it
+    Star,
+    /// `SI_`, `SCL`, ... a slide from `button` to `end_button`
+    Slide {
+        shape: SlideShape,
+        /// Ticks between the star being hit and the slide starting to move
+        wait: u32,
+        /// Ticks the slide takes to move to its end
+        duration: u32,
+        end_button: u32,
+    },
+    /// `TTP`
+    TouchTap(Touch),
+    /// `THO`
+    TouchHold { duration: u32, touch: Touch },
+}
+
+/// Where and how a touch note is shown
+#[derive(Debug, Clone, PartialEq)]
+pub struct Touch {
+    /// Sensor ring, `A` to `E`, `C` being the center
+    pub area: String,
+    /// Whether hitting it sets off a firework
+    pub firework: bool,
+    /// Displayed size, usually `M1`
+    pub size: String,
+}
+
+/// A single note of a ma2 chart
+#[derive(Debug, Clone, PartialEq)]
+pub struct Note {
+    pub style: NoteStyle,
+    pub kind: NoteKind,
+    /// Measure where this note starts
+    pub measure: u32,
+    /// Offset within the measure where this note starts, in ticks
+    pub offset: u32,
+    /// Button 0-7, clockwise from the top right, or the sensor within the area for touch notes
+    pub button: u32,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Bpm {
+    /// Beginning measure where this BPM change takes effect
+    pub measure: u32,
+    /// Offset within the measure where this BPM change takes effect
+    pub offset: u32,
+    pub bpm: f32,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct TimeSignature {
+    /// Beginning measure where this time signature takes effect
+    pub measure: u32,
+    /// Offset within the measure where this time signature takes effect
+    pub offset: u32,
+    pub numerator: u32,
+    pub denominator: u32,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Ma2Metadata {
+    pub version: [String; 2],
+    /// `FES_MODE`, set on charts made for the festival mode
+    pub fes_mode: bool,
+    /// Default BPM (Beats Per Minute) of the song.
+    // `BPM_DEF`
+    pub bpm_default: [f32; 4],
+    /// Default time signature
+    // `MET_DEF`
+    pub metronome_def: [u32; 2],
+    /// Resolution of the chart, defaults to 384 per measure.
+    pub resolution: u32,
+    /// Clock offset
+    // `CLK_DEF`
+    pub clock_default: u32,
+    /// `COMPATIBLE_CODE`, usually `MA2`
+    pub compatible_code: Option<String>,
+    /// BPM changes throughout the chart.
+    pub bpm: Vec<Bpm>,
+    /// Time signatures throughout the chart.
+    pub time_signatures: Vec<TimeSignature>,
+}
+
+impl Default for Ma2Metadata {
+    fn default() -> Self {
+        Self {
+            version: ["0.00.00".to_string(), "1.04.00".to_string()],
+            fes_mode: false,
+            bpm_default: [120.0, 120.0, 120.0, 120.0],
+            metronome_def: [4, 4],
+            resolution: DEFAULT_RESOLUTION,
+            clock_default: DEFAULT_RESOLUTION,
+            compatible_code: Some("MA2".to_string()),
+            bpm: Vec::new(),
+            time_signatures: Vec::new(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Ma2Chart {
+    pub metadata: Ma2Metadata,
+    pub notes: Vec<Note>,
+    /// Trailing `T_XXX` and `TTM_XXX` statistics, kept verbatim in file order
+    pub end_tags: Vec<(String, String)>,
+}
+
+/// Parses field `index` of a line split on whitespace
+fn parse_field<T: FromStr>(
+    line: &str,
+    parts: &[&str],
+    index: usize,
+    name: &'static str,
+) -> Result<T, ParseError> {
+    let field = parts
+        .get(index)
+        .ok_or_else(|| ParseError::missing(line, name))?;
+    field
+        .parse()
+        .map_err(|_| ParseError::invalid(line, field, name))
+}
+
+/// Splits a note tag such as `BRSI_` into its style and the rest of the tag
+fn split_tag(tag: &str) -> Option<(NoteStyle, &str)> {
+    let (prefix, kind) = (tag.get(..2)?, tag.get(2..)?);
+    NOTE_STYLES
+        .iter()
+        .find(|(key, _)| *key == prefix)
+        .map(|(_, style)| (*style, kind))
+}
+
+impl Touch {
+    /// Reads the area, firework and size fields starting at `index`
+    fn parse(line: &str, parts: &[&str], index: usize) -> Result<Self, ParseError> {
+        Ok(Self {
+            area: parse_field(line, parts, index, "touch area")?,
+            firework: parse_field::<u32>(line, parts, index + 1, "firework flag")? != 0,
+            size: parse_field(line, parts, index + 2, "touch size")?,
+        })
+    }
+}
+
+impl Note {
+    pub fn from_line(line: &str) -> Result<Self, ParseError> {
+        let parts: Vec<&str> = line.split_whitespace().collect();
+        let Some(&tag) = parts.first() else {
+            return Err(ParseError::missing(line, "note type"));
+        };
+        let unknown =
+            || ParseError::at_field(line, tag, ParseErrorKind::UnknownNoteType(tag.to_string()));
+        let (style, kind) = split_tag(tag).ok_or_else(unknown)?;
+
+        let kind = match kind {
+            "TAP" => NoteKind::Tap,
+            "HLD" => NoteKind::Hold {
+                duration: parse_field(line, &parts, 4, "hold duration")?,
+            },
+            "STR" => NoteKind::Star,
+            "TTP" => NoteKind::TouchTap(Touch::parse(line, &parts, 4)?),
+            "THO" => NoteKind::TouchHold {
+                duration: parse_field(line, &parts, 4, "touch hold duration")?,
+                touch: Touch::parse(line, &parts, 5)?,
+            },
+            kind => {
+                let (_, shape) = SLIDE_SHAPES
+                    .iter()
+                    .find(|(key, _)| *key == kind)
+                    .ok_or_else(unknown)?;
+                NoteKind::Slide {
+                    shape: *shape,
+                    wait: parse_field(line, &parts, 4, "slide wait")?,
+                    duration: parse_field(line, &parts, 5, "slide duration")?,
+                    end_button: parse_field(line, &parts, 6, "slide end button")?,
+                }
+            }
+        };
+
+        Ok(Self {
+            style,
+            kind,
+            measure: parse_field(line, &parts, 1, "measure")?,
+            offset: parse_field(line, &parts, 2, "offset")?,
+            button: parse_field(line, &parts, 3, "button")?,
+        })
+    }
+
+    /// The tag this note is written with, e.g. `BRSI_`
+    pub fn tag(&self) -> String {
+        let style = NOTE_STYLES
+            .iter()
+            .find(|(_, style)| *style == self.style)
+            .map_or("NM", |(key, _)| key);
+        let kind = match &self.kind {
+            NoteKind::Tap => "TAP",
+            NoteKind::Hold { .. } => "HLD",
+            NoteKind::Star => "STR",
+            NoteKind::Slide { shape, .. } => SLIDE_SHAPES
+                .iter()
+                .find(|(_, other)| other == shape)
+                .map_or("SI_", |(key, _)| key),
+            NoteKind::TouchTap(_) => "TTP",
+            NoteKind::TouchHold { .. } => "THO",
+        };
+        format!("{style}{kind}")
+    }
+}
+
+impl Ma2Chart {
+    /// Parse a complete ma2 chart from a string containing both metadata and notes
+    ///
+    /// Fails on the first line that can't be parsed, see [`Ma2Chart::parse`] to skip them instead.
+    pub fn from_string(content: &str) -> Result<Self, ParseError> {
+        Self::parse(content, ParseMode::Strict).map(|parsed| parsed.value)
+    }
+
+    /// Parse a complete ma2 chart, skipping bad lines in lenient mode
+    pub fn parse(content: &str, mode: ParseMode) -> Result<Parsed<Self>, ParseError> {
+        let mut chart = Ma2Chart::default();
+        let mut diagnostics = Diagnostics::new(mode);
+
+        for (index, line) in content.lines().enumerate() {
+            if let Err(error) = chart.parse_line(line) {
+                diagnostics.report(error.on_line(index + 1))?;
+            }
+        }
+
+        Ok(diagnostics.finish(chart))
+    }
+
+    /// Parse a single metadata, note or end tag line into this chart
+    fn parse_line(&mut self, line: &str) -> Result<(), ParseError> {
+        let parts: Vec<&str> = line.split_whitespace().collect();
+        let Some(&tag) = parts.first() else {
+            return Ok(());
+        };
+        let metadata = &mut self.metadata;
+
+        match tag {
+            "VERSION" => {
+                metadata.version = [
+                    parse_field(line, &parts, 1, "version")?,
+                    parse_field(line, &parts, 2, "version")?,
+                ];
+            }
+            "FES_MODE" => {
+                metadata.fes_mode = parse_field::<u32>(line, &parts, 1, "fes mode flag")? != 0;
+            }
+            "BPM_DEF" => {
+                metadata.bpm_default = [
+                    parse_field(line, &parts, 1, "default BPM")?,
+                    parse_field(line, &parts, 2, "default BPM")?,
+                    parse_field(line, &parts, 3, "default BPM")?,
+                    parse_field(line, &parts, 4, "default BPM")?,
+                ];
+            }
+            "MET_DEF" => {
+                metadata.metronome_def = [
+                    parse_field(line, &parts, 1, "numerator")?,
+                    parse_field(line, &parts, 2, "denominator")?,
+                ];
+            }
+            "RESOLUTION" => metadata.resolution = parse_field(line, &parts, 1, "resolution")?,
+            "CLK_DEF" => metadata.clock_default = parse_field(line, &parts, 1, "clock")?,
+            "COMPATIBLE_CODE" => {
+                metadata.compatible_code = Some(parse_field(line, &parts, 1, "compatible code")?);
+            }
+            "BPM" => metadata.bpm.push(Bpm {
+                measure: parse_field(line, &parts, 1, "measure")?,
+                offset: parse_field(line, &parts, 2, "offset")?,
+                bpm: parse_field(line, &parts, 3, "BPM")?,
+            }),
+            "MET" => metadata.time_signatures.push(TimeSignature {
+                measure: parse_field(line, &parts, 1, "measure")?,
+                offset: parse_field(line, &parts, 2, "offset")?,
+                numerator: parse_field(line, &parts, 3, "numerator")?,
+                denominator: parse_field(line, &parts, 4, "denominator")?,
+            }),
+            tag if tag.starts_with("T_") || tag.starts_with("TTM_") => {
+                self.end_tags.push((tag.to_string(), parts[1..].join("\t")));
+            }
+            // If it's not a metadata field, it has to be a note
+            _ => self.notes.push(Note::from_line(line)?),
+        }
+
+        Ok(())
+    }
+}
+
+impl fmt::Display for Touch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}\t{}\t{}",
+            self.area,
+            if self.firework { 1 } else { 0 },
+            self.size
+        )
+    }
+}
+
+impl fmt::Display for Note {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}\t{}\t{}\t{}",
+            self.tag(),
+            self.measure,
+            self.offset,
+            self.button
+        )?;
+        match &self.kind {
+            NoteKind::Tap | NoteKind::Star => Ok(()),
+            NoteKind::Hold { duration } => write!(f, "\t{}", duration),
+            NoteKind::Slide {
+                wait,
+                duration,
+                end_button,
+                ..
+            } => write!(f, "\t{}\t{}\t{}", wait, duration, end_button),
+            NoteKind::TouchTap(touch) => write!(f, "\t{}", touch),
+            NoteKind::TouchHold { duration, touch } => write!(f, "\t{}\t{}", duration, touch),
+        }
+    }
+}
+
+impl fmt::Display for Ma2Metadata {
+    /// Writes the header block and the BPM/MET composition lines
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "VERSION\t{}\t{}", self.version[0], self.version[1])?;
+        writeln!(f, "FES_MODE\t{}", if self.fes_mode { 1 } else { 0 })?;
+        writeln!(
+            f,
+            "BPM_DEF\t{:.3}\t{:.3}\t{:.3}\t{:.3}",
+            self.bpm_default[0], self.bpm_default[1], self.bpm_default[2], self.bpm_default[3]
+        )?;
+        writeln!(
+            f,
+            "MET_DEF\t{}\t{}",
+            self.metronome_def[0], self.metronome_def[1]
+        )?;
+        writeln!(f, "RESOLUTION\t{}", self.resolution)?;
+        writeln!(f, "CLK_DEF\t{}", self.clock_default)?;
+        if let Some(code) = &self.compatible_code {
+            writeln!(f, "COMPATIBLE_CODE\t{}", code)?;
+        }
+
+        if !self.bpm.is_empty() || !self.time_signatures.is_empty() {
+            writeln!(f)?;
+        }
+        for bpm in &self.bpm {
+            writeln!(f, "BPM\t{}\t{}\t{:.3}", bpm.measure, bpm.offset, bpm.bpm)?;
+        }
+        for met in &self.time_signatures {
+            writeln!(
+                f,
+                "MET\t{}\t{}\t{}\t{}",
+                met.measure, met.offset, met.numerator, met.denominator
+            )?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for Ma2Chart {
+    /// Writes the whole chart in the tab-separated layout used by the game files
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.metadata)?;
+        writeln!(f)?;
+        for note in &self.notes {
+            writeln!(f, "{}", note)?;
+        }
+        if !self.end_tags.is_empty() {
+            writeln!(f)?;
+        }
+        for (tag, value) in &self.end_tags {
+            writeln!(f, "{}\t{}", tag, value)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MA2: &str = "VERSION\t0.00.00\t1.04.00
+FES_MODE\t0
+BPM_DEF\t150.000\t150.000\t150.000\t150.000
+MET_DEF\t4\t4
+RESOLUTION\t384
+CLK_DEF\t384
+COMPATIBLE_CODE\tMA2
+
+BPM\t0\t0\t150.000
+BPM\t4\t192\t200.000
+MET\t0\t0\t4\t4
+
+NMTAP\t1\t0\t0
+BRTAP\t1\t96\t3
+EXTAP\t1\t192\t4
+BXTAP\t1\t288\t7
+NMHLD\t2\t0\t2\t192
+NMSTR\t3\t0\t0
+NMSI_\t3\t0\t0\t96\t96\t4
+BRSCL\t3\t0\t0\t96\t192\t6
+NMSF_\t3\t192\t2\t96\t48\t6
+NMTTP\t4\t0\t0\tC\t1\tM1
+NMTHO\t4\t192\t3\t96\tB\t0\tM1
+
+T_REC_TAP\t4
+TTM_EACHPAIRS\t0
+";
+
+    #[test]
+    fn test_parse_ma2() {
+        let chart = Ma2Chart::from_string(MA2).unwrap();
+        let metadata = &chart.metadata;
+        assert_eq!(metadata.version[1], "1.04.00");
+        assert!(!metadata.fes_mode);
+        assert_eq!(metadata.resolution, 384);
+        assert_eq!(metadata.clock_default, 384);
+        assert_eq!(
+            metadata.bpm[1],
+            Bpm {
+                measure: 4,
+                offset: 192,
+                bpm: 200.0
+            }
+        );
+        assert_eq!(metadata.time_signatures.len(), 1);
+        assert_eq!(chart.notes.len(), 11);
+
+        let styles: Vec<NoteStyle> = chart.notes[..4].iter().map(|note| note.style).collect();
+        assert_eq!(
+            styles,
+            [
+                NoteStyle::Normal,
+                NoteStyle::Break,
+                NoteStyle::Ex,
+                NoteStyle::BreakEx
+            ]
+        );
+        assert_eq!(chart.notes[4].kind, NoteKind::Hold { duration: 192 });
+        assert_eq!(chart.notes[5].kind, NoteKind::Star);
+        assert_eq!(
+            chart.notes[7],
+            Note {
+                style: NoteStyle::Break,
+                kind: NoteKind::Slide {
+                    shape: SlideShape::CircleLeft,
+                    wait: 96,
+                    duration: 192,
+                    end_button: 6,
+                },
+                measure: 3,
+                offset: 0,
+                button: 0,
+            }
+        );
+        assert_eq!(
+            chart.notes[10].kind,
+            NoteKind::TouchHold {
+                duration: 96,
+                touch: Touch {
+                    area: "B".to_string(),
+                    firework: false,
+                    size: "M1".to_string(),
+                },
+            }
+        );
+        assert_eq!(
+            chart.end_tags[0],
+            ("T_REC_TAP".to_string(), "4".to_string())
+        );
+
+        assert_eq!(chart.to_string(), MA2);
+    }
+
+    #[test]
+    fn test_parse_ma2_errors() {
+        let error = Note::from_line("NMXYZ\t0\t0\t0").unwrap_err();
+        assert_eq!(
+            error.kind,
+            ParseErrorKind::UnknownNoteType("NMXYZ".to_string())
+        );
+        let error = Note::from_line("NMHLD\t0\t0\t0").unwrap_err();
+        assert_eq!(
+            error.kind,
+            ParseErrorKind::MissingField {
+                field: "hold duration"
+            }
+        );
+
+        let content = "RESOLUTION\t384\nNMTAP\t0\t0\nNMTAP\t0\t0\t1\n";
+        assert!(Ma2Chart::from_string(content).is_err());
+        let parsed = Ma2Chart::parse(content, ParseMode::Lenient).unwrap();
+        assert_eq!(parsed.value.notes.len(), 1);
+        assert_eq!(parsed.warnings.len(), 1);
+        assert_eq!(parsed.warnings[0].location.line, 2);
+    }
+}
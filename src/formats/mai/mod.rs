@@ -1 +1,2 @@
-//! A certain washing machine game.
\ No newline at end of file
+//! A certain washing machine game.
+pub mod ma2;
@@ -1,3 +1,212 @@
 pub mod chuni;
+pub mod error;
 pub mod geki;
 pub mod mai;
+
+use std::{fmt, path::Path};
+
+use chuni::{
+    ChuniChart,
+    c2s::C2SChart,
+    ir::{Conversion, IrChart},
+    sus::SusChart,
+    ugc::UGCChart,
+};
+use error::{ParseError, ParseMode, Parsed};
+
+/// A chart file format that perfconv knows how to read and/or write
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum ChartFormat {
+    /// CHUNITHM C2S (`.c2s`)
+    C2s,
+    /// Seaurchin/SUSPlayer SUS (`.sus`)
+    Sus,
+    /// UMIGURI UGC (`.ugc`)
+    Ugc,
+}
+
+impl ChartFormat {
+    /// Guess the format from a file extension, case-insensitively
+    pub fn from_extension(path: &Path) -> Option<Self> {
+        let extension = path.extension()?.to_str()?.to_ascii_lowercase();
+        match extension.as_str() {
+            "c2s" => Some(Self::C2s),
+            "sus" => Some(Self::Sus),
+            "ugc" => Some(Self::Ugc),
+            _ => None,
+        }
+    }
+
+    /// Guess the format by sniffing the first meaningful line of a chart
+    ///
+    /// UGC header lines start with `@`, SUS data lines start with `#`,
+    /// and C2S lines start with an uppercase tag such as `VERSION` or `TAP`.
+    pub fn detect(content: &str) -> Option<Self> {
+        for line in content.lines() {
+            let line = line.trim_start_matches('\u{feff}').trim();
+            if line.is_empty() {
+                continue;
+            }
+
+            if line.starts_with('@') {
+                return Some(Self::Ugc);
+            }
+            if line.starts_with('#') {
+                // UGC note lines are `#bar'tick:...`, SUS never uses `'` in a header
+                return match line.split_once(':') {
+                    Some((header, _)) if header.contains('\'') => Some(Self::Ugc),
+                    _ => Some(Self::Sus),
+                };
+            }
+
+            let tag = line.split_whitespace().next()?;
+            if tag.len() >= 3 && tag.chars().all(|c| c.is_ascii_uppercase() || c == '_') {
+                return Some(Self::C2s);
+            }
+        }
+
+        None
+    }
+
+    /// The conventional file extension for this format
+    pub fn extension(&self) -> &'static str {
+        match self {
+            Self::C2s => "c2s",
+            Self::Sus => "sus",
+            Self::Ugc => "ugc",
+        }
+    }
+}
+
+impl fmt::Display for ChartFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::C2s => write!(f, "C2S"),
+            Self::Sus => write!(f, "SUS"),
+            Self::Ugc => write!(f, "UGC"),
+        }
+    }
+}
+
+/// A parsed chart in any of the supported formats
+// only ever a handful of these alive at once, boxing isn't worth it
+#[allow(clippy::large_enum_variant)]
+#[derive(Debug)]
+pub enum Chart {
+    C2s(C2SChart),
+    Sus(SusChart),
+    Ugc(UGCChart),
+}
+
+impl Chart {
+    /// Parse `content` as a chart of the given format
+    ///
+    /// In lenient mode, lines that couldn't be parsed are skipped and returned as warnings.
+    pub fn parse(
+        format: ChartFormat,
+        content: &str,
+        mode: ParseMode,
+    ) -> Result<Parsed<Self>, ParseError> {
+        Ok(match format {
+            ChartFormat::C2s => C2SChart::parse(content, mode)?.map(Self::C2s),
+            ChartFormat::Sus => SusChart::parse(content, mode)?.map(Self::Sus),
+            ChartFormat::Ugc => UGCChart::parse(content, mode)?.map(Self::Ugc),
+        })
+    }
+
+    /// The format this chart is currently in
+    pub fn format(&self) -> ChartFormat {
+        match self {
+            Self::C2s(_) => ChartFormat::C2s,
+            Self::Sus(_) => ChartFormat::Sus,
+            Self::Ugc(_) => ChartFormat::Ugc,
+        }
+    }
+
+    /// Convert this chart into the format-neutral intermediate representation
+    pub fn to_ir(&self) -> Conversion<IrChart> {
+        match self {
+            Self::C2s(chart) => chart.to_ir(),
+            Self::Sus(chart) => chart.to_ir(),
+            Self::Ugc(chart) => chart.to_ir(),
+        }
+    }
+
+    /// Build a chart of the given format from the intermediate representation
+    pub fn from_ir(format: ChartFormat, chart: &IrChart) -> Conversion<Self> {
+        match format {
+            ChartFormat::C2s => C2SChart::from_ir(chart).map(|mut chart| {
+                chart.update_end_tags();
+                Self::C2s(chart)
+            }),
+            ChartFormat::Sus => SusChart::from_ir(chart).map(Self::Sus),
+            ChartFormat::Ugc => UGCChart::from_ir(chart).map(Self::Ugc),
+        }
+    }
+
+    /// Convert this chart into another format, going through the intermediate representation
+    ///
+    /// Anything that couldn't be carried over is listed in the returned warnings.
+    pub fn convert(self, target: ChartFormat) -> Conversion<Self> {
+        if self.format() == target {
+            return Conversion::new(self, Vec::new());
+        }
+
+        let ir = self.to_ir();
+        let mut converted = Self::from_ir(target, &ir.value);
+        let mut warnings = ir.warnings;
+        warnings.append(&mut converted.warnings);
+        Conversion::new(converted.value, warnings)
+    }
+}
+
+/// Writes the chart in its on-disk text form
+impl fmt::Display for Chart {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::C2s(chart) => chart.fmt(f),
+            Self::Sus(chart) => chart.fmt(f),
+            Self::Ugc(chart) => chart.fmt(f),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_format_from_extension() {
+        assert_eq!(
+            ChartFormat::from_extension(Path::new("song/master.c2s")),
+            Some(ChartFormat::C2s)
+        );
+        assert_eq!(
+            ChartFormat::from_extension(Path::new("song.SUS")),
+            Some(ChartFormat::Sus)
+        );
+        assert_eq!(
+            ChartFormat::from_extension(Path::new("song.ugc")),
+            Some(ChartFormat::Ugc)
+        );
+        assert_eq!(ChartFormat::from_extension(Path::new("song.txt")), None);
+    }
+
+    #[test]
+    fn test_format_detect() {
+        assert_eq!(
+            ChartFormat::detect("VERSION\t1.13.00\t1.13.00\nTAP\t0\t0\t8\t4"),
+            Some(ChartFormat::C2s)
+        );
+        assert_eq!(
+            ChartFormat::detect("This is a comment\n#TITLE \"Test\"\n#00010: 14"),
+            Some(ChartFormat::Sus)
+        );
+        assert_eq!(
+            ChartFormat::detect("'comment\n@VER\t8\n#0'0:t04"),
+            Some(ChartFormat::Ugc)
+        );
+        assert_eq!(ChartFormat::detect("#0'0:t04"), Some(ChartFormat::Ugc));
+        assert_eq!(ChartFormat::detect(""), None);
+    }
+}
@@ -0,0 +1,4 @@
+#![doc = include_str!("../README.md")]
+// meowy
+
+pub mod formats;
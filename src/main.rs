@@ -1,8 +1,122 @@
-#![doc = include_str!("../README.md")]
-// meowy
+use std::path::{Path, PathBuf};
 
-mod formats;
+use clap::{Parser, Subcommand};
+use eyre::{OptionExt, WrapErr};
+use perfconv::formats::{
+    Chart, ChartFormat,
+    chuni::{c2s::C2SChart, music::MusicXml},
+    error::ParseMode,
+};
 
-fn main() {
-    println!("Hello, world!");
+#[derive(Debug, Parser)]
+#[command(version, about)]
+struct Cli {
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Debug, Subcommand)]
+enum Command {
+    /// Convert a chart from one format to another
+    Convert {
+        /// Chart file to read
+        input: PathBuf,
+        /// Format to convert the chart into
+        #[arg(long)]
+        to: ChartFormat,
+        /// Format of the input chart, guessed from the extension or contents if omitted
+        #[arg(long)]
+        from: Option<ChartFormat>,
+        /// Where to write the converted chart, defaults to the input path with the new extension
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+        /// Fail on lines that can't be parsed instead of skipping them with a warning
+        #[arg(long)]
+        strict: bool,
+    },
+}
+
+/// Adds the song metadata from the `Music.xml` next to a C2S chart, if there is one
+fn load_music(chart: &mut C2SChart, input: &Path) {
+    let folder = input.parent().unwrap_or(Path::new(""));
+    if !folder.join("Music.xml").is_file() {
+        return;
+    }
+    let file = input
+        .file_name()
+        .and_then(|file| file.to_str())
+        .unwrap_or_default();
+    match MusicXml::load(folder) {
+        Ok(music) => chart.attach_music(music, file),
+        Err(error) => eprintln!("warning: ignoring {error}"),
+    }
+}
+
+fn convert(
+    input: PathBuf,
+    to: ChartFormat,
+    from: Option<ChartFormat>,
+    output: Option<PathBuf>,
+    mode: ParseMode,
+) -> eyre::Result<()> {
+    let content = std::fs::read_to_string(&input)
+        .wrap_err_with(|| format!("failed to read {}", input.display()))?;
+
+    let from = from
+        .or_else(|| ChartFormat::from_extension(&input))
+        .or_else(|| ChartFormat::detect(&content))
+        .ok_or_eyre("could not determine the input format, pass it with --from")?;
+
+    let parsed = Chart::parse(from, &content, mode)
+        .map_err(|error| error.with_file(&input))
+        .wrap_err_with(|| format!("failed to parse {} as {from}", input.display()))?
+        .with_file(&input);
+    for warning in &parsed.warnings {
+        eprintln!("warning: skipped {warning}");
+    }
+    let mut chart = parsed.value;
+    if let Chart::C2s(chart) = &mut chart {
+        load_music(chart, &input);
+    }
+    let converted = chart.convert(to);
+    for warning in &converted.warnings {
+        eprintln!("warning: {warning}");
+    }
+    let converted = converted.value.to_string();
+
+    let output = match output {
+        Some(output) => output,
+        None => {
+            let output = input.with_extension(to.extension());
+            if output == input {
+                eyre::bail!("refusing to overwrite {}, pass --output", input.display());
+            }
+            output
+        }
+    };
+    std::fs::write(&output, converted)
+        .wrap_err_with(|| format!("failed to write {}", output.display()))?;
+
+    Ok(())
+}
+
+fn main() -> eyre::Result<()> {
+    let cli = Cli::parse();
+
+    match cli.command {
+        Command::Convert {
+            input,
+            to,
+            from,
+            output,
+            strict,
+        } => {
+            let mode = if strict {
+                ParseMode::Strict
+            } else {
+                ParseMode::Lenient
+            };
+            convert(input, to, from, output, mode)
+        }
+    }
 }